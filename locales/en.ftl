@@ -0,0 +1,227 @@
+# Replies of the bot. Keep the ids in sync with the other locales; anything missing there falls
+# back to these.
+
+## Commands
+
+start = Hi! Send me an image or a GIF, and I'll convert it for use with @Stickers. Also, I can convert stickers to images or GIFs.
+help =
+    Send an image, a GIF, a video or a sticker to convert it. Captions can carry edits such as trim=0.5-3.0 crop=center speed=1.5, or text: followed by words to draw.
+    Tap a topic below to learn more, or send /pad, /grid and such for a quick note.
+help-try = Try it
+help-back = « Back
+help-more = How does this work?
+help-demo = A sample image sent with the caption: { $caption }
+help-edits = Captions can carry edits: trim=0.5-3.0 crop=center speed=1.5 fps=30 rotate=90 flip mirror invert grayscale, and text: followed by words to draw at the end. They apply to images, GIFs and videos alike where they make sense. Commands work there too, such as /emoji, /slow or /fps 30, so one message is enough.
+help-sets = Send /archive with a set link to get the whole set in a zip. Send that zip back with /restore as the caption to rebuild it under your account, or send /clone with a set link and options to copy it with edits applied. Big sets take a while, and I reply when done.
+help-settings = Settings stick until changed: /target sticker, emoji, discord, whatsapp or signal; /quality 1-100 or lossless; /nogif to get video stickers as webm only; /mp4 to get them as mp4 too; /upscale lanczos, ai or off for images smaller than a sticker; /lang to pick a language; /preset to save caption options under a name. In a group, admins can set them for everyone with /chatsettings. /delete in reply to results of mine takes them away. /history lists your recent results to get them again, and /fav in reply to one keeps it in /favs. /tag cat cute in reply to one saves it with those tags, to get it again with /find cat or by typing my name and cat in any chat. /quota shows how many conversions you have left today, and /premium buys more with Stars, as /donate gives them. /export sends you all I keep about you, and /forgetme deletes it.
+bench =
+    Benchmark, averaged per stage:
+    { $results }
+selftest =
+    Self-test:
+    { $results }
+send-media = Please send an image, a GIF, or a sticker.
+done = Done.
+nothing-changed = Nothing changed.
+reloaded = Config reloaded.
+reloaded-restart = Config reloaded, except { $vars }, which take a restart.
+reload-failed = Could not read CONFIG_FILE: { $error }
+usage-delete = Reply /delete to something I sent you lately to delete it along with all else I sent for the same request.
+usage-tier = Usage: /tier <user id> <0-{ $max }>
+usage-nsfw = Usage: /nsfw <chat id> <on|off|default>
+quota = You have used { $used } of your { $limit } conversions of today, on tier { $tier }. They come back in { $reset }.
+quota-unlimited = You have made { $used } conversions today, on tier { $tier }, which has no limit.
+quota-none = There is no limit on your conversions.
+quota-premium = You have made { $used } conversions today, with premium for { $days } more days.
+payments-off = This instance takes no payments.
+usage-donate = Usage: /donate, or /donate <1-{ $max }> to give that many Stars
+donate-title = Donation
+donate-description = Helps keep this bot running. Thank you!
+donate-thanks = Thank you for your donation!
+premium-title = Premium
+premium-description = { $days } days without a daily limit, with larger files and zips of a whole pack, ahead of the queue.
+premium-paid = Thank you! You have { $days } more days of premium.
+premium-save-failed = Your payment went through but premium could not be saved. Tell an admin this charge: { $charge }
+invoice-outdated = This invoice is out of date, ask for a new one.
+usage-ban = Usage: /ban <user id> or /unban <user id>
+ban-save-failed = Failed to save the ban list.
+usage-target = Usage: /target sticker, emoji, discord, whatsapp or signal
+usage-quality = Usage: /quality <1-100> or /quality lossless
+usage-lang = Usage: /lang { $langs } or /lang auto
+settings-save-failed = Failed to save your settings.
+usage-chatsettings = Usage: /chatsettings target <sticker|emoji|discord|whatsapp|signal>, quality <1-100|lossless>, gif <on|off>, upscale <lanczos|ai|off> or nsfw <on|off> to refuse explicit images or not, each also taking default to leave it to members or to the bot; /chatsettings reset to clear them all
+history = Your recent results, page { $page } of { $pages }. Tap a number to get it again.
+history-entry = { $n }. { $input } → { $output }, { $age } ago
+history-empty = You have no results yet.
+favs = Your favorites, page { $page } of { $pages }. Tap a number to get it again, or ✕ to remove it.
+favs-entry = { $n }. { $output }, saved { $age } ago
+favs-empty = You have no favorites yet. Reply /fav to a result to save it.
+favs-full = You already have { $max } favorites. Remove some with /favs first.
+fav-saved = Saved to /favs.
+emoji-suggest = Emoji that may suit it. Tap one to save the sticker to /favs with it, to send along when adding it to a pack.
+emoji-saved = Saved to /favs with { $emoji }.
+emoji-gone = This sticker is no longer there.
+usage-fav = Reply /fav to a result of mine to save it to /favs.
+found = Found { $n }. Tap a number to get it again.
+find-none = Nothing of yours has those tags. Reply /tag with words to a result to tag it.
+usage-tag = Reply /tag cat cute to a result of mine to save it with those tags, to find it with /find cat.
+export-private = Send /export to me in a private chat, as it holds your history.
+export-failed = Your data could not be gathered, try again later.
+forgetme-confirm = This deletes your settings, presets, history and favorites for good. Send /forgetme confirm to go ahead, or /export first to keep a copy.
+forgot = Done, I no longer keep anything about you.
+history-gone = That result is no longer in your history.
+chat-only = /chatsettings is for groups and channels.
+chat-admins-only = Only admins of this chat can change its settings.
+chat-profile = Settings of this chat, in place of those of its members: { $profile }
+chat-profile-empty = This chat has no settings of its own, so each member's apply. Admins can set them with /chatsettings.
+gif-off = Video stickers will be sent back as webm only. Send /nogif again to get GIFs too.
+gif-on = Video stickers will be sent back as webm and GIF.
+mp4-on = Video stickers will also be sent back as mp4. Send /mp4 off to stop.
+mp4-off = Video stickers will no longer be sent back as mp4.
+usage-mp4 = Usage: /mp4, /mp4 <background color> or /mp4 off
+usage-upscale = Usage: /upscale lanczos, /upscale ai or /upscale off to leave small images at their size
+usage-archive = Usage: /archive https://t.me/addstickers/<name>
+usage-watermark = Usage: /watermark <text> to draw it onto everything I make for you, or /watermark off
+watermark-unavailable = Watermarks cannot be set on this instance.
+usage-preset = Usage: /preset to list presets, /preset <name> to show one, /preset <name> <options> to save your own, or /preset <name> off to remove it. Names are letters, digits, - and _, other than those of options and commands.
+presets = Presets: { $names }. Start a caption with the name of one to use it, like meme Hello.
+preset = { $name }: { $options }
+no-preset = There is no preset named { $name }.
+too-many-presets = You can keep at most { $max } presets.
+usage-clone = Usage: /clone https://t.me/addstickers/<name> [options], such as /clone <link> speed=2 text: mine
+help-convert = Reply /convert to a photo, a GIF or a file, such as one in a group or in Saved Messages, and I'll convert it without it being forwarded to me. Options go after it, such as /convert speed=2x, and /pad, /thumb, /cutout, /speed, /plan or /check replied the same way do what they would in a caption.
+help-pad = Add /pad to the caption of an image or a GIF to pad it to exactly 512×512.
+help-check = Send a sticker or a .webp, .png, .webm or .tgs file with /check as its caption, and I'll tell you whether @Stickers will accept it.
+help-plan = Send a file with /plan as its caption, or reply /plan to it or to a sticker, and I'll tell you what I'd do with it instead of doing it: the format I see, the steps, the encoder and about how big the result gets. Other options in the caption are taken into account.
+help-bg = Add bg=white, bg=#ff8800 or any other color to the caption of an image or a GIF to fill its transparent parts. Transparency is kept by default.
+help-smartcrop = Add /smartcrop to the caption of an image to crop it to a square around its subject. For GIFs and videos, use crop=center, top, bottom, left or right.
+help-pixel = Add /pixel to the caption of an image or a GIF to make crisp pixel art 64 pixels across, or pixel=32 for fewer. Add colors=16 to limit the palette too.
+help-filter = Add filter=bayer, sepia, vhs or glitch to the caption of an image, a GIF or a video for a retro look.
+help-grid = Add grid=3x4 to the caption of a sprite sheet to cut it into 3 rows and 4 columns of stickers, sent back in a zip.
+help-frames = Add /frames to the caption of a GIF or a video to get 4 static stickers of moments spread over it, or frames=N for up to 10.
+help-emoji = Add /emoji to the caption of an image or a GIF to make a 100×100 custom emoji, or send /target emoji to always do so.
+help-discord = Add /discord to the caption of an image or a GIF to make a Discord sticker, or send /target discord to always do so.
+help-whatsapp = Add /whatsapp to the caption of an image or a GIF to make a WhatsApp sticker along with a .wastickers pack, or send /target whatsapp to always do so.
+help-signal = Add /signal to the caption of an image or a GIF to make a sticker for Signal's sticker pack creator, or send /target signal to always do so.
+help-webp = Add /webp to the caption of a GIF or a video to get an animated webp instead of a video sticker.
+help-thumb = Add /thumb to the caption of an image or a GIF to make a sticker set thumbnail.
+help-cutout = Add /cutout to the caption of a photo to remove its background.
+help-ocr = Add /ocr to the caption of a picture, or reply /ocr to a sticker, and I'll send the text in it along with the result and tag the result with its words, to find it by them with /find.
+help-still = Reply /still to a sticker to get its first frame as a transparent PNG, or /still 1.5 for the frame at 1.5 s.
+help-speed = Reply /speed 2x or /speed 0.5x to an animated sticker, a GIF or a video to play it faster or slower, such as to fit a long loop in 3 s. Videos and GIFs take speed=2x, /slow or /fast in the caption too.
+help-reverse = Add /reverse to the caption of a GIF or a video to play it backwards, or /boomerang to play it forwards and then backwards. Reply with either to an animated or video sticker to do the same.
+help-upscale = Images smaller than a sticker are scaled up with Lanczos, which blurs small memes and emoji. Add /upscale to the caption, or reply /upscale to one, to scale it up with a super-resolution model instead, or send /upscale ai to always do so. /upscale off leaves small images at their size.
+help-restore = Send a zip made by /archive with /restore as the caption to rebuild the set under your account. If it stops midway, send it again to continue.
+
+## Progress
+
+sped-up = Sped up { $speed }× to fit in { $max } s.
+content-mismatch = This file is named .{ $claimed } but is really { $real }, so it was converted as { $real }.
+ask-segment = This video is { $duration } s long, but video stickers can be at most { $max } s. Which part should I use?
+segment-first = First 3s
+segment-middle = Middle 3s
+segment-last = Last 3s
+segment-fit = Speed up to fit
+psd-layers = Tap a layer to make it a sticker of its own.
+psd-layer = Layer { $n }
+batch-converted = Converted { $ok } of { $total } files.
+batch-failed = Failed: { $names }
+batch-skipped = Skipped { $n } unsupported files.
+archived = { $n } stickers of { $title }, with a manifest.json for /restore.
+restored = Restored { $n } stickers into { $link }
+restored-partial = Restored { $n } of { $total } stickers into { $link }. Send the zip again to continue.
+job-queued = On it. This takes a while for big sets, I will reply here when it is done.
+cloned = Copied { $n } stickers into { $link }
+cloned-partial = Copied { $n } of { $total } stickers into { $link }. Send the same /clone again to continue.
+note-compressed = Compressed at quality { $quality } to fit in 512 KB.
+note-scaled = Scaled down to { $width }×{ $height } at quality { $quality } to fit in 512 KB, so this is no longer a valid sticker.
+note-webp-lossy = The lossless encoder failed on this image, so it was encoded at quality { $quality } instead.
+note-webp-upscaled = The encoder failed on this image at its size, so it was scaled up to { $width }×{ $height }.
+note-png = The webp encoder failed on this image, so this is a png, which Telegram may not take as a sticker.
+
+## Errors
+
+error-generic = Something went wrong.
+bad-caption = Could not understand the caption. Example: trim=0.5-3.0 crop=center speed=1.5
+bad-option = I don't know the option { $word }. Options look like trim=0.5-3.0 crop=center speed=1.5 q=80 bg=#fff.
+bad-trim = Could not understand { $word }. Give the start and end in seconds, like trim=0.5-3.0
+bad-crop = Could not understand { $word }. Use crop=center, top, bottom, left or right.
+bad-speed = Could not understand { $word }. Use a speed from 0.25 to 4, like speed=1.5 or /speed 2x
+bad-fps = Could not understand { $word }. Give a whole frame rate, like fps=30
+bad-rotate = Could not understand { $word }. Use rotate=90, 180 or 270.
+bad-quality = Could not understand { $word }. Use a quality from 1 to 100, like q=80
+bad-bg = Could not understand { $word }. Use a color like bg=white, bg=#fff or bg=#ff8800, or bg=none.
+bad-grid = Could not understand { $word }. Give rows and columns, like grid=3x4
+bad-frames = Could not understand { $word }. Use frames=1 to frames=10.
+bad-pixel = Could not understand { $word }. Use pixel=8 to pixel=256.
+bad-colors = Could not understand { $word }. Use colors=2 to colors=256.
+bad-filter = Could not understand { $word }. Use filter=bayer, sepia, vhs or glitch.
+bad-pos = Could not understand { $word }. Use pos=top, center or bottom.
+file-too-big = File is too big.
+not-an-image = File is not an image.
+bad-video = Could not read this video.
+bad-tgs = This is not a valid tgs file.
+unknown-duration = Could not tell how long this video is.
+check-usage = Send a .webp, .png, .webm or .tgs file with /check as its caption.
+plan-usage = /plan works on images, GIFs, videos and stickers, one at a time.
+static-too-big = Could not make this image small enough for a sticker.
+webp-failed = The webp encoder failed on this image. Try converting it to a png first.
+too-big-discord = The result is too big for Discord.
+too-big-whatsapp = The result is too big for WhatsApp.
+too-big-signal = The result is too big for Signal.
+clip-too-big = The result is too big, try a shorter clip.
+thumb-too-big = Could not fit the thumbnail into 32 KB, try a shorter or simpler clip.
+result-too-big = The result is too big to send.
+send-failed = Failed to send file.
+send-failed-details = Failed to send file: { $error }
+ffmpeg-failed = Could not convert this file.
+ffmpeg-failed-details =
+    ffmpeg failed:
+    { $stderr }
+timeout = The conversion timed out after { $secs } s, the file is likely too complex. Try a smaller or shorter one.
+timeout-retry = The conversion timed out after { $secs } s, the file is likely too complex. I keep it for { $minutes } minutes: send /retry to try again with { $factor }× the time, or /retry lossy to also skip the slower lossless encode.
+nothing-to-retry = Nothing of yours timed out here in the last few minutes.
+temp-full = I'm short of disk space right now, try again in a few minutes.
+download-timeout = Downloading the file timed out, try again later.
+quota-exceeded = You have used all { $limit } conversions of today. They come back in { $reset }, at midnight UTC.
+worker-crashed = The converter crashed on this file.
+bad-zip = This is not a valid zip file.
+zip-too-many-files = This zip has too many files.
+zip-too-big = This zip is too big when unpacked.
+zip-empty = Found no images or videos in this zip.
+zip-all-failed = None of the files in this zip could be converted.
+grid-too-small = The image is too small for this grid.
+cutout-unavailable = Background removal is not available on this instance.
+upscale-unavailable = Upscaling with a model is not available on this instance. Send /upscale lanczos to go back.
+text-unavailable = Text overlay is not available on this instance.
+heic-unsupported = HEIC images are not supported on this instance.
+avif-unsupported = AVIF images are not supported on this instance.
+jxl-unsupported = JPEG XL images are not supported on this instance.
+raw-unsupported = RAW photos are not supported on this instance.
+pdf-unavailable = PDF input is not available on this instance.
+video-unavailable = Video conversion is unavailable on this instance.
+tgs-unavailable = Animated sticker conversion is unavailable on this instance.
+pdf-failed = Could not render this PDF.
+nsfw-refused = This looks explicit, which is not converted here.
+ocr-unavailable = Reading text is not available on this instance.
+ocr-failed = Could not read the text of this image.
+ocr-usage = /ocr only reads pictures and static stickers.
+ocr-none = No text found.
+still-past-end = The sticker is shorter than that.
+static-replay = A static sticker has no frames to speed up or play backwards.
+set-not-found = There is no such sticker set.
+bad-archive = This zip was not made by /archive, or its files were changed.
+no-owner = Sticker sets can only be made for a user, not on behalf of a chat.
+video-expired = This video has expired, please send it again.
+not-your-video = This is not your video.
+document-expired = This document has expired, please send it again.
+not-your-document = This is not your document.
+image-expired = This image has expired, please send it again.
+not-your-image = This is not your image.
+dup-found = You converted much the same picture { $age } ago. Get that sticker again, or convert this one anyway?
+dup-resend = Send that one
+dup-convert = Convert anyway
+aspect-advice = This picture is far longer than it is wide, and would make a thin sliver of a sticker.
+aspect-crop = Crop it to its subject
+aspect-split = Cut it into { $n } stickers
+aspect-as-is = Convert it as it is
@@ -0,0 +1,224 @@
+## Commands
+
+start = 你好！发给我图片或 GIF，我会把它转换成 @Stickers 可用的贴纸。我也可以把贴纸转换成图片或 GIF。
+help =
+    发送图片、GIF、视频或贴纸即可转换。说明文字里可以写编辑指令，例如 trim=0.5-3.0 crop=center speed=1.5，或者在 text: 之后写要绘制的文字。
+    点下面的主题了解更多，或发送 /pad、/grid 等命令查看简短说明。
+help-try = 试一试
+help-back = « 返回
+help-more = 这是怎么用的？
+help-demo = 以此说明发送的示例图片：{ $caption }
+help-edits = 说明中可以写编辑选项：trim=0.5-3.0 crop=center speed=1.5 fps=30 rotate=90 flip mirror invert grayscale，最后还可以写 text: 加上要绘制的文字。只要适用，图片、GIF 和视频都可以用。命令也可以写在说明里，例如 /emoji、/slow 或 /fps 30，一条消息就够了。
+help-sets = 发送 /archive 加贴纸包链接，可以得到整个贴纸包的 zip。把这个 zip 以 /restore 作为说明发回来，即可在你的账号下重建；发送 /clone 加贴纸包链接和选项，则可以复制一份并应用编辑。大的贴纸包需要一段时间，完成后我会回复。
+help-settings = 设置会一直保留到更改为止：/target sticker、emoji、discord、whatsapp 或 signal；/quality 1-100 或 lossless；/nogif 让视频贴纸只以 webm 发回；/mp4 同时以 mp4 发回；/upscale lanczos、ai 或 off 设置小于贴纸尺寸的图片如何放大；/lang 选择语言；/preset 把说明选项保存为预设。在群组中，管理员可以用 /chatsettings 为所有人设置。用 /delete 回复我的结果可以删除它们。/history 列出最近的结果，可以再次获取；用 /fav 回复某个结果可以把它收藏到 /favs。用 /tag cat cute 回复结果会带上这些标签收藏，之后可以用 /find cat，或在任意聊天里输入我的用户名加 cat 找回。/quota 显示你今天还剩多少次转换，/premium 可以用星星购买更多次数，/donate 则是捐助。/export 会把我保存的关于你的所有数据发给你，/forgetme 会删除它们。
+bench =
+    基准测试，各阶段的平均耗时：
+    { $results }
+selftest =
+    自检：
+    { $results }
+send-media = 请发送图片、GIF 或贴纸。
+done = 好了。
+nothing-changed = 没有变化。
+reloaded = 配置已重新加载。
+reloaded-restart = 配置已重新加载，但 { $vars } 需要重启才能生效。
+reload-failed = 无法读取 CONFIG_FILE：{ $error }
+usage-delete = 用 /delete 回复我最近发给你的消息，即可删除它以及同一请求的其他回复。
+usage-tier = 用法：/tier <用户 ID> <0-{ $max }>
+usage-nsfw = 用法：/nsfw <聊天 ID> <on|off|default>
+quota = 你今天已使用 { $used } / { $limit } 次转换，当前等级为 { $tier }。次数会在 { $reset } 后恢复。
+quota-unlimited = 你今天已转换 { $used } 次，当前等级 { $tier } 没有次数限制。
+quota-none = 你的转换次数没有限制。
+quota-premium = 你今天已转换 { $used } 次，高级版还剩 { $days } 天。
+payments-off = 这个实例不接受付款。
+usage-donate = 用法：/donate，或 /donate <1-{ $max }> 赠送相应数量的星星
+donate-title = 捐助
+donate-description = 帮助这个机器人继续运行。谢谢！
+donate-thanks = 感谢你的捐助！
+premium-title = 高级版
+premium-description = { $days } 天内没有每日次数限制，可以转换更大的文件和整个贴纸包的 zip，并优先排队。
+premium-paid = 谢谢！你的高级版增加了 { $days } 天。
+premium-save-failed = 付款已完成，但高级版没能保存。请把这笔付款告诉管理员：{ $charge }
+invoice-outdated = 这张账单已过期，请重新获取。
+usage-ban = 用法：/ban <用户 ID> 或 /unban <用户 ID>
+ban-save-failed = 保存封禁列表失败。
+usage-target = 用法：/target sticker、emoji、discord、whatsapp 或 signal
+usage-quality = 用法：/quality <1-100> 或 /quality lossless
+usage-lang = 用法：/lang { $langs } 或 /lang auto
+settings-save-failed = 保存设置失败。
+usage-chatsettings = 用法：/chatsettings target <sticker|emoji|discord|whatsapp|signal>、quality <1-100|lossless>、gif <on|off>、upscale <lanczos|ai|off> 或 nsfw <on|off>（是否拒绝露骨图片），每项都可以用 default 交还给成员或机器人决定；/chatsettings reset 清除全部
+history = 你最近的结果，第 { $page } 页，共 { $pages } 页。点编号重新获取。
+history-entry = { $n }. { $input } → { $output }，{ $age }前
+history-empty = 你还没有任何结果。
+favs = 你的收藏，第 { $page } 页，共 { $pages } 页。点编号重新获取，点 ✕ 删除。
+favs-entry = { $n }. { $output }，{ $age }前收藏
+favs-empty = 你还没有收藏。用 /fav 回复某个结果即可收藏。
+favs-full = 你已经有 { $max } 个收藏了，请先在 /favs 里删除一些。
+fav-saved = 已收藏到 /favs。
+emoji-suggest = 可能适合它的表情。点一个即可带着它把贴纸收藏到 /favs，添加到贴纸包时一并发送。
+emoji-saved = 已带着 { $emoji } 收藏到 /favs。
+emoji-gone = 这张贴纸已经不在了。
+usage-fav = 用 /fav 回复我发出的结果，把它收藏到 /favs。
+found = 找到 { $n } 个。点编号重新获取。
+find-none = 你没有带这些标签的结果。用 /tag 加词语回复某个结果即可添加标签。
+usage-tag = 用 /tag cat cute 回复我发出的结果，带上这些标签收藏，之后用 /find cat 找到它。
+export-private = 请在私聊中向我发送 /export，因为它包含你的历史记录。
+export-failed = 无法整理你的数据，请稍后再试。
+forgetme-confirm = 这会永久删除你的设置、预设、历史记录和收藏。发送 /forgetme confirm 以继续，也可以先用 /export 保留一份副本。
+forgot = 完成，我不再保存任何关于你的数据。
+history-gone = 这个结果已不在你的历史记录里了。
+chat-only = /chatsettings 只能在群组和频道中使用。
+chat-admins-only = 只有本聊天的管理员可以更改它的设置。
+chat-profile = 本聊天的设置，会代替成员各自的设置：{ $profile }
+chat-profile-empty = 本聊天没有自己的设置，使用每位成员各自的设置。管理员可以用 /chatsettings 设置。
+gif-off = 视频贴纸将只发回 webm。再次发送 /nogif 可同时获得 GIF。
+gif-on = 视频贴纸将同时发回 webm 和 GIF。
+mp4-on = 视频贴纸还将发回 mp4。发送 /mp4 off 可停止。
+mp4-off = 视频贴纸将不再发回 mp4。
+usage-mp4 = 用法：/mp4、/mp4 <背景颜色> 或 /mp4 off
+usage-upscale = 用法：/upscale lanczos、/upscale ai，或 /upscale off 保持小图片的原始尺寸
+usage-archive = 用法：/archive https://t.me/addstickers/<名称>
+usage-watermark = 用法：/watermark <文字> 在我为你制作的所有内容上加上水印，或 /watermark off
+watermark-unavailable = 这个实例不能设置水印。
+usage-preset = 用法：/preset 列出预设，/preset <名称> 查看某个预设，/preset <名称> <选项> 保存自己的预设，/preset <名称> off 删除它。名称可以包含字母、数字、- 和 _，但不能是选项或命令的名字。
+presets = 预设：{ $names }。在说明开头写上预设名即可使用，例如 meme Hello。
+preset = { $name }：{ $options }
+no-preset = 没有名为 { $name } 的预设。
+too-many-presets = 最多只能保存 { $max } 个预设。
+usage-clone = 用法：/clone https://t.me/addstickers/<名称> [选项]，例如 /clone <链接> speed=2 text: mine
+help-convert = 用 /convert 回复图片、GIF 或文件（例如群组或收藏夹里的），不必先转发给我就能转换。选项写在后面，例如 /convert speed=2x；同样用 /pad、/thumb、/cutout、/speed、/plan 或 /check 回复，效果和写在说明文字里一样。
+help-pad = 在图片或 GIF 的说明文字里加上 /pad，可以把它补边到正好 512×512。
+help-check = 发送贴纸，或以 /check 为说明文字发送 .webp、.png、.webm 或 .tgs 文件，我会告诉你 @Stickers 是否接受它。
+help-plan = 以 /plan 为说明文字发送文件，或用 /plan 回复它或贴纸，我会告诉你我将如何处理它而不实际转换：识别出的格式、步骤、编码器以及结果的大致大小。说明文字里的其他选项也会被考虑在内。
+help-bg = 在图片或 GIF 的说明文字里加上 bg=white、bg=#ff8800 或其他颜色，可以填充透明部分。默认保留透明。
+help-smartcrop = 在图片的说明文字里加上 /smartcrop，可以围绕主体裁成正方形。GIF 和视频请用 crop=center、top、bottom、left 或 right。
+help-pixel = 在图片或 GIF 的说明中加上 /pixel，可以做成宽 64 像素的清晰像素画，pixel=32 则更少。再加上 colors=16 还可以限制调色板。
+help-filter = 在图片、GIF 或视频的说明中加上 filter=bayer、sepia、vhs 或 glitch，即可得到复古效果。
+help-grid = 在精灵图的说明文字里加上 grid=3x4，可以把它切成 3 行 4 列的贴纸，打包成 zip 发回。
+help-frames = 在 GIF 或视频的说明文字里加上 /frames，可以得到 4 张取自不同时刻的静态贴纸，或用 frames=N 最多取 10 张。
+help-emoji = 在图片或 GIF 的说明文字里加上 /emoji，可以做成 100×100 的自定义表情；发送 /target emoji 则一直如此。
+help-discord = 在图片或 GIF 的说明文字里加上 /discord，可以做成 Discord 贴纸；发送 /target discord 则一直如此。
+help-whatsapp = 在图片或 GIF 的说明文字里加上 /whatsapp，可以做成 WhatsApp 贴纸并附带 .wastickers 包；发送 /target whatsapp 则一直如此。
+help-signal = 在图片或 GIF 的说明文字里加上 /signal，可以做成 Signal 贴纸包制作工具可用的贴纸；发送 /target signal 则一直如此。
+help-webp = 在 GIF 或视频的说明文字里加上 /webp，可以得到动态 webp 而不是视频贴纸。
+help-thumb = 在图片或 GIF 的说明文字里加上 /thumb，可以做成贴纸包的缩略图。
+help-cutout = 在照片的说明文字里加上 /cutout，可以去除背景。
+help-ocr = 在图片的说明文字里加上 /ocr，或用 /ocr 回复贴纸，我会连同结果一起发送其中的文字，并用这些词给结果加上标签，之后可以用 /find 找到它。
+help-still = 用 /still 回复贴纸，可以得到它第一帧的透明 PNG；用 /still 1.5 则取 1.5 秒处的一帧。
+help-speed = 用 /speed 2x 或 /speed 0.5x 回复动态贴纸、GIF 或视频，可以加快或放慢播放，例如把较长的循环压进 3 秒。视频和 GIF 也可以在说明文字里写 speed=2x、/slow 或 /fast。
+help-reverse = 在 GIF 或视频的说明文字里加上 /reverse 可以倒放，加上 /boomerang 则先正放再倒放。用它们回复动态贴纸或视频贴纸也一样。
+help-upscale = 小于贴纸尺寸的图片会用 Lanczos 放大，小的表情图和 emoji 会因此变糊。在说明文字里加上 /upscale，或用 /upscale 回复图片，即可改用超分辨率模型放大；发送 /upscale ai 则始终如此。/upscale off 会保持小图片的原始尺寸。
+help-restore = 发送 /archive 生成的 zip 并以 /restore 作为说明，即可在你的账号下重建贴纸包。中途停止的话，再发一次即可继续。
+
+## Progress
+
+sped-up = 已加速 { $speed }× 以控制在 { $max } 秒内。
+content-mismatch = 这个文件的扩展名是 .{ $claimed }，但实际上是 { $real }，因此按 { $real } 转换。
+ask-segment = 这段视频长 { $duration } 秒，但视频贴纸最长只能 { $max } 秒。要用哪一段？
+segment-first = 开头 3 秒
+segment-middle = 中间 3 秒
+segment-last = 结尾 3 秒
+segment-fit = 加速到合适长度
+psd-layers = 点击一个图层，把它单独做成贴纸。
+psd-layer = 图层 { $n }
+batch-converted = 已转换 { $total } 个文件中的 { $ok } 个。
+batch-failed = 失败：{ $names }
+batch-skipped = 跳过了 { $n } 个不支持的文件。
+archived = { $title } 的 { $n } 个贴纸，附带可用于 /restore 的 manifest.json。
+restored = 已将 { $n } 个贴纸恢复到 { $link }
+restored-partial = 已将 { $total } 个贴纸中的 { $n } 个恢复到 { $link }。再发一次 zip 即可继续。
+job-queued = 处理中。大的贴纸包需要一段时间，完成后会在这里回复。
+cloned = 已将 { $n } 个贴纸复制到 { $link }
+cloned-partial = 已将 { $total } 个贴纸中的 { $n } 个复制到 { $link }。再发一次相同的 /clone 即可继续。
+note-compressed = 已用质量 { $quality } 压缩，以控制在 512 KB 内。
+note-scaled = 已缩小到 { $width }×{ $height } 并用质量 { $quality } 压缩以控制在 512 KB 内，因此不再是有效的贴纸。
+note-webp-lossy = 无损编码器处理这张图片失败，因此改用质量 { $quality } 编码。
+note-webp-upscaled = 编码器无法处理这张图片的原始尺寸，因此已放大到 { $width }×{ $height }。
+note-png = webp 编码器处理这张图片失败，因此发送的是 png，Telegram 可能不接受它作为贴纸。
+
+## Errors
+
+error-generic = 出错了。
+bad-caption = 看不懂说明文字。例如：trim=0.5-3.0 crop=center speed=1.5
+bad-option = 不认识选项 { $word }。选项的写法例如 trim=0.5-3.0 crop=center speed=1.5 q=80 bg=#fff。
+bad-trim = 看不懂 { $word }。请给出以秒为单位的起止时间，例如 trim=0.5-3.0
+bad-crop = 看不懂 { $word }。请使用 crop=center、top、bottom、left 或 right。
+bad-speed = 看不懂 { $word }。速度须在 0.25 到 4 之间，例如 speed=1.5 或 /speed 2x
+bad-fps = 看不懂 { $word }。请给出整数帧率，例如 fps=30
+bad-rotate = 看不懂 { $word }。请使用 rotate=90、180 或 270。
+bad-quality = 看不懂 { $word }。质量须在 1 到 100 之间，例如 q=80
+bad-bg = 看不懂 { $word }。请使用颜色，例如 bg=white、bg=#fff 或 bg=#ff8800，或者 bg=none。
+bad-grid = 看不懂 { $word }。请给出行数和列数，例如 grid=3x4
+bad-frames = 看不懂 { $word }。请使用 frames=1 到 frames=10。
+bad-pixel = 看不懂 { $word }。请使用 pixel=8 到 pixel=256。
+bad-colors = 看不懂 { $word }。请使用 colors=2 到 colors=256。
+bad-filter = 看不懂 { $word }。请使用 filter=bayer、sepia、vhs 或 glitch。
+bad-pos = 看不懂 { $word }。请使用 pos=top、center 或 bottom。
+file-too-big = 文件太大了。
+not-an-image = 这个文件不是图片。
+bad-video = 无法读取这段视频。
+bad-tgs = 这不是有效的 tgs 文件。
+unknown-duration = 无法得知这段视频有多长。
+check-usage = 请以 /check 为说明文字发送 .webp、.png、.webm 或 .tgs 文件。
+plan-usage = /plan 适用于图片、GIF、视频和贴纸，每次一个。
+static-too-big = 无法把这张图片压缩到贴纸允许的大小。
+webp-failed = webp 编码器处理这张图片失败。请先把它转换成 png 再试。
+too-big-discord = 结果对 Discord 来说太大了。
+too-big-whatsapp = 结果对 WhatsApp 来说太大了。
+too-big-signal = 结果对 Signal 来说太大了。
+clip-too-big = 结果太大了，请试试更短的片段。
+thumb-too-big = 无法把缩略图控制在 32 KB 内，请试试更短或更简单的片段。
+result-too-big = 结果太大，无法发送。
+send-failed = 发送文件失败。
+send-failed-details = 发送文件失败：{ $error }
+ffmpeg-failed = 无法转换这个文件。
+ffmpeg-failed-details =
+    ffmpeg 失败：
+    { $stderr }
+timeout = 转换在 { $secs } 秒后超时，文件可能太复杂了。请试试更小或更短的文件。
+timeout-retry = 转换在 { $secs } 秒后超时，文件可能太复杂了。文件会保留 { $minutes } 分钟：发送 /retry 可用 { $factor } 倍的时间重试，发送 /retry lossy 还会跳过较慢的无损编码。
+nothing-to-retry = 最近几分钟里这里没有你的超时任务。
+temp-full = 目前磁盘空间不足，请过几分钟再试。
+download-timeout = 下载文件超时，请稍后再试。
+quota-exceeded = 你今天的 { $limit } 次转换已经用完了。次数会在 { $reset } 后（UTC 零点）恢复。
+worker-crashed = 转换程序在处理这个文件时崩溃了。
+bad-zip = 这不是有效的 zip 文件。
+zip-too-many-files = 这个 zip 里的文件太多了。
+zip-too-big = 这个 zip 解压后太大了。
+zip-empty = 这个 zip 里没有图片或视频。
+zip-all-failed = 这个 zip 里的文件都无法转换。
+grid-too-small = 图片太小，无法按这个网格切分。
+cutout-unavailable = 此实例不支持去除背景。
+upscale-unavailable = 此实例不支持用模型放大。发送 /upscale lanczos 即可恢复。
+text-unavailable = 此实例不支持文字叠加。
+heic-unsupported = 此实例不支持 HEIC 图片。
+avif-unsupported = 此实例不支持 AVIF 图片。
+jxl-unsupported = 此实例不支持 JPEG XL 图片。
+raw-unsupported = 此实例不支持 RAW 照片。
+pdf-unavailable = 此实例不支持 PDF 输入。
+video-unavailable = 此实例不支持视频转换。
+tgs-unavailable = 此实例不支持动态贴纸转换。
+pdf-failed = 无法渲染这个 PDF。
+nsfw-refused = 这看起来是露骨内容，这里不转换。
+ocr-unavailable = 此实例不支持识别文字。
+ocr-failed = 无法识别这张图片的文字。
+ocr-usage = /ocr 只能识别图片和静态贴纸。
+ocr-none = 没有找到文字。
+still-past-end = 这个贴纸没有那么长。
+static-replay = 静态贴纸没有可以加速或倒放的帧。
+set-not-found = 没有这个贴纸包。
+bad-archive = 这个 zip 不是 /archive 生成的，或者其中的文件被改动过。
+no-owner = 贴纸包只能为用户创建，不能以群组或频道的身份创建。
+video-expired = 这段视频已过期，请重新发送。
+not-your-video = 这不是你的视频。
+document-expired = 这个文档已过期，请重新发送。
+not-your-document = 这不是你的文档。
+image-expired = 这张图片已过期，请重新发送。
+not-your-image = 这不是你的图片。
+dup-found = 你在 { $age }前转换过几乎一样的图片。要重新获取那张贴纸，还是仍然转换这张？
+dup-resend = 发送那张
+dup-convert = 仍然转换
+aspect-advice = 这张图片的长宽相差太大，做成贴纸会只剩细细一条。
+aspect-crop = 裁剪到主体
+aspect-split = 切成 { $n } 张贴纸
+aspect-as-is = 按原样转换
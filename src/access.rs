@@ -0,0 +1,139 @@
+use crate::config;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use teloxide::types::{ChatId, Message, UserId};
+
+const BAN_FILE: &str = "banned.txt";
+
+// Who a message is from. Channel posts and anonymous group admins have no user behind them, so
+// those are taken as coming from the chat itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actor {
+    User(UserId),
+    Chat(ChatId),
+}
+
+impl Actor {
+    pub fn of(msg: &Message) -> Option<Self> {
+        if let Some(chat) = &msg.sender_chat {
+            return Some(Self::Chat(chat.id));
+        }
+        let user = msg.from()?;
+        // Older clients still mark anonymous admins and channel posts with these bots.
+        if user.id.is_anonymous() || user.id.is_channel() {
+            return Some(Self::Chat(msg.chat.id));
+        }
+        Some(Self::User(user.id))
+    }
+
+    pub fn user(self) -> Option<UserId> {
+        match self {
+            Self::User(id) => Some(id),
+            Self::Chat(_) => None,
+        }
+    }
+
+    // Chat ids are negative, so they never collide with user ids.
+    pub fn key(self) -> i64 {
+        match self {
+            Self::User(id) => id.0 as i64,
+            Self::Chat(id) => id.0,
+        }
+    }
+}
+
+// Users banned at runtime by /ban, persisted one id per line.
+struct BanList {
+    path: PathBuf,
+    users: Mutex<HashSet<UserId>>,
+}
+
+static BANNED: OnceLock<BanList> = OnceLock::new();
+
+impl BanList {
+    fn load() -> Self {
+        let path = config::get().data_dir.join(BAN_FILE);
+        let mut users = HashSet::new();
+        match fs::read_to_string(&path) {
+            Ok(s) => {
+                for line in s.lines().map(str::trim).filter(|s| !s.is_empty()) {
+                    match line.parse() {
+                        Ok(id) => {
+                            users.insert(UserId(id));
+                        }
+                        Err(e) => warn!("{}: ignoring {:?}: {}", path.display(), line, e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("{}: {}", path.display(), e),
+        }
+        info!("loaded {} banned users", users.len());
+        Self {
+            path,
+            users: Mutex::new(users),
+        }
+    }
+
+    fn save(&self, users: &HashSet<UserId>) -> io::Result<()> {
+        let mut s = String::new();
+        for id in users {
+            s.push_str(&id.0.to_string());
+            s.push('\n');
+        }
+        fs::write(&self.path, s)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut HashSet<UserId>) -> bool) -> io::Result<bool> {
+        let mut users = self.users.lock().unwrap();
+        let changed = f(&mut users);
+        if changed {
+            self.save(&users)?;
+        }
+        Ok(changed)
+    }
+}
+
+fn banned() -> &'static BanList {
+    BANNED.get_or_init(BanList::load)
+}
+
+pub fn init() {
+    banned();
+}
+
+pub fn is_admin(id: UserId) -> bool {
+    config::get().admins.contains(&id)
+}
+
+pub fn is_allowed(id: UserId) -> bool {
+    if is_admin(id) {
+        return true;
+    }
+    let cfg = config::get();
+    if cfg.blocked_users.contains(&id) || banned().users.lock().unwrap().contains(&id) {
+        return false;
+    }
+    cfg.allowed_users.as_ref().map_or(true, |s| s.contains(&id))
+}
+
+// Chats have no ids in the user lists, so they are only served by public instances.
+pub fn is_allowed_actor(actor: Actor) -> bool {
+    match actor {
+        Actor::User(id) => is_allowed(id),
+        Actor::Chat(_) => config::get().allowed_users.is_none(),
+    }
+}
+
+// Returns whether the list actually changed.
+pub fn ban(id: UserId) -> io::Result<bool> {
+    banned().update(|s| s.insert(id))
+}
+
+pub fn unban(id: UserId) -> io::Result<bool> {
+    banned().update(|s| s.remove(&id))
+}
@@ -0,0 +1,62 @@
+// Failures that need looking into, reported to the ERROR_CHAT_ID chat besides the log: panics,
+// and errors that are not the user's to fix, such as ffmpeg exiting with an error or a missing
+// converter.
+
+use crate::error::BotError;
+use crate::{config, AppBot};
+use log::warn;
+use std::panic;
+use std::sync::OnceLock;
+use teloxide::prelude::*;
+use tokio::runtime::Handle;
+
+// Leaves room under the 4096 characters Telegram takes.
+const MAX_LEN: usize = 3500;
+
+static BOT: OnceLock<(AppBot, Handle)> = OnceLock::new();
+
+// Installed whether or not ERROR_CHAT_ID is set, which is only read when something happens, so that
+// setting it in a reload takes effect.
+pub fn init(bot: AppBot) {
+    let _ = BOT.set((bot, Handle::current()));
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        hook(info);
+        send(format!("panic: {}", info));
+    }));
+}
+
+pub fn send(mut text: String) {
+    let (Some(chat), Some((bot, rt))) = (config::get().error_chat, BOT.get()) else {
+        return;
+    };
+    if text.len() > MAX_LEN {
+        let mut n = MAX_LEN;
+        while !text.is_char_boundary(n) {
+            n -= 1;
+        }
+        text.truncate(n);
+        text.push('…');
+    }
+    let bot = bot.clone();
+    rt.spawn(async move {
+        if let Err(e) = bot.send_message(chat, text).await {
+            warn!("alert: {:?}", e);
+        }
+    });
+}
+
+// `context` tells who sent what.
+pub fn failure(context: &str, e: &anyhow::Error) {
+    let tail = match e.downcast_ref::<BotError>() {
+        Some(b) if !b.is_internal() => return,
+        Some(b) => b.stderr_tail(),
+        None => None,
+    };
+    let mut text = format!("{}\n{:#}", context, e);
+    if let Some(t) = tail {
+        text.push_str("\n\n");
+        text.push_str(&t);
+    }
+    send(text);
+}
@@ -0,0 +1,172 @@
+// Whole sticker sets dumped into a zip for /archive: the original of every sticker under
+// stickers/, a PNG or GIF of it under converted/, and a manifest.json with the set's name, title,
+// order and emojis and the hash of each original, from which /restore can rebuild the set.
+
+use crate::error::BotError;
+use crate::{
+    caps, decode_image, download_mem, export, ffmpeg_to_gif, temp_file, tgs_to_gif, AppBot, Blob,
+    Input,
+};
+use anyhow::{bail, Result as AnyResult};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Write};
+use teloxide::prelude::*;
+use teloxide::types::{Sticker, StickerFormat, StickerSet};
+use teloxide::RequestError;
+use tokio::io::AsyncWriteExt;
+use zip::write::{FileOptions, ZipWriter};
+
+pub const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Static,
+    Animated,
+    Video,
+}
+
+impl Format {
+    pub fn of(f: &StickerFormat) -> Self {
+        match f {
+            StickerFormat::Raster => Self::Static,
+            StickerFormat::Animated => Self::Animated,
+            StickerFormat::Video => Self::Video,
+        }
+    }
+
+    pub fn ext(self) -> &'static str {
+        match self {
+            Self::Static => "webp",
+            Self::Animated => "tgs",
+            Self::Video => "webm",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    // The original as in the set, relative to the root of the zip.
+    pub file: String,
+    pub format: Format,
+    pub emoji: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub converted: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub title: String,
+    #[serde(default)]
+    pub custom_emoji: bool,
+    // In the order of the set.
+    pub stickers: Vec<Entry>,
+}
+
+// "https://t.me/addstickers/Name", "t.me/addemoji/Name" or just the name.
+pub fn parse_link(s: &str) -> Option<&str> {
+    let s = s.trim_end_matches('/');
+    let name = s
+        .rsplit_once("/addstickers/")
+        .or_else(|| s.rsplit_once("/addemoji/"))
+        .map_or(s, |(_, name)| name);
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (!name.is_empty() && valid).then_some(name)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn convert(data: &[u8], format: Format) -> AnyResult<Blob> {
+    if format == Format::Static {
+        return Ok(Blob::new(
+            export::png(&decode_image(data.to_vec())?)?,
+            "png",
+        ));
+    }
+    let caps = caps::get();
+    if !caps.ffmpeg || (format == Format::Animated && !caps.tgs_to_gif) {
+        bail!(BotError::VideoUnavailable)
+    }
+    let (path, mut f) = temp_file().await?;
+    f.write_all(data).await?;
+    drop(f);
+    match format {
+        Format::Animated => tgs_to_gif(&path).await,
+        _ => ffmpeg_to_gif(Input::File(&path)).await,
+    }
+}
+
+pub async fn fetch(bot: &AppBot, name: &str) -> AnyResult<StickerSet> {
+    match bot.get_sticker_set(name).await {
+        Ok(set) => Ok(set),
+        Err(RequestError::Api(e)) => {
+            info!("get_sticker_set {}: {}", name, e);
+            bail!(BotError::SetNotFound)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn download(bot: &AppBot, sti: &Sticker) -> AnyResult<Vec<u8>> {
+    let f = bot.get_file(&sti.file.id).await?;
+    download_mem(bot, &f).await
+}
+
+// Stickers that fail to convert are still archived, without a converted copy.
+pub async fn build(bot: &AppBot, name: &str) -> AnyResult<(Manifest, Blob)> {
+    let set = fetch(bot, name).await?;
+    let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut stickers = Vec::with_capacity(set.stickers.len());
+    let mut custom_emoji = false;
+    for (i, sti) in set.stickers.iter().enumerate() {
+        custom_emoji |= sti.is_custom_emoji();
+        let data = download(bot, sti).await?;
+        let format = Format::of(&sti.format);
+        let file = format!("stickers/{:03}.{}", i + 1, format.ext());
+        w.start_file(&file, FileOptions::default())?;
+        w.write_all(&data)?;
+        let converted = match convert(&data, format).await {
+            Ok(b) => {
+                let name = format!("converted/{:03}.{}", i + 1, b.ext);
+                w.start_file(&name, FileOptions::default())?;
+                w.write_all(&b.bytes().await?)?;
+                Some(name)
+            }
+            Err(e) => {
+                warn!("archive {}: {}: {:?}", set.name, file, e);
+                None
+            }
+        };
+        stickers.push(Entry {
+            file,
+            format,
+            emoji: sti.emoji.clone().unwrap_or_default(),
+            sha256: sha256_hex(&data),
+            converted,
+        });
+    }
+    let manifest = Manifest {
+        name: set.name,
+        title: set.title,
+        custom_emoji,
+        stickers,
+    };
+    w.start_file(MANIFEST, FileOptions::default())?;
+    w.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    info!(
+        "archived {} stickers of {}",
+        manifest.stickers.len(),
+        manifest.name
+    );
+    let b = Blob::new(w.finish()?.into_inner(), "zip");
+    Ok((manifest, b))
+}
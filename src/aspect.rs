@@ -0,0 +1,81 @@
+// Pictures far longer than they are wide, such as screenshots of chats, which fitted into a
+// sticker become a thin sliver. Instead of converting them as they are, the user is offered to
+// crop them around their subject or to cut them into stickers of about square pieces, and the
+// picture waits here meanwhile.
+
+use crate::grid;
+use crate::offers::{Kind, Offers};
+use crate::options::VideoEdit;
+
+pub static OFFERS: Offers<Offer> = Offers::new("aspect", Kind::Image);
+// Of the long side to the short one, past which advice is given.
+const MAX_RATIO: f64 = 3.0;
+
+// The grid of pieces closest to square, for those too long.
+pub fn split(w: u32, h: u32) -> Option<(u32, u32)> {
+    let (long, short) = (w.max(h), w.min(h).max(1));
+    let ratio = long as f64 / short as f64;
+    if ratio <= MAX_RATIO {
+        return None;
+    }
+    let n = (ratio.round() as u32).clamp(2, grid::MAX_CELLS);
+    Some(if h > w { (n, 1) } else { (1, n) })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Crop,
+    Split,
+    AsIs,
+}
+
+impl Choice {
+    fn code(self) -> char {
+        match self {
+            Choice::Crop => 'c',
+            Choice::Split => 's',
+            Choice::AsIs => 'a',
+        }
+    }
+
+    fn from_code(s: &str) -> Option<Self> {
+        match s {
+            "c" => Some(Choice::Crop),
+            "s" => Some(Choice::Split),
+            "a" => Some(Choice::AsIs),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Offer {
+    pub data: Vec<u8>,
+    pub edit: VideoEdit,
+    pub base: Option<String>,
+    // Rows and columns it would be split into.
+    pub grid: (u32, u32),
+}
+
+pub fn callback_data(token: u64, choice: Choice) -> String {
+    OFFERS.callback_data(token, choice.code())
+}
+
+pub fn parse_callback_data(data: &str) -> Option<(u64, Choice)> {
+    let (token, code) = OFFERS.parse_callback_data(data)?;
+    Some((token, Choice::from_code(code)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_long_pictures() {
+        assert_eq!(split(512, 512), None);
+        assert_eq!(split(300, 900), None);
+        assert_eq!(split(300, 1500), Some((5, 1)));
+        assert_eq!(split(2000, 400), Some((1, 5)));
+        assert_eq!(split(1, 100_000), Some((grid::MAX_CELLS, 1)));
+    }
+}
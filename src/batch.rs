@@ -0,0 +1,146 @@
+// Converting every image and video in an uploaded zip, with the results zipped back.
+
+use crate::error::BotError;
+use crate::i18n::Msg;
+use crate::options::VideoEdit;
+use crate::worker::process_image;
+use crate::{process_video, temp_file, Blob};
+use anyhow::{bail, Result as AnyResult};
+use log::{info, warn};
+use std::io::{Cursor, Read, Write};
+use tokio::io::AsyncWriteExt;
+use zip::write::{FileOptions, ZipWriter};
+use zip::ZipArchive;
+
+// Those of a whole sticker pack, which is what premium gets when BATCH_ENTRIES is lower.
+pub const MAX_ENTRIES: usize = 120;
+// Uncompressed, which is what is trusted over the sizes claimed by the archive.
+const MAX_TOTAL_SIZE: u64 = 64 << 20;
+
+const IMAGE_EXTS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "bmp", "tif", "tiff", "svg", "pdf", "heic", "heif", "avif",
+    "jxl", "cr2", "nef", "dng", "arw", "psd",
+];
+pub const VIDEO_EXTS: &[&str] = &["gif", "mp4", "webm", "mov", "mkv"];
+
+struct Entry {
+    name: String,
+    data: Vec<u8>,
+    video: bool,
+}
+
+fn read_entries(data: Vec<u8>, max: usize) -> AnyResult<(Vec<Entry>, usize)> {
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(data)) else {
+        bail!(BotError::BadZip)
+    };
+    if archive.len() > max * 4 {
+        bail!(BotError::ZipTooManyFiles)
+    }
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    let mut total = 0;
+    for i in 0..archive.len() {
+        let mut f = archive.by_index(i)?;
+        if f.is_dir() {
+            continue;
+        }
+        let name = f.name().to_owned();
+        let ext = name
+            .rsplit_once('.')
+            .map_or(String::new(), |(_, e)| e.to_ascii_lowercase());
+        let video = VIDEO_EXTS.contains(&ext.as_str());
+        // Also skips metadata like __MACOSX/._foo.png.
+        if !(video || IMAGE_EXTS.contains(&ext.as_str())) || name.contains("__MACOSX") {
+            skipped += 1;
+            continue;
+        }
+        if entries.len() == max {
+            bail!(BotError::ZipTooManyFiles)
+        }
+        let mut buf = Vec::new();
+        (&mut f)
+            .take(MAX_TOTAL_SIZE - total + 1)
+            .read_to_end(&mut buf)?;
+        total += buf.len() as u64;
+        if total > MAX_TOTAL_SIZE {
+            bail!(BotError::ZipTooBig)
+        }
+        entries.push(Entry {
+            name,
+            data: buf,
+            video,
+        });
+    }
+    Ok((entries, skipped))
+}
+
+async fn convert(e: Entry, edit: &VideoEdit) -> AnyResult<Blob> {
+    if !e.video {
+        return process_image(e.data, edit).await;
+    }
+    let (path, mut f) = temp_file().await?;
+    f.write_all(&e.data).await?;
+    drop(f);
+    process_video(&path, edit).await
+}
+
+// Results collected into a zip as they come.
+pub struct Archive(ZipWriter<Cursor<Vec<u8>>>);
+
+impl Archive {
+    pub fn new() -> Self {
+        Self(ZipWriter::new(Cursor::new(Vec::new())))
+    }
+
+    // The extension is the result's own.
+    pub async fn add(&mut self, stem: &str, b: &Blob) -> AnyResult<()> {
+        self.0
+            .start_file(format!("{}.{}", stem, b.ext), FileOptions::default())?;
+        self.0.write_all(&b.bytes().await?)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> AnyResult<Blob> {
+        Ok(Blob::new(self.0.finish()?.into_inner(), "zip"))
+    }
+}
+
+// Returns the zip of results and a summary for the caption.
+pub async fn convert_zip(
+    data: Vec<u8>,
+    edit: &VideoEdit,
+    max_entries: usize,
+) -> AnyResult<(Blob, Vec<Msg>)> {
+    let (entries, skipped) = read_entries(data, max_entries)?;
+    if entries.is_empty() {
+        bail!(BotError::ZipEmpty)
+    }
+    let n = entries.len();
+    let mut zip = Archive::new();
+    let mut failed = Vec::new();
+    for e in entries {
+        let name = e.name.clone();
+        let stem = name.rsplit_once('.').map_or(name.as_str(), |(s, _)| s);
+        info!("batch: converting {}", name);
+        match convert(e, edit).await {
+            Ok(b) => zip.add(stem, &b).await?,
+            Err(err) => {
+                warn!("batch: {}: {:?}", name, err);
+                failed.push(name);
+            }
+        }
+    }
+    if failed.len() == n {
+        bail!(BotError::ZipAllFailed)
+    }
+    let mut summary = vec![Msg::new("batch-converted")
+        .arg("ok", n - failed.len())
+        .arg("total", n)];
+    if !failed.is_empty() {
+        summary.push(Msg::new("batch-failed").arg("names", failed.join(", ")));
+    }
+    if skipped > 0 {
+        summary.push(Msg::new("batch-skipped").arg("n", skipped));
+    }
+    Ok((zip.finish()?, summary))
+}
@@ -0,0 +1,186 @@
+// Runs a fixed set of generated inputs through the whole pipeline, for the admin /bench and for
+// --bench, timing each stage so that hosts and encoder settings can be compared. Telegram is left
+// out: the download is mocked by writing the input to a temporary file, and the upload by reading
+// the result back.
+
+use crate::options::VideoEdit;
+use crate::probe;
+use crate::{decode_image, process_decoded, process_video, selftest, temp_file, tgs_to_gif, Blob};
+use anyhow::Result as AnyResult;
+use image::codecs::gif::GifEncoder;
+use image::{Delay, DynamicImage, Frame, ImageOutputFormat, Rgba, RgbaImage};
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+
+pub const MAX_RUNS: u32 = 10;
+// Of the video fixture: three seconds at 30 fps.
+const FRAMES: u32 = 90;
+const STAGES: [&str; 4] = ["download", "decode", "encode", "upload"];
+
+#[derive(Debug, Clone)]
+pub struct Bench {
+    pub name: &'static str,
+    // Of each stage, averaged over the runs.
+    pub stages: [Duration; 4],
+    pub size: u64,
+}
+
+// Detailed enough that encoders cannot cheat on a flat color.
+fn frame(i: u32, w: u32, h: u32) -> RgbaImage {
+    RgbaImage::from_fn(w, h, |x, y| {
+        let (dx, dy) = (x as i32 - (i * 4) as i32, y as i32 - h as i32 / 2);
+        if dx * dx + dy * dy < 48 * 48 {
+            return Rgba([255, 220, 0, 255]);
+        }
+        let a = if x < w / 10 { 0 } else { 255 };
+        Rgba([
+            (x * 255 / w) as u8,
+            (y * 255 / h) as u8,
+            ((x ^ y) + i) as u8,
+            a,
+        ])
+    })
+}
+
+fn png() -> AnyResult<Vec<u8>> {
+    let mut v = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(frame(0, 1024, 768)).write_to(&mut v, ImageOutputFormat::Png)?;
+    Ok(v.into_inner())
+}
+
+fn gif() -> AnyResult<Vec<u8>> {
+    let mut v = Vec::new();
+    {
+        let mut enc = GifEncoder::new(&mut v);
+        let delay = Delay::from_numer_denom_ms(1000, 30);
+        enc.encode_frames((0..FRAMES).map(|i| Frame::from_parts(frame(i, 320, 240), 0, 0, delay)))?;
+    }
+    Ok(v)
+}
+
+async fn download(data: &[u8]) -> AnyResult<TempPath> {
+    let (path, mut f) = temp_file().await?;
+    f.write_all(data).await?;
+    drop(f);
+    Ok(path)
+}
+
+async fn upload(b: &Blob) -> AnyResult<u64> {
+    Ok(b.bytes().await?.len() as u64)
+}
+
+// Times each stage, in order, of one run.
+struct Timer {
+    t: Instant,
+    stages: Vec<Duration>,
+}
+
+impl Timer {
+    fn new() -> Self {
+        Self {
+            t: Instant::now(),
+            stages: vec![],
+        }
+    }
+
+    fn lap(&mut self) {
+        self.stages.push(self.t.elapsed());
+        self.t = Instant::now();
+    }
+}
+
+async fn image(edit: &VideoEdit, data: &[u8]) -> AnyResult<(Vec<Duration>, u64)> {
+    let mut t = Timer::new();
+    let path = download(data).await?;
+    let data = tokio::fs::read(&path).await?;
+    t.lap();
+    let img = tokio::task::spawn_blocking(move || decode_image(data)).await??;
+    t.lap();
+    let b = process_decoded(img, edit).await?;
+    t.lap();
+    let size = upload(&b).await?;
+    t.lap();
+    Ok((t.stages, size))
+}
+
+async fn video(edit: &VideoEdit, data: &[u8]) -> AnyResult<(Vec<Duration>, u64)> {
+    let mut t = Timer::new();
+    let path = download(data).await?;
+    t.lap();
+    probe::probe(&path).await;
+    t.lap();
+    let b = process_video(&path, edit).await?;
+    t.lap();
+    let size = upload(&b).await?;
+    t.lap();
+    Ok((t.stages, size))
+}
+
+// Decoding is rendering the frames, which are then encoded like those of any video.
+async fn tgs(edit: &VideoEdit, data: &[u8]) -> AnyResult<(Vec<Duration>, u64)> {
+    let mut t = Timer::new();
+    let path = download(data).await?;
+    t.lap();
+    let gif = tgs_to_gif(&path).await?;
+    let gif = download(&gif.bytes().await?).await?;
+    t.lap();
+    let b = process_video(&gif, edit).await?;
+    t.lap();
+    let size = upload(&b).await?;
+    t.lap();
+    Ok((t.stages, size))
+}
+
+async fn bench<'a, F, Fut>(name: &'static str, runs: u32, data: &'a [u8], f: F) -> AnyResult<Bench>
+where
+    F: Fn(&'a [u8]) -> Fut,
+    Fut: std::future::Future<Output = AnyResult<(Vec<Duration>, u64)>>,
+{
+    let mut stages = [Duration::ZERO; 4];
+    let mut size = 0;
+    for _ in 0..runs {
+        let (d, n) = f(data).await?;
+        for (sum, d) in stages.iter_mut().zip(d) {
+            *sum += d;
+        }
+        size = n;
+    }
+    Ok(Bench {
+        name,
+        stages: stages.map(|d| d / runs),
+        size,
+    })
+}
+
+// Stops at the first failure, which is what there is to report then.
+pub async fn run(runs: u32) -> AnyResult<Vec<Bench>> {
+    let runs = runs.clamp(1, MAX_RUNS);
+    let edit = VideoEdit::default();
+    let edit = &edit;
+    let (png, gif, tgs_data) = (png()?, gif()?, selftest::tgs()?);
+    Ok(vec![
+        bench("image", runs, &png, |d| image(edit, d)).await?,
+        bench("gif", runs, &gif, |d| video(edit, d)).await?,
+        bench("tgs", runs, &tgs_data, |d| tgs(edit, d)).await?,
+    ])
+}
+
+pub fn summary(r: &AnyResult<Vec<Bench>>) -> String {
+    let benches = match r {
+        Ok(b) => b,
+        Err(e) => return format!("❌ {:#}", e),
+    };
+    let mut s = String::new();
+    for b in benches {
+        write!(s, "{}:", b.name).unwrap();
+        for (stage, d) in STAGES.iter().zip(b.stages) {
+            write!(s, " {} {} ms,", stage, d.as_millis()).unwrap();
+        }
+        writeln!(s, " {} B", b.size).unwrap();
+    }
+    s.truncate(s.trim_end().len());
+    s
+}
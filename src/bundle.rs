@@ -0,0 +1,46 @@
+// Converters shipped in BUNDLE_DIR next to the bot, as in the Docker image, instead of installed
+// on the host. Each is checked against the SHA256SUMS file there at startup, so that a corrupted
+// or swapped binary stops the bot rather than running.
+
+use crate::config;
+use anyhow::{bail, Result as AnyResult};
+use log::info;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::iter;
+use std::path::Path;
+
+pub const SUMS_FILE: &str = "SHA256SUMS";
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut h = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut h)?;
+    Ok(h.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Takes the output format of sha256sum.
+pub fn verify() -> AnyResult<()> {
+    let Some(dir) = &config::get().bundle_dir else {
+        return Ok(());
+    };
+    let sums = fs::read_to_string(dir.join(SUMS_FILE))?;
+    let mut n = 0;
+    for line in sums.lines().filter(|l| !l.trim().is_empty()) {
+        let Some((hash, name)) = line.split_once(char::is_whitespace) else {
+            bail!("{}: bad line {:?}", SUMS_FILE, line)
+        };
+        let name = name.trim_start().trim_start_matches('*');
+        if !sha256(&dir.join(name))?.eq_ignore_ascii_case(hash) {
+            bail!("{}: checksum mismatch", name)
+        }
+        n += 1;
+    }
+    info!("verified {} bundled converters in {}", n, dir.display());
+    // tgs_to_gif.sh looks up gifski and such on PATH.
+    let path = env::var_os("PATH").unwrap_or_default();
+    let paths = iter::once(dir.clone()).chain(env::split_paths(&path));
+    env::set_var("PATH", env::join_paths(paths)?);
+    Ok(())
+}
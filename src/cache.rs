@@ -0,0 +1,76 @@
+// Results kept on disk under DATA_DIR/cache, keyed by the file_unique_id of the input and the
+// variant made of it, such as the GIF of a sticker, so that popular stickers are only converted
+// once. The oldest entries are removed beyond CACHE_SIZE_MB.
+
+use crate::{config, Blob};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::PathBuf;
+use tokio::fs;
+
+const CACHE_DIR: &str = "cache";
+
+fn dir() -> PathBuf {
+    config::get().data_dir.join(CACHE_DIR)
+}
+
+fn path(key: &str) -> PathBuf {
+    let hash = Sha256::digest(key.as_bytes());
+    dir().join(
+        hash.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>(),
+    )
+}
+
+pub async fn get(key: &str, ext: &'static str) -> Option<Blob> {
+    if config::get().cache_size == 0 {
+        return None;
+    }
+    let data = fs::read(path(key)).await.ok()?;
+    info!("cache hit: {} B for {}", data.len(), key);
+    Some(Blob::new(data, ext))
+}
+
+async fn write(key: &str, b: &Blob) -> io::Result<()> {
+    fs::create_dir_all(dir()).await?;
+    let path = path(key);
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, b.bytes().await?).await?;
+    fs::rename(&tmp, &path).await
+}
+
+async fn prune(max: u64) -> io::Result<()> {
+    let mut entries = vec![];
+    let mut total = 0;
+    let mut rd = fs::read_dir(dir()).await?;
+    while let Some(e) = rd.next_entry().await? {
+        let m = e.metadata().await?;
+        total += m.len();
+        entries.push((m.modified()?, m.len(), e.path()));
+    }
+    entries.sort();
+    for (_, len, path) in entries {
+        if total <= max {
+            break;
+        }
+        fs::remove_file(&path).await?;
+        total -= len;
+    }
+    Ok(())
+}
+
+pub async fn put(key: &str, b: &Blob) {
+    let max = config::get().cache_size;
+    if max == 0 {
+        return;
+    }
+    if let Err(e) = write(key, b).await {
+        warn!("cache: {}", e);
+        return;
+    }
+    if let Err(e) = prune(max).await {
+        warn!("cache: {}", e);
+    }
+}
@@ -0,0 +1,79 @@
+// The external converters found at startup. Media needing a missing one is refused with a clear
+// message instead of failing halfway, and the admins are told once.
+
+use crate::{alert, config};
+use log::{info, warn};
+use std::env;
+use std::path::Path;
+use std::sync::OnceLock;
+
+#[cfg(feature = "ocr")]
+use crate::ocr::TESSERACT;
+#[cfg(feature = "pdf")]
+use crate::vector::PDFTOPPM;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    pub ffmpeg: bool,
+    pub ffprobe: bool,
+    pub tgs_to_gif: bool,
+    #[cfg(feature = "pdf")]
+    pub pdftoppm: bool,
+    #[cfg(feature = "ocr")]
+    pub tesseract: bool,
+}
+
+static DETECTED: OnceLock<Capabilities> = OnceLock::new();
+
+// Like a shell would find it, so names with a slash are taken as paths.
+fn on_path(name: &str) -> bool {
+    if name.contains('/') {
+        return Path::new(name).is_file();
+    }
+    env::var_os("PATH").map_or(false, |paths| {
+        env::split_paths(&paths).any(|dir| dir.join(name).is_file())
+    })
+}
+
+pub fn detect() {
+    let cfg = config::get();
+    let caps = Capabilities {
+        ffmpeg: on_path(&cfg.ffmpeg),
+        ffprobe: on_path(&cfg.ffprobe),
+        tgs_to_gif: on_path(&cfg.tgs_to_gif),
+        #[cfg(feature = "pdf")]
+        pdftoppm: on_path(PDFTOPPM),
+        #[cfg(feature = "ocr")]
+        tesseract: on_path(TESSERACT),
+    };
+    let mut missing = vec![];
+    if !caps.ffmpeg {
+        missing.push(cfg.ffmpeg.as_str());
+    }
+    if !caps.ffprobe {
+        missing.push(cfg.ffprobe.as_str());
+    }
+    if !caps.tgs_to_gif {
+        missing.push(cfg.tgs_to_gif.as_str());
+    }
+    #[cfg(feature = "pdf")]
+    if !caps.pdftoppm {
+        missing.push(PDFTOPPM);
+    }
+    #[cfg(feature = "ocr")]
+    if !caps.tesseract {
+        missing.push(TESSERACT);
+    }
+    if missing.is_empty() {
+        info!("all converters found");
+    } else {
+        let s = format!("missing converters: {}", missing.join(", "));
+        warn!("{}", s);
+        alert::send(s);
+    }
+    DETECTED.set(caps).ok();
+}
+
+pub fn get() -> Capabilities {
+    *DETECTED.get().expect("capabilities not detected")
+}
@@ -0,0 +1,132 @@
+// The command line, for running the pipeline without Telegram: "convert" turns a local file into a
+// sticker as if it had been sent to the bot with the caption given by -c, which helps telling a
+// bad input from a bad upload and scripting conversions in bulk.
+
+use crate::batch::VIDEO_EXTS;
+use crate::options::VideoEdit;
+use crate::{bench, plan, probe, temp_file, tgs_to_gif, watermark, worker, Blob, MAX_DURATION};
+use anyhow::{Context, Result as AnyResult};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Time each stage of the pipeline on generated inputs, averaged over RUNS, and exit
+    #[arg(long, value_name = "RUNS", num_args = 0..=1, default_missing_value = "1")]
+    pub bench: Option<u32>,
+    #[command(subcommand)]
+    pub command: Option<Cmd>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Cmd {
+    /// Convert a local file into a sticker
+    Convert {
+        input: PathBuf,
+        /// Where to write the sticker, by default next to the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Options as given in a caption, such as "speed=2x fps=30"
+        #[arg(short, long, default_value = "")]
+        caption: String,
+        /// Only tell what would be done, as /plan does
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl Cli {
+    // Whether to run it rather than the bot.
+    pub fn is_oneshot(&self) -> bool {
+        self.bench.is_some() || self.command.is_some()
+    }
+
+    pub async fn run(self) -> AnyResult<()> {
+        if let Some(runs) = self.bench {
+            let r = bench::run(runs).await;
+            println!("{}", bench::summary(&r));
+            r?;
+        }
+        match self.command {
+            Some(Cmd::Convert {
+                input,
+                output,
+                caption,
+                dry_run,
+            }) => convert(&input, output, &caption, dry_run).await,
+            None => Ok(()),
+        }
+    }
+}
+
+fn ext(path: &Path) -> String {
+    path.extension()
+        .map_or(String::new(), |e| e.to_string_lossy().to_ascii_lowercase())
+}
+
+async fn write_temp(data: &[u8]) -> AnyResult<tempfile::TempPath> {
+    let (path, mut f) = temp_file().await?;
+    f.write_all(data).await?;
+    drop(f);
+    Ok(path)
+}
+
+// Long videos are sped up when the bot would, short of asking for a segment.
+async fn video(path: &Path, edit: &mut VideoEdit) -> AnyResult<Blob> {
+    let info = probe::probe(path).await.unwrap_or_default();
+    println!("input: {:?}", info);
+    if edit.is_empty() {
+        if let Some(d) = info.duration.filter(|&d| d > MAX_DURATION + 0.1) {
+            let speed = d / MAX_DURATION;
+            println!("speeding up {:.1} s video by {:.2}x", d, speed);
+            edit.speed = Some(speed);
+        }
+    }
+    edit.cap_fps(&info);
+    crate::process_video(path, edit).await
+}
+
+async fn plan(input: &Path, data: Vec<u8>, edit: &VideoEdit) -> AnyResult<()> {
+    let p = match ext(input).as_str() {
+        "tgs" => plan::tgs(input, edit).await?,
+        e if VIDEO_EXTS.contains(&e) => plan::video(input, edit).await?,
+        _ => plan::image(data, edit).await?,
+    };
+    println!("{}", p);
+    Ok(())
+}
+
+pub async fn convert(
+    input: &Path,
+    output: Option<PathBuf>,
+    caption: &str,
+    dry_run: bool,
+) -> AnyResult<()> {
+    let data = tokio::fs::read(input)
+        .await
+        .with_context(|| input.display().to_string())?;
+    let mut edit = VideoEdit::parse(caption)?;
+    edit.watermark = watermark::get(None);
+    if dry_run || edit.plan {
+        return plan(input, data, &edit).await;
+    }
+    let b = match ext(input).as_str() {
+        "tgs" => {
+            let gif = tgs_to_gif(&write_temp(&data).await?).await?;
+            video(&write_temp(&gif.bytes().await?).await?, &mut edit).await?
+        }
+        e if VIDEO_EXTS.contains(&e) => video(&write_temp(&data).await?, &mut edit).await?,
+        _ => worker::process_image(data, &edit).await?,
+    };
+    let output = output.unwrap_or_else(|| input.with_extension(format!("sticker.{}", b.ext)));
+    tokio::fs::write(&output, b.bytes().await?)
+        .await
+        .with_context(|| output.display().to_string())?;
+    println!("{}: {} B", output.display(), b.len());
+    if let Some(s) = b.report(edit.target()) {
+        println!("{}", s);
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+// Copying a sticker set into a new one of the sender for /clone, with the options of the command
+// applied to every sticker, such as "/clone <link> speed=2 text: mine". The copy is named after
+// the original and the user, so that running it again resumes it, as with /restore.
+
+use crate::archive::{self, Format};
+use crate::error::BotError;
+use crate::options::VideoEdit;
+use crate::restore::{self, NewSet, Restored};
+use crate::target::Target;
+use crate::{caps, process_video, temp_file, tgs_to_gif, worker, AppBot};
+use anyhow::{bail, Result as AnyResult};
+use log::{info, warn};
+use teloxide::prelude::*;
+use teloxide::types::{InputSticker, UserId};
+use tokio::io::AsyncWriteExt;
+
+// Options that make something other than a sticker of the set.
+fn check(edit: &VideoEdit) -> AnyResult<()> {
+    let other = edit.target.map_or(false, |t| !t.is_telegram())
+        || edit.check
+        || edit.thumb
+        || edit.webp
+        || edit.report
+        || edit.grid.is_some()
+        || edit.frames.is_some();
+    if other {
+        bail!(BotError::BadCaption)
+    }
+    Ok(())
+}
+
+// Animated stickers cannot be edited as they are, so they become video stickers when there is
+// anything to apply.
+async fn transform(data: Vec<u8>, format: Format, edit: &VideoEdit) -> AnyResult<InputSticker> {
+    if edit.is_empty() && !edit.cutout {
+        return restore::input_sticker(format, data);
+    }
+    if format == Format::Static {
+        let b = worker::process_image(data, edit).await?;
+        return restore::input_sticker(format, b.bytes().await?);
+    }
+    let caps = caps::get();
+    if !caps.ffmpeg || (format == Format::Animated && !caps.tgs_to_gif) {
+        bail!(BotError::VideoUnavailable)
+    }
+    let (path, mut f) = temp_file().await?;
+    f.write_all(&data).await?;
+    drop(f);
+    let b = if format == Format::Animated {
+        let gif = tgs_to_gif(&path).await?;
+        let (gif_path, mut f) = temp_file().await?;
+        f.write_all(&gif.bytes().await?).await?;
+        drop(f);
+        process_video(&gif_path, edit).await?
+    } else {
+        process_video(&path, edit).await?
+    };
+    restore::input_sticker(Format::Video, b.bytes().await?)
+}
+
+pub async fn clone(
+    bot: &AppBot,
+    user: UserId,
+    name: &str,
+    mut edit: VideoEdit,
+) -> AnyResult<Restored> {
+    check(&edit)?;
+    let src = archive::fetch(bot, name).await?;
+    let custom_emoji = src.stickers.iter().any(|s| s.is_custom_emoji());
+    if custom_emoji {
+        edit.target = Some(Target::Emoji);
+    }
+    let me = bot.get_me().await?;
+    let set = NewSet {
+        user,
+        name: restore::set_name(&src.name, &user.0.to_string(), me.username()),
+        title: src.title.clone(),
+        custom_emoji,
+    };
+    let mut r = Restored {
+        added: set.existing(bot).await?.min(src.stickers.len()),
+        total: src.stickers.len(),
+        name: set.name.clone(),
+    };
+    for sti in src.stickers.iter().skip(r.added) {
+        let data = archive::download(bot, sti).await?;
+        let sticker = transform(data, Format::of(&sti.format), &edit).await?;
+        let emoji = sti.emoji.as_deref().unwrap_or_default();
+        if let Err(e) = set.add(bot, r.added == 0, sticker, emoji).await {
+            warn!("clone {}: {}", r.name, e);
+            if r.added == 0 {
+                bail!(BotError::from(e))
+            }
+            break;
+        }
+        r.added += 1;
+    }
+    info!(
+        "cloned {} of {} stickers of {} into {}",
+        r.added, r.total, src.name, r.name
+    );
+    Ok(r)
+}
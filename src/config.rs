@@ -0,0 +1,447 @@
+// Read from the environment, where KEY=VALUE lines of CONFIG_FILE take precedence. The admin
+// /reload reads the file again, so that limits, allowlists and encoder settings change without a
+// restart, while what was set up with the rest is kept until one.
+
+use crate::batch::MAX_ENTRIES;
+use crate::payments::MAX_STARS;
+use crate::text::Position;
+use crate::watermark::Corner;
+use crate::WebpFallback;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+use teloxide::types::{ChatId, UserId};
+use url::Url;
+
+#[derive(Debug)]
+pub struct Config {
+    pub data_dir: PathBuf,
+    pub admins: HashSet<UserId>,
+    // None means everyone is allowed.
+    pub allowed_users: Option<HashSet<UserId>>,
+    pub blocked_users: HashSet<UserId>,
+    // Daily conversions of each tier, the first being everyone's, with None for no limit. Empty
+    // means there are no quotas.
+    pub quotas: Vec<Option<u32>>,
+    // In Telegram Stars, None leaving /donate and /premium out.
+    pub donate_stars: Option<u32>,
+    pub premium_stars: Option<u32>,
+    pub premium_days: u32,
+    // Of premium users, who are also let past batch_entries to a whole pack.
+    pub premium_file_size: Option<u32>,
+    // Files converted from one zip.
+    pub batch_entries: usize,
+    // Maximum number of external converters running at once.
+    pub max_jobs: usize,
+    // none, auto, vaapi, qsv or nvenc.
+    pub hw_accel: Option<String>,
+    pub vaapi_device: String,
+    // Speed up videos slightly over the duration limit instead of asking which part to keep.
+    pub auto_speedup: bool,
+    pub max_auto_speed: f64,
+    // lossy, upscale, png or fail, when the webp encoder fails on an image.
+    pub webp_fallback: WebpFallback,
+    // Telegram rejects video stickers above 30 fps.
+    pub max_fps: u32,
+    // u2net ONNX model used by /cutout.
+    pub cutout_model: Option<PathBuf>,
+    // ESRGAN or Real-CUGAN ONNX model used by /upscale.
+    pub upscale_model: Option<PathBuf>,
+    // Image classifier ONNX model and its emoji, one per class, used to suggest sticker emoji.
+    pub emoji_model: Option<PathBuf>,
+    pub emoji_labels: Option<PathBuf>,
+    // Classifier ONNX model of explicit images, the indexes of the explicit classes, and the share
+    // of them from which input is refused.
+    pub nsfw_model: Option<PathBuf>,
+    pub nsfw_classes: Vec<usize>,
+    pub nsfw_threshold: f32,
+    // Also checked in private chats, with NSFW_FILTER=all rather than groups.
+    pub nsfw_everywhere: bool,
+    // Tesseract languages read by /ocr, joined with +.
+    pub ocr_langs: String,
+    // Text overlays.
+    pub font_path: Option<PathBuf>,
+    pub text_size: f32,
+    pub text_outline: u32,
+    pub text_position: Position,
+    // Composited onto every output, the image if both are given.
+    pub watermark_text: Option<String>,
+    pub watermark_image: Option<PathBuf>,
+    pub watermark_corner: Corner,
+    pub watermark_opacity: f32,
+    // Lets users set a watermark text of their own when the instance has none.
+    pub user_watermarks: bool,
+    // Always append a size and validity report to converted stickers.
+    pub report: bool,
+    // Show the tail of ffmpeg's stderr to users too, under a spoiler. Admins always see it.
+    pub show_stderr: bool,
+    // Where failures and panics are reported.
+    pub error_chat: Option<ChatId>,
+    // Largest input accepted. Telegram's servers hand out files up to 20 MB, a local Bot API
+    // server at api_url up to 2 GB.
+    pub max_file_size: u32,
+    pub api_url: Option<Url>,
+    // Bytes of results kept under data_dir, 0 disables the cache.
+    pub cache_size: u64,
+    // Where temporary files go, the system's by default, and bytes of them allowed at once, 0
+    // for no limit.
+    pub temp_dir: Option<PathBuf>,
+    pub max_temp_size: u64,
+    // Serves GET /healthz, which runs the self-test.
+    pub health_addr: Option<SocketAddr>,
+    // Holds verified converters, which become the defaults of the tools below.
+    pub bundle_dir: Option<PathBuf>,
+    // External tools, by name on PATH or by path.
+    pub ffmpeg: String,
+    pub ffprobe: String,
+    pub tgs_to_gif: String,
+    // Added to every ffmpeg run as global options, e.g. "-threads 2".
+    pub ffmpeg_args: Vec<String>,
+    // Added after the libvpx-vp9 encoder is chosen, e.g. "-deadline good -cpu-used 2".
+    pub vp9_args: Vec<String>,
+    // Output options when turning video stickers into GIFs.
+    pub gif_args: Vec<String>,
+    // oxipng preset from 0 to 6 for PNG outputs, None to send them as encoded, and whether to
+    // deflate them with zopfli, which is far slower.
+    pub png_level: Option<u8>,
+    pub png_zopfli: bool,
+    // Scaler flags of the scale filters, e.g. lanczos.
+    pub scale_flags: Option<String>,
+    // Of each converter run: the nice level, the best-effort I/O priority from 0 to 7, memory in
+    // MB and CPUs. The memory bounds the address space, or the RSS with cgroup_dir.
+    pub job_nice: Option<i32>,
+    pub job_ionice: Option<u8>,
+    pub job_memory: Option<u64>,
+    pub job_cpus: Option<f64>,
+    // A cgroup v2 directory delegated to the bot, which must not be in it itself, with the memory
+    // and cpu controllers enabled for its children.
+    pub cgroup_dir: Option<PathBuf>,
+    // How long each kind of converter run may take, as lottie renders take far longer than
+    // probes, and how long a download may.
+    pub ffmpeg_timeout: Duration,
+    pub tgs_timeout: Duration,
+    pub probe_timeout: Duration,
+    pub pdf_timeout: Duration,
+    pub ocr_timeout: Duration,
+    pub download_timeout: Duration,
+    // Processes converting images, each replaced after worker_jobs jobs. 0 converts in-process.
+    // One taking longer than worker_timeout over a job is killed.
+    pub workers: usize,
+    pub worker_jobs: u32,
+    pub worker_timeout: Duration,
+}
+
+// Leaked, so that what get() returned stays valid after a reload, which is rare enough for it not
+// to matter.
+static CONFIG: RwLock<Option<&'static Config>> = RwLock::new(None);
+// KEY=VALUE lines of CONFIG_FILE, which take the place of the environment.
+static FILE_VARS: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+fn read_file() -> io::Result<HashMap<String, String>> {
+    let Some(path) = env::var_os("CONFIG_FILE") else {
+        return Ok(HashMap::new());
+    };
+    let mut vars = HashMap::new();
+    for line in fs::read_to_string(path)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((k, v)) => {
+                vars.insert(k.trim().to_owned(), v.trim().to_owned());
+            }
+            None => warn!("CONFIG_FILE: ignoring {:?}", line),
+        }
+    }
+    Ok(vars)
+}
+
+fn lookup(var: &str) -> Result<String, env::VarError> {
+    let file = FILE_VARS.read().unwrap();
+    match file.as_ref().and_then(|f| f.get(var)) {
+        Some(v) => Ok(v.clone()),
+        None => env::var(var),
+    }
+}
+
+fn lookup_os(var: &str) -> Option<OsString> {
+    match lookup(var) {
+        Ok(v) => Some(v.into()),
+        Err(_) => env::var_os(var),
+    }
+}
+
+fn parse_env<T: FromStr>(var: &str) -> Option<T>
+where
+    T::Err: Display,
+{
+    let s = lookup(var).ok()?;
+    match s.trim().parse() {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!("{}: ignoring {:?}: {}", var, s, e);
+            None
+        }
+    }
+}
+
+// Whole seconds, above zero.
+fn parse_secs(var: &str, default: u64) -> Duration {
+    Duration::from_secs(parse_env::<u64>(var).filter(|&n| n > 0).unwrap_or(default))
+}
+
+fn parse_users(var: &str) -> Option<HashSet<UserId>> {
+    let s = lookup(var).ok()?;
+    let mut ids = HashSet::new();
+    for part in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        match part.parse() {
+            Ok(id) => {
+                ids.insert(UserId(id));
+            }
+            Err(e) => warn!("{}: ignoring {:?}: {}", var, part, e),
+        }
+    }
+    Some(ids)
+}
+
+fn parse_quotas(var: &str) -> Vec<Option<u32>> {
+    let Ok(s) = lookup(var) else {
+        return vec![];
+    };
+    let mut v = vec![];
+    for part in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        match part {
+            "unlimited" => v.push(None),
+            _ => match part.parse() {
+                Ok(n) => v.push(Some(n)),
+                Err(e) => warn!("{}: ignoring {:?}: {}", var, part, e),
+            },
+        }
+    }
+    v
+}
+
+fn parse_args(var: &str, default: &str) -> Vec<String> {
+    lookup(var)
+        .unwrap_or_else(|_| default.to_owned())
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect()
+}
+
+impl Config {
+    // What stores, converters, workers and the like were set up with.
+    fn keep_startup(&mut self, old: &Config) -> Vec<&'static str> {
+        let mut kept = vec![];
+        macro_rules! keep {
+            ($($field:ident $var:literal),* $(,)?) => {
+                $(
+                    if self.$field != old.$field {
+                        kept.push($var);
+                        self.$field = old.$field.clone();
+                    }
+                )*
+            };
+        }
+        keep!(
+            data_dir "DATA_DIR",
+            max_jobs "MAX_JOBS",
+            hw_accel "HW_ACCEL",
+            vaapi_device "VAAPI_DEVICE",
+            cutout_model "CUTOUT_MODEL",
+            upscale_model "UPSCALE_MODEL",
+            emoji_model "EMOJI_MODEL",
+            emoji_labels "EMOJI_LABELS",
+            nsfw_model "NSFW_MODEL",
+            font_path "FONT_PATH",
+            health_addr "HEALTH_ADDR",
+            api_url "TELEGRAM_API_URL",
+            temp_dir "TEMP_DIR",
+            bundle_dir "BUNDLE_DIR",
+            ffmpeg "FFMPEG",
+            ffprobe "FFPROBE",
+            tgs_to_gif "TGS_TO_GIF",
+            cgroup_dir "CGROUP_DIR",
+            workers "WORKERS",
+        );
+        kept
+    }
+
+    fn from_env() -> Self {
+        let bundle_dir = lookup_os("BUNDLE_DIR").map(PathBuf::from);
+        let tool = |var: &str, name: &str| {
+            lookup(var).unwrap_or_else(|_| match &bundle_dir {
+                Some(dir) => dir.join(name).to_string_lossy().into_owned(),
+                None => name.to_owned(),
+            })
+        };
+        // Text watermarks are drawn with it, so without it every output would fail.
+        let font_path = lookup_os("FONT_PATH").map(PathBuf::from);
+        let needs_font = |var: &str, set: bool| {
+            if set && font_path.is_none() {
+                warn!("{}: ignoring, as FONT_PATH is not set", var);
+            }
+            set && font_path.is_some()
+        };
+        let watermark_text = lookup("WATERMARK_TEXT")
+            .ok()
+            .filter(|s| needs_font("WATERMARK_TEXT", !s.is_empty()));
+        let user_watermarks = needs_font(
+            "USER_WATERMARKS",
+            parse_env("USER_WATERMARKS").unwrap_or(false),
+        );
+        Self {
+            data_dir: lookup_os("DATA_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            admins: parse_users("ADMIN_USERS").unwrap_or_default(),
+            allowed_users: parse_users("ALLOWED_USERS"),
+            blocked_users: parse_users("BLOCKED_USERS").unwrap_or_default(),
+            quotas: parse_quotas("QUOTAS"),
+            donate_stars: parse_env("DONATE_STARS").filter(|n| (1..=MAX_STARS).contains(n)),
+            premium_stars: parse_env("PREMIUM_STARS").filter(|n| (1..=MAX_STARS).contains(n)),
+            premium_days: parse_env("PREMIUM_DAYS").filter(|&n| n > 0).unwrap_or(30),
+            premium_file_size: parse_env("PREMIUM_FILE_SIZE_MB")
+                .filter(|&n: &u32| (1..=4000).contains(&n))
+                .map(|n| n << 20),
+            batch_entries: parse_env("BATCH_ENTRIES")
+                .filter(|n| (1..=MAX_ENTRIES).contains(n))
+                .unwrap_or(MAX_ENTRIES),
+            max_jobs: parse_env("MAX_JOBS")
+                .filter(|&n| n > 0)
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get())),
+            hw_accel: lookup("HW_ACCEL").ok(),
+            vaapi_device: lookup("VAAPI_DEVICE")
+                .unwrap_or_else(|_| "/dev/dri/renderD128".to_owned()),
+            auto_speedup: parse_env("AUTO_SPEEDUP").unwrap_or(true),
+            max_auto_speed: parse_env("MAX_AUTO_SPEED")
+                .filter(|&x: &f64| x >= 1.0)
+                .unwrap_or(2.0),
+            webp_fallback: lookup("WEBP_FALLBACK")
+                .ok()
+                .and_then(|s| WebpFallback::parse(&s))
+                .unwrap_or_default(),
+            max_fps: parse_env("MAX_FPS")
+                .filter(|&n| (1..=30).contains(&n))
+                .unwrap_or(30),
+            cutout_model: lookup_os("CUTOUT_MODEL").map(PathBuf::from),
+            upscale_model: lookup_os("UPSCALE_MODEL").map(PathBuf::from),
+            emoji_model: lookup_os("EMOJI_MODEL").map(PathBuf::from),
+            emoji_labels: lookup_os("EMOJI_LABELS").map(PathBuf::from),
+            nsfw_model: lookup_os("NSFW_MODEL").map(PathBuf::from),
+            nsfw_classes: lookup("NSFW_CLASSES")
+                .map(|s| s.split(',').filter_map(|n| n.trim().parse().ok()).collect())
+                .unwrap_or_else(|_| vec![1]),
+            nsfw_threshold: parse_env("NSFW_THRESHOLD")
+                .filter(|x: &f32| (0.0..=1.0).contains(x))
+                .unwrap_or(0.8),
+            nsfw_everywhere: match lookup("NSFW_FILTER").as_deref() {
+                Ok("all") => true,
+                Ok("groups") | Err(_) => false,
+                Ok(s) => {
+                    warn!("NSFW_FILTER: ignoring {:?}", s);
+                    false
+                }
+            },
+            ocr_langs: lookup("OCR_LANGS")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| "eng".to_owned()),
+            font_path,
+            text_size: parse_env("TEXT_SIZE")
+                .filter(|&x: &f32| x >= 8.0)
+                .unwrap_or(64.0),
+            text_outline: parse_env("TEXT_OUTLINE").filter(|&n| n <= 16).unwrap_or(3),
+            text_position: lookup("TEXT_POSITION")
+                .ok()
+                .and_then(|s| Position::parse(&s))
+                .unwrap_or_default(),
+            watermark_text,
+            watermark_image: lookup_os("WATERMARK_IMAGE").map(PathBuf::from),
+            watermark_corner: lookup("WATERMARK_CORNER")
+                .ok()
+                .and_then(|s| Corner::parse(&s))
+                .unwrap_or_default(),
+            watermark_opacity: parse_env("WATERMARK_OPACITY")
+                .filter(|x: &f32| (0.0..=1.0).contains(x))
+                .unwrap_or(0.5),
+            user_watermarks,
+            report: parse_env("REPORT").unwrap_or(false),
+            show_stderr: parse_env("SHOW_STDERR").unwrap_or(false),
+            error_chat: parse_env("ERROR_CHAT_ID").map(ChatId),
+            health_addr: parse_env("HEALTH_ADDR"),
+            max_file_size: parse_env("MAX_FILE_SIZE_MB")
+                .filter(|&n: &u32| (1..=4000).contains(&n))
+                .map_or(10 << 20, |n| n << 20),
+            api_url: parse_env("TELEGRAM_API_URL"),
+            cache_size: parse_env::<u64>("CACHE_SIZE_MB").unwrap_or(256) << 20,
+            temp_dir: lookup_os("TEMP_DIR").map(PathBuf::from),
+            max_temp_size: parse_env::<u64>("MAX_TEMP_MB").unwrap_or(2048) << 20,
+            ffmpeg: tool("FFMPEG", "ffmpeg"),
+            ffprobe: tool("FFPROBE", "ffprobe"),
+            tgs_to_gif: tool("TGS_TO_GIF", "tgs_to_gif.sh"),
+            ffmpeg_args: parse_args("FFMPEG_ARGS", ""),
+            vp9_args: parse_args("VP9_ARGS", ""),
+            gif_args: parse_args("GIF_ARGS", "-c:v gif -f gif"),
+            png_level: match lookup("PNG_LEVEL").as_deref() {
+                Ok("off") => None,
+                _ => Some(parse_env("PNG_LEVEL").filter(|&n| n <= 6).unwrap_or(2)),
+            },
+            png_zopfli: parse_env("PNG_ZOPFLI").unwrap_or(false),
+            scale_flags: lookup("SCALE_FLAGS").ok().filter(|s| !s.is_empty()),
+            job_nice: parse_env("JOB_NICE").filter(|n| (0..=19).contains(n)),
+            job_ionice: parse_env("JOB_IONICE").filter(|&n| n <= 7),
+            job_memory: parse_env("JOB_MEMORY_MB").filter(|&n| n > 0),
+            job_cpus: parse_env("JOB_CPUS").filter(|&x: &f64| x > 0.0),
+            cgroup_dir: lookup_os("CGROUP_DIR").map(PathBuf::from),
+            ffmpeg_timeout: parse_secs("FFMPEG_TIMEOUT", 60),
+            tgs_timeout: parse_secs("TGS_TIMEOUT", 180),
+            probe_timeout: parse_secs("PROBE_TIMEOUT", 15),
+            pdf_timeout: parse_secs("PDF_TIMEOUT", 60),
+            ocr_timeout: parse_secs("OCR_TIMEOUT", 30),
+            download_timeout: parse_secs("DOWNLOAD_TIMEOUT", 120),
+            workers: parse_env("WORKERS").unwrap_or(0),
+            worker_jobs: parse_env("WORKER_JOBS").filter(|&n| n > 0).unwrap_or(100),
+            worker_timeout: parse_secs("WORKER_TIMEOUT", 120),
+            bundle_dir,
+        }
+    }
+}
+
+pub fn init() -> &'static Config {
+    let mut config = CONFIG.write().unwrap();
+    if let Some(c) = *config {
+        return c;
+    }
+    let vars = read_file().unwrap_or_else(|e| {
+        warn!("CONFIG_FILE: {}", e);
+        HashMap::new()
+    });
+    *FILE_VARS.write().unwrap() = Some(vars);
+    *config.insert(Box::leak(Box::new(Config::from_env())))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.read().unwrap().expect("config not initialized")
+}
+
+// With CONFIG_FILE read again. Those set up at startup keep their values, and the names of any
+// that changed nonetheless are returned, to be told that they wait for a restart.
+pub fn reload() -> io::Result<Vec<&'static str>> {
+    *FILE_VARS.write().unwrap() = Some(read_file()?);
+    let mut c = Config::from_env();
+    let kept = c.keep_startup(get());
+    info!("config reloaded, keeping {:?}", kept);
+    *CONFIG.write().unwrap() = Some(Box::leak(Box::new(c)));
+    Ok(kept)
+}
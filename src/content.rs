@@ -0,0 +1,106 @@
+// What a document really holds, told by its magic bytes, as its name may lie: a .gif that is an
+// mp4, or a .png that is a webm. Only images and videos are told apart here, GIFs going with the
+// latter as they are converted by ffmpeg, and so do tgs files, which infer only knows as gzip.
+// Anything else is routed by its name as before.
+
+use flate2::read::GzDecoder;
+use infer::MatcherType;
+use std::io::Read;
+
+// infer looks no further than the tar header at 257.
+pub const HEAD_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Content {
+    pub ext: &'static str,
+    pub video: bool,
+}
+
+// Lottie files start with their version or frame rate, Telegram's with "tgs" before them.
+const LOTTIE_KEYS: [&str; 3] = ["\"tgs\":", "\"v\":", "\"fr\":"];
+
+// What the head decompresses to is enough to tell, the whole file being checked before rendering.
+fn is_tgs(head: &[u8]) -> bool {
+    if !head.starts_with(&[0x1f, 0x8b]) {
+        return false;
+    }
+    let mut json = [0; 64];
+    let mut d = GzDecoder::new(head);
+    let mut n = 0;
+    // The head is cut short, which only fails the read past what it holds.
+    while n < json.len() {
+        match d.read(&mut json[n..]) {
+            Ok(0) | Err(_) => break,
+            Ok(k) => n += k,
+        }
+    }
+    let s = String::from_utf8_lossy(&json[..n]);
+    let s = s.trim_start();
+    s.starts_with('{') && LOTTIE_KEYS.iter().any(|k| s.contains(k))
+}
+
+pub fn sniff(head: &[u8]) -> Option<Content> {
+    if is_tgs(head) {
+        return Some(Content {
+            ext: "tgs",
+            video: true,
+        });
+    }
+    let t = infer::get(head)?;
+    let video = match t.matcher_type() {
+        MatcherType::Video => true,
+        MatcherType::Image => t.extension() == "gif",
+        _ => return None,
+    };
+    Some(Content {
+        ext: t.extension(),
+        video,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(vec![], Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn sniffed(head: &[u8]) -> Option<(&'static str, bool)> {
+        sniff(head).map(|c| (c.ext, c.video))
+    }
+
+    #[test]
+    fn images_and_videos() {
+        assert_eq!(
+            sniffed(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(("png", false))
+        );
+        assert_eq!(sniffed(b"GIF89a\x01\0\x01\0"), Some(("gif", true)));
+        assert_eq!(
+            sniffed(b"\0\0\0\x18ftypisom\0\0\x02\0isomiso2"),
+            Some(("mp4", true))
+        );
+    }
+
+    #[test]
+    fn tgs_by_content() {
+        let tgs = gzip(br#"{"tgs":1,"v":"5.5.2","fr":60}"#);
+        assert_eq!(sniffed(&tgs), Some(("tgs", true)));
+        // Cut short, as heads are.
+        assert_eq!(sniffed(&tgs[..tgs.len() - 4]), Some(("tgs", true)));
+        assert_eq!(sniffed(&gzip(b"not lottie at all")), None);
+    }
+
+    #[test]
+    fn others_by_name() {
+        assert_eq!(sniffed(b"PK\x03\x04\x14\0\0\0"), None);
+        assert_eq!(sniffed(b"plain text"), None);
+        assert_eq!(sniffed(b""), None);
+    }
+}
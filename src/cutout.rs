@@ -0,0 +1,86 @@
+// Background removal with a u2net salient object detection model, run through onnxruntime.
+
+use crate::error::BotError;
+use crate::{config, job_permit};
+use anyhow::{anyhow, bail, Result as AnyResult};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+use log::info;
+use ndarray::{Array4, CowArray, Ix4};
+use ort::{Environment, GraphOptimizationLevel, OrtOwnedTensor, Session, SessionBuilder, Value};
+use std::sync::OnceLock;
+
+// u2net and its smaller variant u2netp both take 320x320 inputs.
+const SIDE: u32 = 320;
+const MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+static SESSION: OnceLock<Result<Session, String>> = OnceLock::new();
+
+fn session() -> AnyResult<&'static Session> {
+    SESSION
+        .get_or_init(|| {
+            let Some(path) = &config::get().cutout_model else {
+                return Err("CUTOUT_MODEL is not set".to_owned());
+            };
+            let env = Environment::builder()
+                .with_name("cutout")
+                .build()
+                .map_err(|e| e.to_string())?
+                .into_arc();
+            let s = SessionBuilder::new(&env)
+                .and_then(|b| b.with_optimization_level(GraphOptimizationLevel::Level3))
+                .and_then(|b| b.with_model_from_file(path))
+                .map_err(|e| e.to_string())?;
+            info!("cutout: loaded {}", path.display());
+            Ok(s)
+        })
+        .as_ref()
+        .map_err(|e| anyhow!("cutout: {}", e))
+}
+
+// Returns the saliency mask at the model's resolution, normalized to 0..=255.
+fn predict(img: &DynamicImage) -> AnyResult<GrayImage> {
+    let session = session()?;
+    let small = img.resize_exact(SIDE, SIDE, FilterType::Triangle).to_rgb8();
+    let mut input = Array4::<f32>::zeros((1, 3, SIDE as usize, SIDE as usize));
+    for (x, y, px) in small.enumerate_pixels() {
+        for (c, &v) in px.0.iter().enumerate() {
+            input[[0, c, y as usize, x as usize]] = (v as f32 / 255.0 - MEAN[c]) / STD[c];
+        }
+    }
+    let input = CowArray::from(input.into_dyn());
+    let outputs = session.run(vec![Value::from_array(session.allocator(), &input)?])?;
+    let out: OrtOwnedTensor<f32, _> = outputs[0].try_extract()?;
+    let out = out.view().into_dimensionality::<Ix4>()?;
+
+    let (lo, hi) = out
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let range = if hi > lo { hi - lo } else { 1.0 };
+    let mut mask = GrayImage::new(SIDE, SIDE);
+    for (x, y, px) in mask.enumerate_pixels_mut() {
+        let v = out[[0, 0, y as usize, x as usize]];
+        *px = Luma([((v - lo) / range * 255.0).round() as u8]);
+    }
+    Ok(mask)
+}
+
+fn apply(img: DynamicImage) -> AnyResult<DynamicImage> {
+    let (w, h) = img.dimensions();
+    let mask = imageops::resize(&predict(&img)?, w, h, FilterType::Triangle);
+    let mut rgba = img.to_rgba8();
+    for (px, m) in rgba.pixels_mut().zip(mask.pixels()) {
+        // Keep any transparency the source already had.
+        px[3] = (px[3] as u16 * m[0] as u16 / 255) as u8;
+    }
+    Ok(DynamicImage::ImageRgba8(rgba))
+}
+
+pub async fn remove_background(img: DynamicImage) -> AnyResult<DynamicImage> {
+    if config::get().cutout_model.is_none() {
+        bail!(BotError::CutoutUnavailable)
+    }
+    let _permit = job_permit().await;
+    tokio::task::spawn_blocking(move || apply(img)).await?
+}
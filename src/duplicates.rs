@@ -0,0 +1,97 @@
+// Pictures converted again. Static results of plain conversions are kept in the history with a
+// difference hash of what they were made from, which changes little with scaling, compression or
+// a watermark, and worked out by the conversion while it has the picture decoded. A result whose
+// hash is close to one of them is not sent right away: the user is offered the earlier one
+// instead, or the new one anyway, which waits here meanwhile.
+
+use crate::offers::{Kind, Offers};
+use crate::options::VideoEdit;
+use crate::Blob;
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+pub static OFFERS: Offers<Offer> = Offers::new("dup", Kind::Image);
+// Of the 64 bits, up to which the pictures are taken as the same.
+pub const MAX_DISTANCE: u32 = 5;
+
+// Whether each pixel of a 9x8 grayscale thumbnail is brighter than the next one in its row, with
+// transparent parts taken as white.
+pub fn hash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_rgba8();
+    let luma = |x: u32, y: u32| {
+        let px = small.get_pixel(x, y);
+        let a = px[3] as u32;
+        let l = (299 * px[0] as u32 + 587 * px[1] as u32 + 114 * px[2] as u32) / 1000;
+        l * a + 255 * (255 - a)
+    };
+    let mut h = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            h = h << 1 | (luma(x, y) > luma(x + 1, y)) as u64;
+        }
+    }
+    h
+}
+
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    // Send the earlier result again.
+    Resend,
+    Convert,
+}
+
+#[derive(Debug)]
+pub struct Offer {
+    pub blob: Blob,
+    pub edit: VideoEdit,
+    pub base: Option<String>,
+    // Of the earlier result.
+    pub file_id: String,
+}
+
+pub fn callback_data(token: u64, choice: Choice) -> String {
+    let code = match choice {
+        Choice::Resend => 'r',
+        Choice::Convert => 'c',
+    };
+    OFFERS.callback_data(token, code)
+}
+
+pub fn parse_callback_data(data: &str) -> Option<(u64, Choice)> {
+    let (token, code) = OFFERS.parse_callback_data(data)?;
+    let choice = match code {
+        "r" => Choice::Resend,
+        "c" => Choice::Convert,
+        _ => return None,
+    };
+    Some((token, choice))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn distance_counts_bits() {
+        assert_eq!(distance(0, 0), 0);
+        assert_eq!(distance(0b1011, 0b0001), 2);
+        assert_eq!(distance(0, u64::MAX), 64);
+    }
+
+    #[test]
+    fn hash_survives_scaling() {
+        // Brighter to the right in the top half, and to the left in the bottom one.
+        let img = RgbaImage::from_fn(90, 80, |x, y| {
+            let v = (if y < 40 { x * 2 } else { 180 - x * 2 }) as u8;
+            Rgba([v, v, v, 255])
+        });
+        let img = DynamicImage::ImageRgba8(img);
+        let small = img.resize_exact(45, 40, FilterType::Triangle);
+        assert!(distance(hash(&img), hash(&small)) <= MAX_DISTANCE);
+    }
+}
@@ -0,0 +1,144 @@
+// Emoji suggested for static stickers made from a picture, by an image classifier run through
+// onnxruntime such as a MobileNet fine-tuned on emoji. EMOJI_MODEL takes 224x224 images normalized
+// like ImageNet and gives a score per class, and EMOJI_LABELS has the emoji of each class, one per
+// line. They are offered as buttons under the result, and the one tapped saves it to the
+// favorites with the emoji as a tag, so that it is at hand when adding the sticker to a pack.
+// Stickers sent in already have their own emoji.
+
+use crate::{config, job_permit};
+use anyhow::{anyhow, Result as AnyResult};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use log::{info, warn};
+use ndarray::{Array4, CowArray, Ix2};
+use ort::{Environment, GraphOptimizationLevel, OrtOwnedTensor, Session, SessionBuilder, Value};
+use std::fs;
+use std::sync::OnceLock;
+
+const SIDE: u32 = 224;
+const MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const STD: [f32; 3] = [0.229, 0.224, 0.225];
+const SUGGESTIONS: usize = 4;
+// Of the softmax, below which a class is not worth offering.
+const MIN_SCORE: f32 = 0.05;
+pub const PREFIX: &str = "emoji";
+
+struct Model {
+    session: Session,
+    labels: Vec<String>,
+}
+
+static MODEL: OnceLock<Result<Model, String>> = OnceLock::new();
+
+fn model() -> AnyResult<&'static Model> {
+    MODEL
+        .get_or_init(|| {
+            let cfg = config::get();
+            let (Some(path), Some(labels)) = (&cfg.emoji_model, &cfg.emoji_labels) else {
+                return Err("EMOJI_MODEL or EMOJI_LABELS is not set".to_owned());
+            };
+            let labels: Vec<_> = fs::read_to_string(labels)
+                .map_err(|e| format!("{}: {}", labels.display(), e))?
+                .lines()
+                .map(|l| l.trim().to_owned())
+                .collect();
+            let env = Environment::builder()
+                .with_name("emoji")
+                .build()
+                .map_err(|e| e.to_string())?
+                .into_arc();
+            let session = SessionBuilder::new(&env)
+                .and_then(|b| b.with_optimization_level(GraphOptimizationLevel::Level3))
+                .and_then(|b| b.with_model_from_file(path))
+                .map_err(|e| e.to_string())?;
+            info!(
+                "emoji: loaded {} of {} labels",
+                path.display(),
+                labels.len()
+            );
+            Ok(Model { session, labels })
+        })
+        .as_ref()
+        .map_err(|e| anyhow!("emoji: {}", e))
+}
+
+pub fn is_enabled() -> bool {
+    let cfg = config::get();
+    cfg.emoji_model.is_some() && cfg.emoji_labels.is_some()
+}
+
+// Transparent pixels are taken as white, as stickers are mostly seen on light backgrounds.
+fn classify(img: &DynamicImage) -> AnyResult<Vec<String>> {
+    let m = model()?;
+    let small = img
+        .resize_exact(SIDE, SIDE, FilterType::Triangle)
+        .to_rgba8();
+    let mut input = Array4::<f32>::zeros((1, 3, SIDE as usize, SIDE as usize));
+    for (x, y, px) in small.enumerate_pixels() {
+        let a = px[3] as f32 / 255.0;
+        for c in 0..3 {
+            let v = (px[c] as f32 * a + 255.0 * (1.0 - a)) / 255.0;
+            input[[0, c, y as usize, x as usize]] = (v - MEAN[c]) / STD[c];
+        }
+    }
+    let input = CowArray::from(input.into_dyn());
+    let outputs = m
+        .session
+        .run(vec![Value::from_array(m.session.allocator(), &input)?])?;
+    let out: OrtOwnedTensor<f32, _> = outputs[0].try_extract()?;
+    let out = out.view().into_dimensionality::<Ix2>()?;
+    let logits: Vec<f32> = out.row(0).to_vec();
+    let max = logits.iter().cloned().fold(f32::MIN, f32::max);
+    let sum: f32 = logits.iter().map(|&v| (v - max).exp()).sum();
+    let mut scored: Vec<_> = logits
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| (i, (v - max).exp() / sum))
+        .collect();
+    scored.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+    let mut v: Vec<String> = vec![];
+    for (i, score) in scored {
+        if v.len() == SUGGESTIONS || score < MIN_SCORE {
+            break;
+        }
+        match m.labels.get(i).filter(|l| !l.is_empty()) {
+            Some(l) if !v.contains(l) => v.push(l.clone()),
+            Some(_) => {}
+            None => warn!("emoji: no label for class {}", i),
+        }
+    }
+    Ok(v)
+}
+
+// Best first, none when the model is unsure or fails.
+pub async fn suggest(img: DynamicImage) -> Vec<String> {
+    let _permit = job_permit().await;
+    let r = tokio::task::spawn_blocking(move || classify(&img)).await;
+    match r.map_err(anyhow::Error::from).and_then(|r| r) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("{:#}", e);
+            vec![]
+        }
+    }
+}
+
+pub fn callback_data(emoji: &str) -> String {
+    format!("{}:{}", PREFIX, emoji)
+}
+
+pub fn parse_callback_data(data: &str) -> Option<&str> {
+    data.strip_prefix(PREFIX)?.strip_prefix(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_data_round_trip() {
+        assert_eq!(parse_callback_data(&callback_data("😂")), Some("😂"));
+        assert_eq!(parse_callback_data("emojis:😂"), None);
+        assert_eq!(parse_callback_data("dup:1:r"), None);
+    }
+}
@@ -0,0 +1,281 @@
+// Failures worth telling apart, each with a message under locales/. The Display strings are for
+// the log, while users get the translated message, and internal details such as the stderr of
+// ffmpeg are only shown to admins. Anything else that goes wrong is reported as error-generic.
+
+use crate::i18n::Msg;
+use crate::{config, quota, stash};
+use log::error;
+use std::process::Output;
+use teloxide::RequestError;
+use thiserror::Error;
+
+// Lines of stderr shown to admins.
+const STDERR_TAIL_LINES: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum BotError {
+    #[error("bad caption")]
+    BadCaption,
+    #[error("bad option {word}")]
+    BadOption { id: &'static str, word: String },
+    #[error("file too large")]
+    TooLarge,
+    #[error("not an image")]
+    NotAnImage,
+    #[error("unreadable video")]
+    BadVideo,
+    #[error("invalid tgs")]
+    BadTgs,
+    #[error("unknown duration")]
+    UnknownDuration,
+    #[error("/check on an unsupported file")]
+    CheckUsage,
+    #[error("/plan on an unsupported file")]
+    PlanUsage,
+    #[error("static sticker does not fit")]
+    StaticTooBig,
+    #[error("webp encoder failed")]
+    WebpFailed,
+    #[error("too big for discord")]
+    TooBigForDiscord,
+    #[error("too big for whatsapp")]
+    TooBigForWhatsapp,
+    #[error("too big for signal")]
+    TooBigForSignal,
+    #[error("clip does not fit")]
+    ClipTooBig,
+    #[error("thumbnail does not fit")]
+    ThumbTooBig,
+    #[error("result too large to send")]
+    ResultTooBig,
+    #[error("invalid zip")]
+    BadZip,
+    #[error("too many files in zip")]
+    ZipTooManyFiles,
+    #[error("zip too large when unpacked")]
+    ZipTooBig,
+    #[error("nothing to convert in zip")]
+    ZipEmpty,
+    #[error("every file in zip failed")]
+    ZipAllFailed,
+    #[error("image too small for grid")]
+    GridTooSmall,
+    #[error("cutout unavailable")]
+    CutoutUnavailable,
+    #[error("upscaling model unavailable")]
+    UpscaleUnavailable,
+    #[error("text overlay unavailable")]
+    TextUnavailable,
+    #[error("heif unsupported")]
+    HeicUnsupported,
+    #[error("avif unsupported")]
+    AvifUnsupported,
+    #[error("jxl unsupported")]
+    JxlUnsupported,
+    #[error("raw unsupported")]
+    RawUnsupported,
+    #[error("pdf unavailable")]
+    PdfUnavailable,
+    #[error("ffmpeg missing")]
+    VideoUnavailable,
+    #[error("tgs_to_gif missing")]
+    TgsUnavailable,
+    #[error("pdftoppm failed")]
+    PdfFailed,
+    #[error("ocr unavailable")]
+    OcrUnavailable,
+    #[error("tesseract failed")]
+    OcrFailed,
+    #[error("/ocr on something other than a picture")]
+    OcrUsage,
+    #[error("explicit image")]
+    Nsfw,
+    #[error("sticker set not found")]
+    SetNotFound,
+    #[error("not a zip from /archive")]
+    BadArchive,
+    #[error("no user to own the set")]
+    NoOwner,
+    #[error("still past the end")]
+    StillPastEnd,
+    #[error("replay of a static sticker")]
+    StaticReplay,
+    #[error("video expired")]
+    VideoExpired,
+    #[error("not the user's video")]
+    NotYourVideo,
+    #[error("document expired")]
+    DocumentExpired,
+    #[error("not the user's document")]
+    NotYourDocument,
+    #[error("image expired")]
+    ImageExpired,
+    #[error("not the user's image")]
+    NotYourImage,
+    // The stderr is logged when this is made.
+    #[error("ffmpeg failed")]
+    FfmpegFailed { stderr: String },
+    #[error("child timed out after {secs} s")]
+    Timeout { secs: u64 },
+    // The same, with the input kept for /retry.
+    #[error("child timed out after {secs} s, kept for retry")]
+    RetryableTimeout { secs: u64 },
+    #[error("nothing to retry")]
+    NothingToRetry,
+    #[error("temporary files over quota")]
+    TempFull,
+    #[error("download timed out")]
+    DownloadTimeout,
+    #[error("daily quota of {limit} used up")]
+    QuotaExceeded { limit: u32 },
+    // Raised inside a worker, which only sends back what the user is told.
+    #[error("worker: {detail}")]
+    Worker {
+        msg: Msg,
+        internal: bool,
+        detail: String,
+    },
+    // The failure of a run shared with identical requests, reported by the first of them.
+    #[error("shared: {detail}")]
+    Shared { msg: Msg, detail: String },
+    #[error("worker died")]
+    WorkerCrashed,
+    #[error("telegram: {0}")]
+    TelegramError(#[from] RequestError),
+}
+
+impl BotError {
+    pub fn ffmpeg(out: &Output) -> Self {
+        let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+        error!("ffmpeg failed: {:?}\n{}", out.status, stderr.trim_end());
+        Self::FfmpegFailed { stderr }
+    }
+
+    fn id(&self) -> &'static str {
+        match self {
+            Self::BadCaption => "bad-caption",
+            Self::BadOption { id, .. } => id,
+            Self::TooLarge => "file-too-big",
+            Self::NotAnImage => "not-an-image",
+            Self::BadVideo => "bad-video",
+            Self::BadTgs => "bad-tgs",
+            Self::UnknownDuration => "unknown-duration",
+            Self::CheckUsage => "check-usage",
+            Self::PlanUsage => "plan-usage",
+            Self::StaticTooBig => "static-too-big",
+            Self::WebpFailed => "webp-failed",
+            Self::TooBigForDiscord => "too-big-discord",
+            Self::TooBigForWhatsapp => "too-big-whatsapp",
+            Self::TooBigForSignal => "too-big-signal",
+            Self::ClipTooBig => "clip-too-big",
+            Self::ThumbTooBig => "thumb-too-big",
+            Self::ResultTooBig => "result-too-big",
+            Self::BadZip => "bad-zip",
+            Self::ZipTooManyFiles => "zip-too-many-files",
+            Self::ZipTooBig => "zip-too-big",
+            Self::ZipEmpty => "zip-empty",
+            Self::ZipAllFailed => "zip-all-failed",
+            Self::GridTooSmall => "grid-too-small",
+            Self::CutoutUnavailable => "cutout-unavailable",
+            Self::UpscaleUnavailable => "upscale-unavailable",
+            Self::TextUnavailable => "text-unavailable",
+            Self::HeicUnsupported => "heic-unsupported",
+            Self::AvifUnsupported => "avif-unsupported",
+            Self::JxlUnsupported => "jxl-unsupported",
+            Self::RawUnsupported => "raw-unsupported",
+            Self::PdfUnavailable => "pdf-unavailable",
+            Self::VideoUnavailable => "video-unavailable",
+            Self::TgsUnavailable => "tgs-unavailable",
+            Self::PdfFailed => "pdf-failed",
+            Self::OcrUnavailable => "ocr-unavailable",
+            Self::OcrFailed => "ocr-failed",
+            Self::OcrUsage => "ocr-usage",
+            Self::Nsfw => "nsfw-refused",
+            Self::SetNotFound => "set-not-found",
+            Self::BadArchive => "bad-archive",
+            Self::NoOwner => "no-owner",
+            Self::StillPastEnd => "still-past-end",
+            Self::StaticReplay => "static-replay",
+            Self::VideoExpired => "video-expired",
+            Self::NotYourVideo => "not-your-video",
+            Self::DocumentExpired => "document-expired",
+            Self::NotYourDocument => "not-your-document",
+            Self::ImageExpired => "image-expired",
+            Self::NotYourImage => "not-your-image",
+            Self::FfmpegFailed { .. } => "ffmpeg-failed",
+            Self::Timeout { .. } => "timeout",
+            Self::RetryableTimeout { .. } => "timeout-retry",
+            Self::NothingToRetry => "nothing-to-retry",
+            Self::TempFull => "temp-full",
+            Self::DownloadTimeout => "download-timeout",
+            Self::QuotaExceeded { .. } => "quota-exceeded",
+            Self::Worker { .. } | Self::Shared { .. } => "error-generic",
+            Self::WorkerCrashed => "worker-crashed",
+            Self::TelegramError(_) => "send-failed",
+        }
+    }
+
+    // Failures of the bot rather than of the input.
+    pub fn is_internal(&self) -> bool {
+        match self {
+            Self::Worker { internal, .. } => *internal,
+            _ => matches!(
+                self,
+                Self::FfmpegFailed { .. }
+                    | Self::Timeout { .. }
+                    | Self::RetryableTimeout { .. }
+                    | Self::TempFull
+                    | Self::TelegramError(_)
+                    | Self::PdfFailed
+                    | Self::OcrFailed
+                    | Self::WorkerCrashed
+            ),
+        }
+    }
+
+    pub fn stderr_tail(&self) -> Option<String> {
+        let Self::FfmpegFailed { stderr } = self else {
+            return None;
+        };
+        let lines: Vec<_> = stderr.trim_end().lines().collect();
+        Some(lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n"))
+            .filter(|s| !s.trim().is_empty())
+    }
+
+    pub fn msg(&self) -> Msg {
+        match self {
+            Self::Worker { msg, .. } | Self::Shared { msg, .. } => msg.clone(),
+            Self::BadOption { id, word } => Msg::new(id).arg("word", word),
+            Self::Timeout { secs } => Msg::new(self.id()).arg("secs", secs),
+            Self::RetryableTimeout { secs } => Msg::new(self.id())
+                .arg("secs", secs)
+                .arg("minutes", stash::TTL_MINUTES)
+                .arg("factor", stash::TIMEOUT_FACTOR),
+            Self::QuotaExceeded { limit } => Msg::new(self.id())
+                .arg("limit", limit)
+                .arg("reset", quota::until_reset()),
+            _ => Msg::new(self.id()),
+        }
+    }
+
+    // Also carries what went wrong inside, for admins, or for everyone with SHOW_STDERR.
+    fn detailed_msg(&self, admin: bool) -> Msg {
+        match self {
+            Self::FfmpegFailed { .. } if admin || config::get().show_stderr => {
+                match self.stderr_tail() {
+                    Some(tail) => Msg::new("ffmpeg-failed-details").spoiler("stderr", tail),
+                    None => self.msg(),
+                }
+            }
+            Self::TelegramError(e) if admin => Msg::new("send-failed-details").arg("error", e),
+            _ => self.msg(),
+        }
+    }
+}
+
+pub fn user_message(e: &anyhow::Error, admin: bool) -> Msg {
+    match e.downcast_ref::<BotError>() {
+        Some(b) => b.detailed_msg(admin),
+        None => Msg::new("error-generic"),
+    }
+}
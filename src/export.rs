@@ -0,0 +1,237 @@
+// Stickers for other messengers. Discord takes 320x320 PNG or APNG under 512 KB. WhatsApp takes
+// 512x512 webp under 100 KB, or 500 KB when animated, imported from a .wastickers archive that
+// also carries a 96x96 tray icon. Signal takes 512x512 webp or APNG under 300 KB; its packs are
+// encrypted and uploaded by the client, so only the assets are made here.
+
+use crate::error::BotError;
+use crate::ffjob::FfmpegJob;
+use crate::options::VideoEdit;
+use crate::target::Target;
+use crate::{config, pad_square, Blob, Input, MAX_DURATION};
+use anyhow::{anyhow, bail, Result as AnyResult};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat};
+use log::{info, warn};
+use oxipng::Deflaters;
+use std::io::{Cursor, Write};
+use std::num::NonZeroU8;
+use std::path::Path;
+use webp::Encoder as WebpEncoder;
+use zip::write::{FileOptions, ZipWriter};
+
+const DISCORD_MAX_SIZE: u64 = 512 * 1000;
+const WHATSAPP_MAX_SIZE: u64 = 100 * 1000;
+const WHATSAPP_MAX_ANIMATED_SIZE: u64 = 500 * 1000;
+const SIGNAL_MAX_SIZE: u64 = 300 * 1000;
+const TRAY_SIDE: u32 = 96;
+
+// Each is tried in order until the output fits.
+const WEBP_QUALITIES: [f32; 6] = [90.0, 80.0, 70.0, 60.0, 45.0, 30.0];
+const ANIMATED_WEBP_QUALITIES: [u32; 4] = [75, 60, 45, 30];
+const APNG_FPS: [Option<u32>; 4] = [None, Some(20), Some(15), Some(10)];
+// Of PNG_ZOPFLI, past which outputs barely get smaller.
+const ZOPFLI_ITERATIONS: NonZeroU8 = match NonZeroU8::new(15) {
+    Some(n) => n,
+    None => unreachable!(),
+};
+
+// Shrunk losslessly by oxipng, as the image crate only does a quick deflate. APNG from ffmpeg is
+// left as it is.
+pub fn png(img: &DynamicImage) -> AnyResult<Vec<u8>> {
+    let mut v = Cursor::new(Vec::new());
+    img.write_to(&mut v, ImageOutputFormat::Png)?;
+    Ok(optimize_png(v.into_inner()))
+}
+
+fn optimize_png(data: Vec<u8>) -> Vec<u8> {
+    let cfg = config::get();
+    let Some(level) = cfg.png_level else {
+        return data;
+    };
+    let mut opts = oxipng::Options::from_preset(level);
+    if cfg.png_zopfli {
+        opts.deflate = Deflaters::Zopfli {
+            iterations: ZOPFLI_ITERATIONS,
+        };
+    }
+    match oxipng::optimize_from_memory(&data, &opts) {
+        Ok(v) if v.len() < data.len() => {
+            info!("oxipng: {} B to {} B", data.len(), v.len());
+            v
+        }
+        Ok(_) => data,
+        Err(e) => {
+            warn!("oxipng: {}", e);
+            data
+        }
+    }
+}
+
+// The image is already fitted and padded to the target's side.
+pub fn discord_image(img: &DynamicImage) -> AnyResult<Blob> {
+    let v = png(img)?;
+    if v.len() as u64 > DISCORD_MAX_SIZE {
+        bail!(BotError::TooBigForDiscord)
+    }
+    Ok(Blob::new(v, "png"))
+}
+
+fn webp_image(img: &DynamicImage, max_size: u64) -> AnyResult<Option<Blob>> {
+    let enc = WebpEncoder::from_image(img).map_err(|e| anyhow!("webp: {}", e))?;
+    let mem = enc.encode_lossless();
+    if mem.len() as u64 <= max_size {
+        return Ok(Some(Blob::new(mem.to_vec(), "webp")));
+    }
+    for q in WEBP_QUALITIES {
+        let mem = enc.encode(q);
+        if mem.len() as u64 <= max_size {
+            info!("webp: {} B at quality {}", mem.len(), q);
+            return Ok(Some(Blob::new(mem.to_vec(), "webp")));
+        }
+    }
+    Ok(None)
+}
+
+pub fn whatsapp_image(img: &DynamicImage) -> AnyResult<Blob> {
+    match webp_image(img, WHATSAPP_MAX_SIZE)? {
+        Some(b) => Ok(b),
+        None => bail!(BotError::TooBigForWhatsapp),
+    }
+}
+
+pub fn signal_image(img: &DynamicImage) -> AnyResult<Blob> {
+    match webp_image(img, SIGNAL_MAX_SIZE)? {
+        Some(b) => Ok(b),
+        None => bail!(BotError::TooBigForSignal),
+    }
+}
+
+pub fn tray_icon(img: &DynamicImage) -> AnyResult<Blob> {
+    let img = img.resize(TRAY_SIDE, TRAY_SIDE, FilterType::Lanczos3);
+    Ok(Blob::new(png(&pad_square(&img, TRAY_SIDE))?, "png"))
+}
+
+async fn run(
+    file: &Path,
+    edit: &VideoEdit,
+    side: u32,
+    pad: bool,
+    extra_vf: &str,
+    args: &[&str],
+    ext: &'static str,
+) -> AnyResult<Blob> {
+    FfmpegJob::new(Input::File(file), ext)
+        .edit(edit, side, pad)
+        .await?
+        .filter(extra_vf)
+        .duration(MAX_DURATION)
+        .args(args.iter().copied())
+        .run()
+        .await
+}
+
+// Lowers the frame rate until the output fits.
+async fn apng_video(file: &Path, edit: &VideoEdit, max_size: u64) -> AnyResult<Option<Blob>> {
+    let side = edit.target().side();
+    for fps in APNG_FPS {
+        let extra = fps.map_or(String::new(), |n| format!(",fps={}", n));
+        let b = run(
+            file,
+            edit,
+            side,
+            true,
+            &extra,
+            &["-plays", "0", "-f", "apng"],
+            "png",
+        )
+        .await?;
+        if b.len() <= max_size {
+            return Ok(Some(b));
+        }
+        info!("apng: {} B at {:?} fps too big", b.len(), fps);
+    }
+    Ok(None)
+}
+
+async fn whatsapp_video(file: &Path, edit: &VideoEdit) -> AnyResult<Blob> {
+    let side = Target::Whatsapp.side();
+    for q in ANIMATED_WEBP_QUALITIES {
+        let q = q.to_string();
+        let args = [
+            "-c:v",
+            "libwebp_anim",
+            "-lossless",
+            "0",
+            "-q:v",
+            q.as_str(),
+            "-loop",
+            "0",
+            "-an",
+            "-f",
+            "webp",
+        ];
+        let b = run(file, edit, side, true, "", &args, "webp").await?;
+        if b.len() <= WHATSAPP_MAX_ANIMATED_SIZE {
+            return Ok(b);
+        }
+        info!("whatsapp: {} B at quality {} too big", b.len(), q);
+    }
+    bail!(BotError::ClipTooBig)
+}
+
+// Animated webp for use outside of Telegram, only padded when asked to.
+pub async fn animated_webp(file: &Path, edit: &VideoEdit) -> AnyResult<Blob> {
+    let target = edit.target();
+    let args = [
+        "-c:v",
+        "libwebp_anim",
+        "-lossless",
+        "0",
+        "-q:v",
+        "80",
+        "-loop",
+        "0",
+        "-an",
+        "-f",
+        "webp",
+    ];
+    let pad = edit.pad || target.square();
+    run(file, edit, target.side(), pad, "", &args, "webp").await
+}
+
+pub async fn encode_video(file: &Path, edit: &VideoEdit) -> AnyResult<Blob> {
+    let r = match edit.target() {
+        Target::Discord => apng_video(file, edit, DISCORD_MAX_SIZE).await?,
+        Target::Signal => apng_video(file, edit, SIGNAL_MAX_SIZE).await?,
+        _ => return whatsapp_video(file, edit).await,
+    };
+    match r {
+        Some(b) => Ok(b),
+        None => bail!(BotError::ClipTooBig),
+    }
+}
+
+// Taken from the first frame, since ffmpeg cannot decode animated webp back.
+pub async fn video_tray_icon(file: &Path, edit: &VideoEdit) -> AnyResult<Blob> {
+    let args = ["-frames:v", "1", "-c:v", "png", "-f", "image2"];
+    run(file, edit, TRAY_SIDE, true, "", &args, "png").await
+}
+
+// WhatsApp only imports packs of at least three stickers, so this is meant to be merged with
+// others by the user.
+pub async fn wastickers(sticker: &Blob, tray: &Blob, title: &str, author: &str) -> AnyResult<Blob> {
+    let sticker = sticker.bytes().await?;
+    let tray = tray.bytes().await?;
+    let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+    let entries: [(&str, &[u8]); 4] = [
+        ("title.txt", title.as_bytes()),
+        ("author.txt", author.as_bytes()),
+        ("tray.png", &tray[..]),
+        ("1.webp", &sticker[..]),
+    ];
+    for (name, data) in entries {
+        w.start_file(name, FileOptions::default())?;
+        w.write_all(data)?;
+    }
+    Ok(Blob::new(w.finish()?.into_inner(), "wastickers"))
+}
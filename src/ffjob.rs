@@ -0,0 +1,187 @@
+// One ffmpeg run put together from its pieces: what it reads, how much of it, the filters over it,
+// the encoder and what it writes. Modes such as an fps cap, padding, speed or hardware encoding
+// each add a piece to the runs that want them, rather than another full command line.
+
+use crate::error::BotError;
+use crate::hwaccel::HwAccel;
+use crate::options::VideoEdit;
+use crate::target::{self, Target};
+use crate::{
+    config, ffmpeg, probe, stash, temp_file, temp_path, text, wait_output, wait_output_fed,
+    watermark, Blob, Input,
+};
+use anyhow::{bail, Result as AnyResult};
+use std::time::Duration;
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+
+// What is found out about the output.
+enum Report {
+    None,
+    Probe,
+    Check(Target),
+}
+
+pub struct FfmpegJob<'a> {
+    input: Input<'a>,
+    input_args: Vec<String>,
+    hw: Option<HwAccel>,
+    duration: Option<f64>,
+    filters: Vec<String>,
+    // Read by the filters, so kept until ffmpeg is done.
+    files: Vec<TempPath>,
+    // VP9 by the hardware encoder if any, or else by libvpx, lossless or not.
+    vp9: Option<bool>,
+    output_args: Vec<String>,
+    ext: &'static str,
+    report: Report,
+    timeout: Duration,
+}
+
+impl<'a> FfmpegJob<'a> {
+    pub fn new(input: Input<'a>, ext: &'static str) -> Self {
+        Self {
+            input,
+            input_args: vec![],
+            hw: None,
+            duration: None,
+            filters: vec![],
+            files: vec![],
+            vp9: None,
+            output_args: vec![],
+            ext,
+            report: Report::None,
+            timeout: config::get().ffmpeg_timeout,
+        }
+    }
+
+    // Options placed before the input.
+    pub fn input_args<I: IntoIterator<Item = S>, S: Into<String>>(mut self, args: I) -> Self {
+        self.input_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn hw(mut self, hw: Option<HwAccel>) -> Self {
+        self.hw = hw;
+        self
+    }
+
+    // Of the output, after any speed change.
+    pub fn duration(mut self, secs: f64) -> Self {
+        self.duration = Some(secs);
+        self
+    }
+
+    // Pieces may keep the commas they were joined to others with.
+    pub fn filter(mut self, f: &str) -> Self {
+        let f = f.trim_matches(',');
+        if !f.is_empty() {
+            self.filters.push(f.to_owned());
+        }
+        self
+    }
+
+    // The edits, then fitting into a square of `side`, padded to it when asked, with the
+    // background, text and watermark over that.
+    pub async fn edit(mut self, edit: &VideoEdit, side: u32, pad: bool) -> AnyResult<Self> {
+        if edit.retry {
+            self.timeout *= stash::TIMEOUT_FACTOR;
+        }
+        let mut job = self
+            .input_args(edit.input_args())
+            .filter(&edit.filters())
+            .filter(&edit.scale_filter(side));
+        if pad {
+            job = job.filter(&target::pad_filter(side));
+        }
+        job = job.filter(&edit.background_filter());
+        if let Some(o) = &edit.text {
+            let (path, mut f) = temp_file().await?;
+            f.write_all(o.text.as_bytes()).await?;
+            drop(f);
+            job = job.filter(&text::drawtext_filter(o, &path.to_string_lossy(), side)?);
+            job.files.push(path);
+        }
+        if let Some(w) = &edit.watermark {
+            let (filter, path) = watermark::filter(w, side).await?;
+            job = job.filter(&filter);
+            job.files.push(path);
+        }
+        Ok(job)
+    }
+
+    pub fn vp9(mut self, lossless: bool) -> Self {
+        self.vp9 = Some(lossless);
+        self
+    }
+
+    // Output options, such as the codec and the container.
+    pub fn args<I: IntoIterator<Item = S>, S: Into<String>>(mut self, args: I) -> Self {
+        self.output_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn probe(mut self) -> Self {
+        self.report = Report::Probe;
+        self
+    }
+
+    // Probed and checked against the requirements of `target`.
+    pub fn check(mut self, target: Target) -> Self {
+        self.report = Report::Check(target);
+        self
+    }
+
+    pub async fn run(mut self) -> AnyResult<Blob> {
+        let out_path = temp_path()?;
+        let mut cmd = ffmpeg();
+        cmd.args(["-hide_banner", "-y"]);
+        if let Some(hw) = self.hw {
+            hw.input_args(&mut cmd);
+        }
+        cmd.args(&self.input_args).arg("-i");
+        match &self.input {
+            Input::File(path) => cmd.arg(path),
+            Input::Pipe(_) => cmd.arg("pipe:0"),
+        };
+        if let Some(d) = self.duration {
+            cmd.arg("-t").arg(d.to_string());
+        }
+        if let Some(hw) = self.hw {
+            self = self.filter(hw.filter_suffix());
+        }
+        if !self.filters.is_empty() {
+            cmd.arg("-vf").arg(self.filters.join(","));
+        }
+        if let Some(lossless) = self.vp9 {
+            if !self
+                .hw
+                .map_or(false, |hw| hw.encoder_args(&mut cmd, lossless))
+            {
+                cmd.args(["-c:v", "libvpx-vp9"])
+                    .args(&config::get().vp9_args);
+                if lossless {
+                    cmd.args(["-lossless", "1"]);
+                }
+            }
+        }
+        // Keeps the location and such of the source out of the result.
+        cmd.args(["-map_metadata", "-1"])
+            .args(&self.output_args)
+            .arg(&out_path);
+        let out = match self.input {
+            Input::File(_) => wait_output(&mut cmd, self.timeout).await?,
+            Input::Pipe(rx) => wait_output_fed(&mut cmd, rx, self.timeout).await?,
+        };
+        drop(self.files);
+        if !out.status.success() {
+            bail!(BotError::ffmpeg(&out))
+        }
+        let info = match self.report {
+            Report::None => None,
+            Report::Probe => probe::probe(&out_path).await,
+            Report::Check(target) => probe::check_output(&out_path, target).await,
+        };
+        Ok(Blob::from_temp(out_path, self.ext).await?.with_info(info))
+    }
+}
@@ -0,0 +1,182 @@
+// Modern phone and camera image formats that the image crate cannot decode by itself. Each
+// decoder is behind its own feature, as they pull in native libraries or heavy crates.
+
+use anyhow::{bail, Result as AnyResult};
+use image::DynamicImage;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Heif,
+    Avif,
+    Jxl,
+    Raw,
+}
+
+const HEIF_BRANDS: &[&[u8]] = &[
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+const JXL_CONTAINER: &[u8] = b"\0\0\0\x0cJXL \r\n\x87\n";
+// Longest side of decoded RAW photos, which are far larger than any sticker.
+#[cfg(feature = "raw")]
+const RAW_MAX_SIDE: usize = 2048;
+
+pub fn sniff(data: &[u8]) -> Option<Kind> {
+    if data.starts_with(&[0xff, 0x0a]) || data.starts_with(JXL_CONTAINER) {
+        return Some(Kind::Jxl);
+    }
+    // Canon CR2 has its own mark after the TIFF header.
+    if data.len() >= 10 && data.starts_with(b"II*\0") && &data[8..10] == b"CR" {
+        return Some(Kind::Raw);
+    }
+    // Most other RAW formats are plain TIFF on the outside, so they are only told apart by
+    // trying, which is done when the decoder is there.
+    #[cfg(feature = "raw")]
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") || data.starts_with(b"FUJIFILM") {
+        return Some(Kind::Raw);
+    }
+    // ISO base media files start with an ftyp box naming the major brand.
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        let brand = &data[8..12];
+        if brand == b"avif" || brand == b"avis" {
+            return Some(Kind::Avif);
+        }
+        if HEIF_BRANDS.contains(&brand) {
+            return Some(Kind::Heif);
+        }
+    }
+    None
+}
+
+// The EXIF orientation of a JPEG, PNG, TIFF, HEIF or webp file, 1 meaning upright.
+pub fn orientation(data: &[u8]) -> u32 {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return 1;
+    };
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .unwrap_or(1)
+}
+
+pub fn orient(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+pub fn decode(kind: Kind, data: &[u8]) -> AnyResult<DynamicImage> {
+    match kind {
+        Kind::Heif => decode_heif(data),
+        Kind::Avif => decode_avif(data),
+        Kind::Jxl => decode_jxl(data),
+        Kind::Raw => decode_raw(data),
+    }
+}
+
+#[cfg(feature = "heif")]
+fn decode_heif(data: &[u8]) -> AnyResult<DynamicImage> {
+    use image::RgbaImage;
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let ctx = HeifContext::read_from_bytes(data)?;
+    let handle = ctx.primary_image_handle()?;
+    let img = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+    let planes = img.planes();
+    let Some(p) = planes.interleaved else {
+        bail!("heif: no interleaved plane")
+    };
+    let (w, h) = (p.width, p.height);
+    let row = w as usize * 4;
+    let mut buf = Vec::with_capacity(row * h as usize);
+    for y in 0..h as usize {
+        buf.extend_from_slice(&p.data[y * p.stride..y * p.stride + row]);
+    }
+    Ok(DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(w, h, buf).unwrap(),
+    ))
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_heif(_data: &[u8]) -> AnyResult<DynamicImage> {
+    bail!(crate::error::BotError::HeicUnsupported)
+}
+
+// The image crate decodes these itself with its avif-decoder feature.
+fn decode_avif(data: &[u8]) -> AnyResult<DynamicImage> {
+    #[cfg(feature = "avif")]
+    return Ok(image::load_from_memory_with_format(
+        data,
+        image::ImageFormat::Avif,
+    )?);
+    #[cfg(not(feature = "avif"))]
+    {
+        let _ = data;
+        bail!(crate::error::BotError::AvifUnsupported)
+    }
+}
+
+#[cfg(feature = "jxl")]
+fn decode_jxl(data: &[u8]) -> AnyResult<DynamicImage> {
+    use image::{Rgba32FImage, RgbaImage};
+    use jxl_oxide::JxlImage;
+
+    let image = JxlImage::builder().read(data)?;
+    let render = image.render_frame(0)?;
+    let fb = render.image_all_channels();
+    let (w, h, channels) = (fb.width() as u32, fb.height() as u32, fb.channels());
+    let mut out = Rgba32FImage::new(w, h);
+    for (px, src) in out.pixels_mut().zip(fb.buf().chunks_exact(channels)) {
+        px.0 = match *src {
+            [l] => [l, l, l, 1.0],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 1.0],
+            [r, g, b, a, ..] => [r, g, b, a],
+            [] => [0.0; 4],
+        };
+    }
+    let img: RgbaImage = DynamicImage::ImageRgba32F(out).into_rgba8();
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+#[cfg(not(feature = "jxl"))]
+fn decode_jxl(_data: &[u8]) -> AnyResult<DynamicImage> {
+    bail!(crate::error::BotError::JxlUnsupported)
+}
+
+#[cfg(feature = "raw")]
+fn decode_raw(data: &[u8]) -> AnyResult<DynamicImage> {
+    use anyhow::anyhow;
+    use image::RgbImage;
+    use imagepipe::{ImageSource, Pipeline};
+    use log::info;
+
+    let raw = match rawloader::decode(&mut Cursor::new(data)) {
+        Ok(raw) => raw,
+        Err(e) => {
+            // Plain TIFF images end up here too.
+            info!("raw: {}, trying as tiff", e);
+            return Ok(image::load_from_memory(data)?);
+        }
+    };
+    info!("raw: {} {}", raw.make, raw.model);
+    let mut pipe =
+        Pipeline::new_from_source(ImageSource::Raw(raw)).map_err(|e| anyhow!("raw: {}", e))?;
+    pipe.globals.settings.maxwidth = RAW_MAX_SIDE;
+    pipe.globals.settings.maxheight = RAW_MAX_SIDE;
+    let out = pipe.output_8bit(None).map_err(|e| anyhow!("raw: {}", e))?;
+    let img = RgbImage::from_raw(out.width as u32, out.height as u32, out.data)
+        .ok_or_else(|| anyhow!("raw: bad output size"))?;
+    Ok(DynamicImage::ImageRgb8(img))
+}
+
+#[cfg(not(feature = "raw"))]
+fn decode_raw(_data: &[u8]) -> AnyResult<DynamicImage> {
+    bail!(crate::error::BotError::RawUnsupported)
+}
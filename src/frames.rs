@@ -0,0 +1,64 @@
+// Still frames taken from a video at evenly spaced moments, for /frames.
+
+use crate::error::BotError;
+use crate::probe::VideoInfo;
+use crate::{config, ffmpeg, temp_path, wait_output, Blob};
+use anyhow::{bail, Result as AnyResult};
+use image::DynamicImage;
+use std::path::Path;
+use tempfile::TempPath;
+
+pub const DEFAULT_COUNT: u32 = 4;
+pub const MAX_COUNT: u32 = 10;
+
+// A PNG of the frame at `t`. The libvpx decoder keeps the alpha channel of webm stickers, which
+// the native one drops.
+async fn render_frame(file: &Path, t: f64, vp9: bool) -> AnyResult<TempPath> {
+    let out_path = temp_path()?;
+    let mut cmd = ffmpeg();
+    cmd.args(["-hide_banner", "-y", "-ss"])
+        .arg(format!("{:.3}", t));
+    if vp9 {
+        cmd.args(["-c:v", "libvpx-vp9"]);
+    }
+    let out = wait_output(
+        cmd.arg("-i")
+            .arg(file)
+            .args(["-frames:v", "1", "-c:v", "png", "-f", "image2"])
+            .arg(&out_path),
+        config::get().ffmpeg_timeout,
+    )
+    .await?;
+    if !out.status.success() {
+        bail!(BotError::ffmpeg(&out))
+    }
+    // Nothing is written when `t` is past the end.
+    if tokio::fs::metadata(&out_path).await?.len() == 0 {
+        bail!(BotError::StillPastEnd)
+    }
+    Ok(out_path)
+}
+
+async fn frame_at(file: &Path, t: f64) -> AnyResult<DynamicImage> {
+    let path = render_frame(file, t, false).await?;
+    Ok(image::load_from_memory(&tokio::fs::read(&path).await?)?)
+}
+
+// For /still, transparent where the sticker is.
+pub async fn still(file: &Path, t: f64, vp9: bool) -> AnyResult<Blob> {
+    let path = render_frame(file, t, vp9).await?;
+    Ok(Blob::from_temp(path, "png").await?)
+}
+
+// Frames are taken from the middle of each of `n` equal parts, which avoids the fade-ins and
+// black frames often found at the very ends.
+pub async fn extract(file: &Path, info: &VideoInfo, n: u32) -> AnyResult<Vec<DynamicImage>> {
+    let Some(d) = info.duration.filter(|&d| d > 0.0) else {
+        bail!(BotError::UnknownDuration)
+    };
+    let mut frames = Vec::with_capacity(n as usize);
+    for i in 0..n {
+        frames.push(frame_at(file, d * (i as f64 + 0.5) / n as f64).await?);
+    }
+    Ok(frames)
+}
@@ -0,0 +1,44 @@
+// Sprite sheets and collages cut into a grid given by "grid=<rows>x<cols>", each cell becoming a
+// sticker of its own.
+
+use crate::batch::Archive;
+use crate::error::BotError;
+use crate::options::VideoEdit;
+use crate::{process_decoded, Blob};
+use anyhow::{bail, Result as AnyResult};
+use image::{DynamicImage, GenericImageView};
+
+pub const MAX_CELLS: u32 = 64;
+
+// Cells are numbered row by row. Leftover pixels from uneven division go to the last row and
+// column.
+pub fn split(img: &DynamicImage, rows: u32, cols: u32) -> Vec<DynamicImage> {
+    let (w, h) = img.dimensions();
+    let (cw, ch) = (w / cols, h / rows);
+    let mut cells = Vec::with_capacity((rows * cols) as usize);
+    for r in 0..rows {
+        for c in 0..cols {
+            let cell_w = if c == cols - 1 { w - c * cw } else { cw };
+            let cell_h = if r == rows - 1 { h - r * ch } else { ch };
+            cells.push(img.crop_imm(c * cw, r * ch, cell_w, cell_h));
+        }
+    }
+    cells
+}
+
+pub async fn convert(
+    img: DynamicImage,
+    (rows, cols): (u32, u32),
+    edit: &VideoEdit,
+) -> AnyResult<Blob> {
+    let (w, h) = img.dimensions();
+    if w < cols || h < rows {
+        bail!(BotError::GridTooSmall)
+    }
+    let mut zip = Archive::new();
+    for (i, cell) in split(&img, rows, cols).into_iter().enumerate() {
+        let b = process_decoded(cell, edit).await?;
+        zip.add(&format!("{:02}", i + 1), &b).await?;
+    }
+    zip.finish()
+}
@@ -0,0 +1,225 @@
+// The interactive /help: an overview with a button per topic, each of which opens its help-*
+// message in place, with a button to try it on a generated sample image where that makes sense.
+// Errors that have a topic come with a button leading to it.
+
+use crate::i18n::Msg;
+use crate::options::VideoEdit;
+use crate::{worker, AppBot};
+use anyhow::Result as AnyResult;
+use image::{DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile};
+
+const PREFIX: &str = "help";
+const DEMO: &str = "demo";
+const BUTTONS_PER_ROW: usize = 4;
+
+struct Topic {
+    key: &'static str,
+    msg: &'static str,
+    // The caption the sample is converted with.
+    demo: Option<&'static str>,
+}
+
+const fn entry(key: &'static str, msg: &'static str, demo: Option<&'static str>) -> Topic {
+    Topic { key, msg, demo }
+}
+
+const TOPICS: &[Topic] = &[
+    entry("edits", "help-edits", Some("rotate=90 text: Hello")),
+    entry("convert", "help-convert", None),
+    entry("pad", "help-pad", Some("/pad")),
+    entry("check", "help-check", None),
+    entry("plan", "help-plan", None),
+    entry("bg", "help-bg", Some("bg=#ff8800")),
+    entry("smartcrop", "help-smartcrop", Some("/smartcrop")),
+    entry("grid", "help-grid", None),
+    entry("pixel", "help-pixel", Some("pixel=32 colors=8")),
+    entry("filter", "help-filter", Some("filter=vhs")),
+    entry("frames", "help-frames", None),
+    entry("emoji", "help-emoji", Some("/emoji")),
+    entry("discord", "help-discord", Some("/discord")),
+    entry("whatsapp", "help-whatsapp", None),
+    entry("signal", "help-signal", None),
+    entry("webp", "help-webp", None),
+    entry("thumb", "help-thumb", Some("/thumb")),
+    entry("cutout", "help-cutout", None),
+    entry("upscale", "help-upscale", None),
+    entry("ocr", "help-ocr", None),
+    entry("still", "help-still", None),
+    entry("speed", "help-speed", None),
+    entry("reverse", "help-reverse", None),
+    entry("sets", "help-sets", None),
+    entry("settings", "help-settings", None),
+];
+
+// Replies that point at a topic, such as the usage of a command.
+const CONTEXT: &[(&str, &str)] = &[
+    ("bad-caption", "edits"),
+    ("bad-option", "edits"),
+    ("bad-trim", "edits"),
+    ("bad-crop", "edits"),
+    ("bad-speed", "speed"),
+    ("static-replay", "speed"),
+    ("bad-fps", "edits"),
+    ("bad-rotate", "edits"),
+    ("bad-pos", "edits"),
+    ("bad-quality", "settings"),
+    ("bad-bg", "bg"),
+    ("bad-grid", "grid"),
+    ("bad-frames", "frames"),
+    ("bad-pixel", "pixel"),
+    ("bad-filter", "filter"),
+    ("bad-colors", "pixel"),
+    ("check-usage", "check"),
+    ("plan-usage", "plan"),
+    ("grid-too-small", "grid"),
+    ("cutout-unavailable", "cutout"),
+    ("upscale-unavailable", "upscale"),
+    ("ocr-unavailable", "ocr"),
+    ("ocr-usage", "ocr"),
+    ("static-too-big", "pad"),
+    ("send-media", "edits"),
+    ("usage-target", "settings"),
+    ("usage-quality", "settings"),
+    ("usage-mp4", "settings"),
+    ("usage-upscale", "upscale"),
+    ("usage-lang", "settings"),
+    ("usage-preset", "settings"),
+    ("usage-chatsettings", "settings"),
+    ("forgetme-confirm", "settings"),
+    ("usage-archive", "sets"),
+    ("usage-clone", "sets"),
+    ("bad-archive", "sets"),
+    ("set-not-found", "sets"),
+    ("still-past-end", "still"),
+];
+
+pub enum Action {
+    Overview,
+    Open(&'static str),
+    Demo(&'static str),
+}
+
+fn find(key: &str) -> Option<&'static Topic> {
+    TOPICS.iter().find(|t| t.key == key)
+}
+
+pub fn parse_callback_data(data: &str) -> Option<Action> {
+    let mut it = data.split(':');
+    if it.next()? != PREFIX {
+        return None;
+    }
+    let Some(key) = it.next() else {
+        return Some(Action::Overview);
+    };
+    let t = find(key)?;
+    Some(match it.next() {
+        Some(DEMO) => Action::Demo(t.key),
+        _ => Action::Open(t.key),
+    })
+}
+
+pub fn overview_keyboard() -> InlineKeyboardMarkup {
+    let buttons: Vec<_> = TOPICS
+        .iter()
+        .map(|t| InlineKeyboardButton::callback(t.key, format!("{}:{}", PREFIX, t.key)))
+        .collect();
+    InlineKeyboardMarkup::new(buttons.chunks(BUTTONS_PER_ROW).map(|c| c.to_vec()))
+}
+
+// The message of a topic, with a button to try it and one back to the overview.
+pub fn topic(key: &str, lang: &str) -> Option<(Msg, InlineKeyboardMarkup)> {
+    let t = find(key)?;
+    let mut row = vec![];
+    if t.demo.is_some() {
+        row.push(InlineKeyboardButton::callback(
+            Msg::new("help-try").tr(lang),
+            format!("{}:{}:{}", PREFIX, t.key, DEMO),
+        ));
+    }
+    row.push(InlineKeyboardButton::callback(
+        Msg::new("help-back").tr(lang),
+        PREFIX,
+    ));
+    Some((Msg::new(t.msg), InlineKeyboardMarkup::new([row])))
+}
+
+// A button to the topic of the reply, if it has one.
+pub fn context_keyboard(m: &Msg, lang: &str) -> Option<InlineKeyboardMarkup> {
+    let &(_, key) = CONTEXT.iter().find(|(id, _)| *id == m.id())?;
+    Some(InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            Msg::new("help-more").tr(lang),
+            format!("{}:{}", PREFIX, key),
+        ),
+    ]]))
+}
+
+// A transparent margin around a gradient with a bright spot off the center, so that padding,
+// backgrounds and cropping all show.
+fn sample() -> AnyResult<Vec<u8>> {
+    let img = RgbaImage::from_fn(384, 256, |x, y| {
+        if x < 24 || y < 24 || x >= 360 || y >= 232 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let (dx, dy) = (x as i32 - 260, y as i32 - 100);
+        if dx * dx + dy * dy < 40 * 40 {
+            return Rgba([255, 220, 0, 255]);
+        }
+        Rgba([(x * 2 / 3) as u8, (y * 3 / 4) as u8, 160, 255])
+    });
+    let mut v = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(img).write_to(&mut v, ImageOutputFormat::Png)?;
+    Ok(v.into_inner())
+}
+
+// Sends the sample with the caption of the topic, then what it converts to.
+pub async fn demo(bot: &AppBot, chat: ChatId, key: &str, lang: &str) -> AnyResult<()> {
+    let Some(caption) = find(key).and_then(|t| t.demo) else {
+        return Ok(());
+    };
+    let data = sample()?;
+    let edit = VideoEdit::parse(caption)?;
+    let b = worker::process_image(data.clone(), &edit).await?;
+    let text = Msg::new("help-demo").arg("caption", caption).tr(lang);
+    let sent = bot
+        .send_photo(chat, InputFile::memory(data).file_name("sample.png"))
+        .caption(text)
+        .await?;
+    let reply_to = sent.id;
+    if b.ext == "webp" {
+        bot.send_sticker(chat, b.input_file(None))
+            .reply_to_message_id(reply_to)
+            .await?;
+    } else {
+        bot.send_document(chat, b.input_file(Some(key)))
+            .reply_to_message_id(reply_to)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_data() {
+        assert!(matches!(
+            parse_callback_data("help"),
+            Some(Action::Overview)
+        ));
+        assert!(matches!(
+            parse_callback_data("help:grid"),
+            Some(Action::Open("grid"))
+        ));
+        assert!(matches!(
+            parse_callback_data("help:pad:demo"),
+            Some(Action::Demo("pad"))
+        ));
+        assert!(parse_callback_data("help:nope").is_none());
+        assert!(parse_callback_data("hist:h:p:0").is_none());
+    }
+}
@@ -0,0 +1,506 @@
+// The last results of each user, so that /history can send them again without scrolling back to
+// them, and those saved with /fav, listed by /favs as a small sticker library of their own. Each
+// list is kept in a json file by user with the file ids of the results, which only work with the
+// bot that sent them, along with what they were made from and when. Results are found again by
+// their tags with /find or in inline mode, those of stickers starting with their emoji, and more
+// given with /tag, which also saves the result to the favorites.
+
+use crate::access::Actor;
+use crate::duplicates;
+use crate::i18n::Msg;
+use crate::store::JsonStore;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+const PAGE_SIZE: usize = 5;
+// Shown by /find, with a button each.
+pub const FIND_LIMIT: usize = 10;
+// The most Telegram takes in an answer to an inline query.
+pub const INLINE_LIMIT: usize = 50;
+const PREFIX: &str = "hist";
+pub const MAX_TAGS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum List {
+    History,
+    Favorites,
+}
+
+impl List {
+    fn file(self) -> &'static str {
+        match self {
+            Self::History => "history.json",
+            Self::Favorites => "favorites.json",
+        }
+    }
+
+    // Of each user. Old results make way for new ones, while favorites have to be removed.
+    pub fn max_entries(self) -> usize {
+        match self {
+            Self::History => 50,
+            Self::Favorites => 100,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Self::History => "h",
+            Self::Favorites => "f",
+        }
+    }
+
+    pub fn empty(self) -> &'static str {
+        match self {
+            Self::History => "history-empty",
+            Self::Favorites => "favs-empty",
+        }
+    }
+
+    fn msgs(self) -> (&'static str, &'static str) {
+        match self {
+            Self::History => ("history", "history-entry"),
+            Self::Favorites => ("favs", "favs-entry"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    bot: u64,
+    // Increasing for each user, so that buttons keep pointing at the same entry.
+    id: u64,
+    time: u64,
+    pub input: String,
+    output: String,
+    pub file_id: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Of what a static result shows, for those of plain conversions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u64>,
+}
+
+impl Entry {
+    // Such as "3 h", since it was made.
+    pub fn age(&self) -> String {
+        age(now().saturating_sub(self.time))
+    }
+
+    // Numbered and dated once pushed.
+    fn new(bot: u64, input: &str, output: &str, file_id: String, tags: Vec<String>) -> Self {
+        Self {
+            bot,
+            id: 0,
+            time: 0,
+            input: input.to_owned(),
+            output: output.to_owned(),
+            file_id,
+            tags,
+            hash: None,
+        }
+    }
+
+    // What it is called in lists and inline results.
+    pub fn title(&self) -> String {
+        match self.tags.is_empty() {
+            true => self.output.clone(),
+            false => format!("{} {}", self.output, self.tags.join(" ")),
+        }
+    }
+
+    // Each word starts a tag or the format, so that results show up while it is still typed.
+    // Emoji may come without spaces between them, and one of them is then enough.
+    fn matches(&self, words: &[String]) -> bool {
+        let tags: Vec<&str> = self
+            .tags
+            .iter()
+            .chain([&self.output, &self.input])
+            .map(String::as_str)
+            .filter(|t| !t.is_empty())
+            .collect();
+        words.iter().all(|w| {
+            let emoji = !w.chars().any(char::is_alphanumeric);
+            tags.iter()
+                .any(|t| t.starts_with(w.as_str()) || emoji && w.contains(t))
+        })
+    }
+
+    pub fn key(&self, list: List) -> String {
+        format!("{}{}", list.code(), self.id)
+    }
+}
+
+pub enum Action {
+    Page(usize),
+    Resend(u64),
+    // Only of favorites.
+    Remove(u64),
+}
+
+type Store = JsonStore<HashMap<i64, VecDeque<Entry>>>;
+
+static HISTORY: OnceLock<Store> = OnceLock::new();
+static FAVORITES: OnceLock<Store> = OnceLock::new();
+
+fn store(list: List) -> &'static Store {
+    let cell = match list {
+        List::History => &HISTORY,
+        List::Favorites => &FAVORITES,
+    };
+    cell.get_or_init(|| JsonStore::load(list.file()))
+}
+
+pub fn init() {
+    store(List::History);
+    store(List::Favorites);
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn record(
+    bot: u64,
+    actor: Actor,
+    input: &str,
+    output: &str,
+    file_id: String,
+    tags: Vec<String>,
+    hash: Option<u64>,
+) {
+    let mut e = Entry::new(bot, input, output, file_id, tags);
+    e.hash = hash;
+    push(List::History, actor, e);
+}
+
+// Tags are matched in lowercase, as typed.
+pub fn parse_tags<'a>(words: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut v: Vec<String> = vec![];
+    for w in words.map(str::to_lowercase) {
+        if v.len() == MAX_TAGS {
+            break;
+        }
+        if !v.contains(&w) {
+            v.push(w);
+        }
+    }
+    v
+}
+
+// Whether it was saved, which it is not when the favorites are full. One already saved is only
+// moved to the top with the tags added to its own, which are first those it has in the history.
+pub fn fav(bot: u64, actor: Actor, output: &str, file_id: String, tags: Vec<String>) -> bool {
+    let mut all = find_file(List::History, actor, &file_id).map_or(vec![], |e| e.tags);
+    let list = List::Favorites;
+    let s = store(list);
+    let mut users = s.lock();
+    let entries = users.entry(actor.key()).or_default();
+    let saved = entries.iter().position(|e| e.file_id == file_id);
+    if saved.is_none() && entries.len() >= list.max_entries() {
+        return false;
+    }
+    if let Some(e) = saved.and_then(|i| entries.remove(i)) {
+        all = e.tags;
+    }
+    drop(users);
+    for t in tags {
+        if !all.contains(&t) {
+            all.push(t);
+        }
+    }
+    all.truncate(MAX_TAGS);
+    push(list, actor, Entry::new(bot, "", output, file_id, all));
+    true
+}
+
+fn find_file(list: List, actor: Actor, file_id: &str) -> Option<Entry> {
+    store(list)
+        .lock()
+        .get(&actor.key())?
+        .iter()
+        .rfind(|e| e.file_id == file_id)
+        .cloned()
+}
+
+// Of both lists, favorites first and newest first, each result once, past the first `skip` of
+// them as inline queries are paged.
+pub fn search(
+    bot: u64,
+    actor: Actor,
+    words: &[String],
+    skip: usize,
+    limit: usize,
+) -> Vec<(List, Entry)> {
+    let mut v: Vec<(List, Entry)> = vec![];
+    for list in [List::Favorites, List::History] {
+        let users = store(list).lock();
+        let Some(entries) = users.get(&actor.key()) else {
+            continue;
+        };
+        for e in entries.iter().rev() {
+            if v.len() >= skip + limit {
+                break;
+            }
+            let seen = v.iter().any(|(_, f)| f.file_id == e.file_id);
+            if e.bot == bot && !seen && e.matches(words) {
+                v.push((list, e.clone()));
+            }
+        }
+    }
+    v.split_off(skip.min(v.len()))
+}
+
+fn push(list: List, actor: Actor, mut e: Entry) {
+    let s = store(list);
+    let mut users = s.lock();
+    let entries = users.entry(actor.key()).or_default();
+    if entries.len() >= list.max_entries() {
+        entries.pop_front();
+    }
+    e.id = entries.back().map_or(0, |e| e.id + 1);
+    e.time = now();
+    entries.push_back(e);
+    drop(users);
+    s.save_later();
+}
+
+// Of every bot, for /export.
+pub fn entries(list: List, actor: Actor) -> Vec<Entry> {
+    store(list)
+        .lock()
+        .get(&actor.key())
+        .map_or(vec![], |v| v.iter().cloned().collect())
+}
+
+pub fn forget(actor: Actor) -> io::Result<()> {
+    for list in [List::History, List::Favorites] {
+        let s = store(list);
+        let mut users = s.lock();
+        if users.remove(&actor.key()).is_some() {
+            s.save(&users)?;
+        }
+    }
+    Ok(())
+}
+
+// The newest result of the history showing much the same as `hash`.
+pub fn similar(bot: u64, actor: Actor, hash: u64) -> Option<Entry> {
+    store(List::History)
+        .lock()
+        .get(&actor.key())?
+        .iter()
+        .rev()
+        .find(|e| {
+            e.bot == bot
+                && e.hash.map_or(false, |h| {
+                    duplicates::distance(h, hash) <= duplicates::MAX_DISTANCE
+                })
+        })
+        .cloned()
+}
+
+pub fn find(list: List, bot: u64, actor: Actor, id: u64) -> Option<Entry> {
+    store(list)
+        .lock()
+        .get(&actor.key())?
+        .iter()
+        .find(|e| e.bot == bot && e.id == id)
+        .cloned()
+}
+
+pub fn unfav(bot: u64, actor: Actor, id: u64) -> bool {
+    let s = store(List::Favorites);
+    let mut users = s.lock();
+    let Some(entries) = users.get_mut(&actor.key()) else {
+        return false;
+    };
+    let n = entries.len();
+    entries.retain(|e| e.bot != bot || e.id != id);
+    if entries.len() == n {
+        return false;
+    }
+    drop(users);
+    s.save_later();
+    true
+}
+
+pub fn callback_data(list: List, action: &Action) -> String {
+    let (code, n) = match *action {
+        Action::Page(page) => ("p", page as u64),
+        Action::Resend(id) => ("r", id),
+        Action::Remove(id) => ("d", id),
+    };
+    format!("{}:{}:{}:{}", PREFIX, list.code(), code, n)
+}
+
+pub fn parse_callback_data(data: &str) -> Option<(List, Action)> {
+    let mut it = data.split(':');
+    if it.next()? != PREFIX {
+        return None;
+    }
+    let list = match it.next()? {
+        "h" => List::History,
+        "f" => List::Favorites,
+        _ => return None,
+    };
+    let code = it.next()?;
+    let n: u64 = it.next()?.parse().ok()?;
+    let action = match code {
+        "p" => Action::Page(n as usize),
+        "r" => Action::Resend(n),
+        "d" if list == List::Favorites => Action::Remove(n),
+        _ => return None,
+    };
+    Some((list, action))
+}
+
+fn age(secs: u64) -> String {
+    match secs {
+        s if s < 3600 => format!("{} min", (s / 60).max(1)),
+        s if s < 86400 => format!("{} h", s / 3600),
+        s => format!("{} d", s / 86400),
+    }
+}
+
+// A page of the list, newest first, with a button to send each again, for favorites one to
+// remove each too, and ones to turn pages. None when there is nothing to list.
+pub fn render(
+    list: List,
+    bot: u64,
+    actor: Actor,
+    page: usize,
+    lang: &str,
+) -> Option<(String, InlineKeyboardMarkup)> {
+    let (header, line) = list.msgs();
+    let users = store(list).lock();
+    let entries: Vec<_> = users
+        .get(&actor.key())?
+        .iter()
+        .rev()
+        .filter(|e| e.bot == bot)
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+    let pages = (entries.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let page = page.min(pages - 1);
+    let shown = entries
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE);
+    let now = now();
+    let mut lines = vec![Msg::new(header)
+        .arg("page", page + 1)
+        .arg("pages", pages)
+        .tr(lang)];
+    let (mut resend, mut remove) = (vec![], vec![]);
+    for (i, e) in shown {
+        lines.push(
+            Msg::new(line)
+                .arg("n", i + 1)
+                .arg("input", &e.input)
+                .arg("output", e.title())
+                .arg("age", age(now.saturating_sub(e.time)))
+                .tr(lang),
+        );
+        resend.push(InlineKeyboardButton::callback(
+            format!("↻ {}", i + 1),
+            callback_data(list, &Action::Resend(e.id)),
+        ));
+        if list == List::Favorites {
+            remove.push(InlineKeyboardButton::callback(
+                format!("✕ {}", i + 1),
+                callback_data(list, &Action::Remove(e.id)),
+            ));
+        }
+    }
+    let mut nav = vec![];
+    if page > 0 {
+        nav.push(InlineKeyboardButton::callback(
+            "‹",
+            callback_data(list, &Action::Page(page - 1)),
+        ));
+    }
+    if page + 1 < pages {
+        nav.push(InlineKeyboardButton::callback(
+            "›",
+            callback_data(list, &Action::Page(page + 1)),
+        ));
+    }
+    let rows: Vec<_> = [resend, remove, nav]
+        .into_iter()
+        .filter(|r| !r.is_empty())
+        .collect();
+    Some((lines.join("\n"), InlineKeyboardMarkup::new(rows)))
+}
+
+// What /find found, with a button to send each again.
+pub fn render_found(found: &[(List, Entry)], lang: &str) -> (String, InlineKeyboardMarkup) {
+    let mut lines = vec![Msg::new("found").arg("n", found.len()).tr(lang)];
+    let mut buttons = vec![];
+    for (i, (list, e)) in found.iter().enumerate() {
+        lines.push(format!("{}. {}", i + 1, e.title()));
+        buttons.push(InlineKeyboardButton::callback(
+            format!("↻ {}", i + 1),
+            callback_data(*list, &Action::Resend(e.id)),
+        ));
+    }
+    let rows: Vec<_> = buttons.chunks(PAGE_SIZE).map(|c| c.to_vec()).collect();
+    (lines.join("\n"), InlineKeyboardMarkup::new(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> {
+        parse_tags(s.split_whitespace())
+    }
+
+    #[test]
+    fn tags_deduplicated_and_capped() {
+        assert_eq!(words("Cat dog cat DOG cat"), ["cat", "dog"]);
+        let many: Vec<_> = (0..MAX_TAGS + 5).map(|i| format!("t{} t0", i)).collect();
+        let tags = words(&many.join(" "));
+        assert_eq!(tags.len(), MAX_TAGS);
+        assert_eq!(tags[1], "t1");
+    }
+
+    #[test]
+    fn matches_prefixes_and_emoji() {
+        let e = Entry::new(1, "image", "webp", "id".into(), words("😂 cats meme"));
+        assert!(e.matches(&words("cat")));
+        assert!(e.matches(&words("Cat mem")));
+        assert!(e.matches(&words("web")));
+        assert!(e.matches(&words("image")));
+        assert!(e.matches(&words("😂😭")));
+        assert!(!e.matches(&words("dog")));
+        assert!(!e.matches(&words("cat dog")));
+        assert!(!e.matches(&words("ats")));
+    }
+
+    #[test]
+    fn callback_data_round_trip() {
+        let cases = [
+            (List::History, Action::Page(2)),
+            (List::History, Action::Resend(5)),
+            (List::Favorites, Action::Remove(7)),
+        ];
+        for (list, action) in cases {
+            let (l, a) = parse_callback_data(&callback_data(list, &action)).unwrap();
+            assert_eq!(l, list);
+            assert_eq!(callback_data(l, &a), callback_data(list, &action));
+        }
+        // Only favorites are removed.
+        assert!(parse_callback_data("hist:h:d:7").is_none());
+        assert!(parse_callback_data("hist:x:p:0").is_none());
+        assert!(parse_callback_data("help:h:p:0").is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{config, ffmpeg, wait_output};
+use log::{info, warn};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HwAccel {
+    Vaapi,
+    Qsv,
+    // NVENC has no VP9 encoder, so this only offloads decoding to CUDA.
+    Nvenc,
+}
+
+const AUTO_ORDER: [HwAccel; 3] = [HwAccel::Vaapi, HwAccel::Qsv, HwAccel::Nvenc];
+
+static DETECTED: OnceLock<Option<HwAccel>> = OnceLock::new();
+
+impl HwAccel {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "vaapi" => Some(Self::Vaapi),
+            "qsv" => Some(Self::Qsv),
+            "nvenc" | "cuda" => Some(Self::Nvenc),
+            _ => None,
+        }
+    }
+
+    // Options placed before the input.
+    pub fn input_args(self, cmd: &mut Command) {
+        match self {
+            Self::Vaapi => {
+                cmd.arg("-vaapi_device").arg(&config::get().vaapi_device);
+            }
+            Self::Qsv => {
+                cmd.args(["-init_hw_device", "qsv=hw", "-filter_hw_device", "hw"]);
+            }
+            Self::Nvenc => {
+                cmd.args(["-hwaccel", "cuda"]);
+            }
+        }
+    }
+
+    // Appended to the software scaling filter to move frames onto the device.
+    pub fn filter_suffix(self) -> &'static str {
+        match self {
+            Self::Vaapi => ",format=nv12,hwupload",
+            Self::Qsv => ",format=nv12,hwupload=extra_hw_frames=64",
+            Self::Nvenc => "",
+        }
+    }
+
+    // Returns false if encoding should still be done by libvpx-vp9. The hardware encoders have
+    // no lossless mode, so the lossless attempt uses a low quantizer instead. Neither of them
+    // keeps alpha.
+    pub fn encoder_args(self, cmd: &mut Command, lossless: bool) -> bool {
+        let Some(enc) = self.encoder() else {
+            return false;
+        };
+        cmd.args(["-c:v", enc]);
+        if lossless {
+            cmd.args(["-global_quality", "10"]);
+        }
+        true
+    }
+
+    // Of VP9, if the device has one.
+    pub fn encoder(self) -> Option<&'static str> {
+        match self {
+            Self::Vaapi => Some("vp9_vaapi"),
+            Self::Qsv => Some("vp9_qsv"),
+            Self::Nvenc => None,
+        }
+    }
+
+    async fn probe(self) -> bool {
+        let mut cmd = ffmpeg();
+        cmd.args(["-hide_banner", "-loglevel", "error"]);
+        match self {
+            Self::Nvenc => {
+                cmd.args(["-init_hw_device", "cuda"]);
+            }
+            _ => self.input_args(&mut cmd),
+        }
+        cmd.args(["-f", "lavfi", "-i", "color=c=black:s=64x64:d=0.1"]);
+        if let Some(enc) = self.encoder() {
+            cmd.arg("-vf")
+                .arg(&self.filter_suffix()[1..])
+                .args(["-c:v", enc]);
+        }
+        cmd.args(["-f", "null", "-"]).stderr(Stdio::null());
+        match wait_output(&mut cmd, config::get().probe_timeout).await {
+            Ok(out) => out.status.success(),
+            Err(e) => {
+                warn!("probing {:?}: {}", self, e);
+                false
+            }
+        }
+    }
+}
+
+pub async fn detect() {
+    let want = config::get().hw_accel.as_deref().unwrap_or("none");
+    let candidates = match want {
+        "none" | "" => vec![],
+        "auto" => AUTO_ORDER.to_vec(),
+        s => {
+            let hw = HwAccel::parse(s);
+            if hw.is_none() {
+                warn!("unknown HW_ACCEL {:?}, using software encoding", s);
+            }
+            hw.into_iter().collect()
+        }
+    };
+    let mut found = None;
+    for &hw in &candidates {
+        if hw.probe().await {
+            found = Some(hw);
+            break;
+        }
+        info!("{:?} is not available", hw);
+    }
+    match found {
+        Some(hw) => info!("using {:?} for video encoding", hw),
+        None if !candidates.is_empty() => warn!("no hardware acceleration available"),
+        None => {}
+    }
+    DETECTED.set(found).ok();
+}
+
+pub fn get() -> Option<HwAccel> {
+    DETECTED.get().copied().flatten()
+}
@@ -0,0 +1,144 @@
+// Replies in the user's language, from the fluent files under locales/. The language is the one
+// set with /lang, or else the one the Telegram client reports, falling back to English.
+
+use crate::access::Actor;
+use crate::settings;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use teloxide::types::{MessageEntity, MessageEntityKind};
+use unic_langid::LanguageIdentifier;
+
+pub const DEFAULT: &str = "en";
+
+const LOCALES: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("zh", include_str!("../locales/zh.ftl")),
+];
+
+type Bundle = FluentBundle<FluentResource>;
+
+static BUNDLES: OnceLock<Vec<(&'static str, Bundle)>> = OnceLock::new();
+
+fn bundles() -> &'static [(&'static str, Bundle)] {
+    BUNDLES.get_or_init(|| {
+        LOCALES
+            .iter()
+            .map(|&(lang, src)| {
+                let res = FluentResource::try_new(src.to_owned())
+                    .unwrap_or_else(|(_, e)| panic!("{}.ftl: {:?}", lang, e));
+                let id: LanguageIdentifier = lang.parse().unwrap();
+                let mut b = FluentBundle::new_concurrent(vec![id]);
+                // The bidi isolation marks show up as boxes in some clients.
+                b.set_use_isolating(false);
+                b.add_resource(res)
+                    .unwrap_or_else(|e| panic!("{}.ftl: {:?}", lang, e));
+                (lang, b)
+            })
+            .collect()
+    })
+}
+
+pub fn init() {
+    bundles();
+}
+
+// Maps a client language code such as "en-US" or "zh-hans" onto a shipped locale.
+pub fn supported(code: &str) -> Option<&'static str> {
+    let primary = code.split(['-', '_']).next()?.to_ascii_lowercase();
+    LOCALES.iter().map(|&(l, _)| l).find(|&l| l == primary)
+}
+
+pub fn names() -> String {
+    LOCALES.map(|(l, _)| l).join(", ")
+}
+
+pub fn lang_for(actor: Option<Actor>, code: Option<&str>) -> &'static str {
+    actor
+        .and_then(|a| settings::get(a).lang)
+        .as_deref()
+        .and_then(supported)
+        .or_else(|| code.and_then(supported))
+        .unwrap_or(DEFAULT)
+}
+
+// A reply to be translated once the language is known. Owned when it comes back from a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Msg {
+    id: Cow<'static, str>,
+    args: Vec<(Cow<'static, str>, String)>,
+    // The argument to hide behind a spoiler.
+    spoiler: Option<Cow<'static, str>>,
+}
+
+impl Msg {
+    pub fn new(id: &'static str) -> Self {
+        Self {
+            id: Cow::Borrowed(id),
+            args: vec![],
+            spoiler: None,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn arg(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.args.push((Cow::Borrowed(name), value.to_string()));
+        self
+    }
+
+    pub fn spoiler(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.spoiler = Some(Cow::Borrowed(name));
+        self.arg(name, value)
+    }
+
+    // Along with the entities for any spoiler, whose offsets are in UTF-16 code units.
+    pub fn tr_entities(&self, lang: &str) -> (String, Vec<MessageEntity>) {
+        let s = self.tr(lang);
+        let mut entities = vec![];
+        let value = self
+            .spoiler
+            .as_ref()
+            .and_then(|name| self.args.iter().find(|(k, _)| k == name))
+            .map(|(_, v)| v.as_str())
+            .filter(|v| !v.is_empty());
+        if let Some(i) = value.and_then(|v| s.rfind(v)) {
+            let offset = s[..i].encode_utf16().count();
+            let length = value.unwrap().encode_utf16().count();
+            entities.push(MessageEntity::new(
+                MessageEntityKind::Spoiler,
+                offset,
+                length,
+            ));
+        }
+        (s, entities)
+    }
+
+    pub fn tr(&self, lang: &str) -> String {
+        let mut args = FluentArgs::new();
+        for (k, v) in &self.args {
+            args.set(&**k, v.clone());
+        }
+        for l in [lang, DEFAULT] {
+            let Some((_, b)) = bundles().iter().find(|(x, _)| *x == l) else {
+                continue;
+            };
+            let Some(pattern) = b.get_message(&self.id).and_then(|m| m.value()) else {
+                continue;
+            };
+            let mut errors = vec![];
+            let s = b.format_pattern(pattern, Some(&args), &mut errors);
+            if !errors.is_empty() {
+                warn!("{}: {}: {:?}", l, self.id, errors);
+            }
+            return s.into_owned();
+        }
+        warn!("no message {}", self.id);
+        self.id.clone().into_owned()
+    }
+}
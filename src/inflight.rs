@@ -0,0 +1,72 @@
+// Conversions in progress, so that identical requests arriving together, such as a sticker
+// forwarded by several users at once, share one run. Keys are the file_unique_id along with the
+// options. Later requests get a clone of the result, or the message of its failure, which only
+// the first one reports.
+
+use crate::error::{user_message, BotError};
+use crate::i18n::Msg;
+use crate::Blob;
+use anyhow::{bail, Result as AnyResult};
+use log::info;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::watch;
+
+type Outcome = Option<Result<Blob, (Msg, String)>>;
+
+static RUNNING: OnceLock<Mutex<HashMap<String, watch::Receiver<Outcome>>>> = OnceLock::new();
+
+fn running() -> &'static Mutex<HashMap<String, watch::Receiver<Outcome>>> {
+    RUNNING.get_or_init(Default::default)
+}
+
+// Removes the entry even when the first run is dropped midway.
+struct Entry<'a>(&'a str);
+
+impl Drop for Entry<'_> {
+    fn drop(&mut self) {
+        running().lock().unwrap().remove(self.0);
+    }
+}
+
+pub async fn run<F: Future<Output = AnyResult<Blob>>>(key: String, f: F) -> AnyResult<Blob> {
+    let found = {
+        let mut map = running().lock().unwrap();
+        match map.get(&key) {
+            Some(rx) => Err(rx.clone()),
+            None => {
+                let (tx, rx) = watch::channel(None);
+                map.insert(key.clone(), rx);
+                Ok(tx)
+            }
+        }
+    };
+    let mut rx = match found {
+        Ok(tx) => {
+            let _entry = Entry(&key);
+            let r = f.await;
+            let shared = match &r {
+                Ok(b) => Ok(b.clone()),
+                Err(e) => Err((user_message(e, false), format!("{:#}", e))),
+            };
+            tx.send(Some(shared)).ok();
+            return r;
+        }
+        Err(rx) => rx,
+    };
+    info!("waiting for the same conversion in flight");
+    loop {
+        let outcome = rx.borrow().clone();
+        match outcome {
+            Some(Ok(b)) => return Ok(b),
+            Some(Err((msg, detail))) => bail!(BotError::Shared { msg, detail }),
+            None => {}
+        }
+        if rx.changed().await.is_err() {
+            break;
+        }
+    }
+    // The first run was dropped, so this one goes on by itself.
+    f.await
+}
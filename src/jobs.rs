@@ -0,0 +1,321 @@
+// Long jobs on whole sticker sets, namely /archive, /restore and /clone, kept in DATA_DIR/jobs.db
+// so that they survive a restart. Each job goes from queued to running to done or failed, and jobs
+// still queued or running when the bot comes back up are run again, which /restore and /clone
+// pick up where they stopped. The user is replied to whenever the job finishes.
+
+use crate::error::{user_message, BotError};
+use crate::i18n::{self, Msg};
+use crate::options::VideoEdit;
+use crate::{access, alert, archive, bot_id, clone, config, download_mem, restore, retry, AppBot};
+use anyhow::{bail, Result as AnyResult};
+use log::{error, info, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::prelude::*;
+use teloxide::types::{MessageId, UserId};
+use tokio::sync::Semaphore;
+
+const JOBS_FILE: &str = "jobs.db";
+// Jobs run at once, the rest stay queued.
+const MAX_RUNNING: usize = 2;
+// Runs cut short by restarts before a job is given up, in case it is what brings the bot down.
+const MAX_ATTEMPTS: u32 = 3;
+// Finished jobs are kept this long, for the log.
+const KEEP_SECS: u64 = 7 * 24 * 3600;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Task {
+    Archive { name: String },
+    // The zip is downloaded again when resumed, as file ids do not expire.
+    Restore { file_id: String },
+    Clone { name: String, edit: VideoEdit },
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    id: i64,
+    pub chat: ChatId,
+    pub user: Option<UserId>,
+    pub reply_to: MessageId,
+    pub lang: &'static str,
+    pub task: Task,
+}
+
+impl Job {
+    pub fn new(msg: &Message, user: Option<UserId>, lang: &'static str, task: Task) -> Self {
+        Self {
+            id: 0,
+            chat: msg.chat.id,
+            user,
+            reply_to: msg.id,
+            lang,
+            task,
+        }
+    }
+}
+
+static DB: OnceLock<Mutex<Connection>> = OnceLock::new();
+static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn open() -> rusqlite::Result<Connection> {
+    let path = config::get().data_dir.join(JOBS_FILE);
+    let db = Connection::open(&path)?;
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS jobs (
+            id INTEGER PRIMARY KEY,
+            bot INTEGER NOT NULL,
+            chat INTEGER NOT NULL,
+            user INTEGER,
+            reply_to INTEGER NOT NULL,
+            lang TEXT NOT NULL,
+            task TEXT NOT NULL,
+            state TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            updated INTEGER NOT NULL
+        )",
+    )?;
+    let pruned = db.execute(
+        "DELETE FROM jobs WHERE state IN ('done', 'failed') AND updated < ?1",
+        params![now().saturating_sub(KEEP_SECS)],
+    )?;
+    info!("opened {}, pruned {} finished jobs", path.display(), pruned);
+    Ok(db)
+}
+
+fn db() -> &'static Mutex<Connection> {
+    DB.get_or_init(|| Mutex::new(open().expect("cannot open the job database")))
+}
+
+pub fn init() {
+    db();
+}
+
+fn insert(bot: u64, job: &Job) -> rusqlite::Result<i64> {
+    let db = db().lock().unwrap();
+    db.execute(
+        "INSERT INTO jobs (bot, chat, user, reply_to, lang, task, state, updated)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'queued', ?7)",
+        params![
+            bot,
+            job.chat.0,
+            job.user.map(|u| u.0),
+            job.reply_to.0,
+            job.lang,
+            serde_json::to_string(&job.task).unwrap(),
+            now()
+        ],
+    )?;
+    Ok(db.last_insert_rowid())
+}
+
+fn set_state(id: i64, state: &str) {
+    let db = db().lock().unwrap();
+    let attempts = if state == "running" { 1 } else { 0 };
+    if let Err(e) = db.execute(
+        "UPDATE jobs SET state = ?1, attempts = attempts + ?2, updated = ?3 WHERE id = ?4",
+        params![state, attempts, now(), id],
+    ) {
+        error!("job {}: {}", id, e);
+    }
+}
+
+// Unfinished jobs of the bot, except those that keep getting cut short, which are failed.
+fn unfinished(bot: u64) -> rusqlite::Result<Vec<Job>> {
+    let db = db().lock().unwrap();
+    db.execute(
+        "UPDATE jobs SET state = 'failed', updated = ?1
+         WHERE bot = ?2 AND state IN ('queued', 'running') AND attempts >= ?3",
+        params![now(), bot, MAX_ATTEMPTS],
+    )?;
+    let mut stmt = db.prepare(
+        "SELECT id, chat, user, reply_to, lang, task FROM jobs
+         WHERE bot = ?1 AND state IN ('queued', 'running') ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![bot], |r| {
+        Ok((
+            r.get::<_, i64>(0)?,
+            r.get::<_, i64>(1)?,
+            r.get::<_, Option<u64>>(2)?,
+            r.get::<_, i32>(3)?,
+            r.get::<_, String>(4)?,
+            r.get::<_, String>(5)?,
+        ))
+    })?;
+    let mut jobs = vec![];
+    for row in rows {
+        let (id, chat, user, reply_to, lang, task) = row?;
+        let Ok(task) = serde_json::from_str(&task) else {
+            warn!("job {}: unreadable task {}", id, task);
+            continue;
+        };
+        jobs.push(Job {
+            id,
+            chat: ChatId(chat),
+            user: user.map(UserId),
+            reply_to: MessageId(reply_to),
+            lang: i18n::supported(&lang).unwrap_or(i18n::DEFAULT),
+            task,
+        });
+    }
+    Ok(jobs)
+}
+
+// What is kept of one of the user's jobs, for /export.
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub task: serde_json::Value,
+    pub state: String,
+    pub updated: u64,
+}
+
+pub fn of_user(user: UserId) -> rusqlite::Result<Vec<Record>> {
+    let db = db().lock().unwrap();
+    let mut stmt =
+        db.prepare("SELECT task, state, updated FROM jobs WHERE user = ?1 ORDER BY id")?;
+    let rows = stmt.query_map(params![user.0], |r| {
+        let task: String = r.get(0)?;
+        Ok(Record {
+            task: serde_json::from_str(&task).unwrap_or(serde_json::Value::String(task)),
+            state: r.get(1)?,
+            updated: r.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// Unfinished jobs are left to run, and go once pruned.
+pub fn forget(user: UserId) -> rusqlite::Result<usize> {
+    let db = db().lock().unwrap();
+    db.execute(
+        "DELETE FROM jobs WHERE user = ?1 AND state IN ('done', 'failed')",
+        params![user.0],
+    )
+}
+
+// Saved before it starts, so that it is not lost should the bot stop right away.
+pub fn submit(bot: &AppBot, mut job: Job) -> AnyResult<()> {
+    job.id = insert(bot_id(bot), &job)?;
+    info!("job {} queued: {:?}", job.id, job.task);
+    tokio::spawn(execute(bot.clone(), job));
+    Ok(())
+}
+
+pub fn resume(bot: &AppBot) {
+    let jobs = match unfinished(bot_id(bot)) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("jobs: {}", e);
+            return;
+        }
+    };
+    for job in jobs {
+        info!("resuming job {}: {:?}", job.id, job.task);
+        tokio::spawn(execute(bot.clone(), job));
+    }
+}
+
+async fn download(bot: &AppBot, file_id: &str) -> AnyResult<Vec<u8>> {
+    let f = bot.get_file(file_id).await?;
+    download_mem(bot, &f).await
+}
+
+async fn reply(bot: &AppBot, job: &Job, m: Msg) -> AnyResult<()> {
+    bot.send_message(job.chat, m.tr(job.lang))
+        .reply_to_message_id(job.reply_to)
+        .allow_sending_without_reply(true)
+        .await?;
+    Ok(())
+}
+
+async fn reply_set(
+    bot: &AppBot,
+    job: &Job,
+    r: restore::Restored,
+    done: &'static str,
+    partial: &'static str,
+) -> AnyResult<()> {
+    let id = if r.complete() { done } else { partial };
+    let m = Msg::new(id)
+        .arg("n", r.added)
+        .arg("total", r.total)
+        .arg("link", format!("https://t.me/addstickers/{}", r.name));
+    reply(bot, job, m).await
+}
+
+// Replies with a zip of the whole set.
+async fn archive(bot: &AppBot, job: &Job, name: &str) -> AnyResult<()> {
+    let (manifest, b) = archive::build(bot, name).await?;
+    let caption = Msg::new("archived")
+        .arg("n", manifest.stickers.len())
+        .arg("title", &manifest.title)
+        .tr(job.lang);
+    let r = bot
+        .send_document(job.chat, b.input_file(Some(&manifest.name)))
+        .caption(caption)
+        .reply_to_message_id(job.reply_to)
+        .allow_sending_without_reply(true)
+        .disable_content_type_detection(true)
+        .await;
+    if let Err(e) = r {
+        if retry::is_too_large(&e) {
+            bail!(BotError::ResultTooBig)
+        }
+        bail!(BotError::from(e))
+    }
+    Ok(())
+}
+
+async fn run(bot: &AppBot, job: &Job) -> AnyResult<()> {
+    match &job.task {
+        Task::Archive { name } => archive(bot, job, name).await,
+        Task::Restore { file_id } => {
+            let Some(user) = job.user else {
+                bail!(BotError::NoOwner)
+            };
+            let data = download(bot, file_id).await?;
+            let r = restore::restore(bot, user, data).await?;
+            reply_set(bot, job, r, "restored", "restored-partial").await
+        }
+        Task::Clone { name, edit } => {
+            let Some(user) = job.user else {
+                bail!(BotError::NoOwner)
+            };
+            let r = clone::clone(bot, user, name, edit.clone()).await?;
+            reply_set(bot, job, r, "cloned", "cloned-partial").await
+        }
+    }
+}
+
+async fn execute(bot: AppBot, job: Job) {
+    let _slot = SLOTS
+        .get_or_init(|| Semaphore::new(MAX_RUNNING))
+        .acquire()
+        .await
+        .unwrap();
+    set_state(job.id, "running");
+    match run(&bot, &job).await {
+        Ok(()) => {
+            info!("job {} done", job.id);
+            set_state(job.id, "done");
+        }
+        Err(e) => {
+            error!("job {}: {:?}", job.id, e);
+            set_state(job.id, "failed");
+            let context = format!("{:?} job {} in {}", job.task, job.id, job.chat.0);
+            alert::failure(&context, &e);
+            let admin = job.user.map_or(false, access::is_admin);
+            if let Err(e) = reply(&bot, &job, user_message(&e, admin)).await {
+                warn!("job {}: {}", job.id, e);
+            }
+        }
+    }
+}
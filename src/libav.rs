@@ -0,0 +1,172 @@
+// In-process VP9 encoding through the libav* libraries, replacing the ffmpeg subprocess for
+// sticker encodes. The output still goes through a temporary file since the webm muxer wants a
+// seekable output.
+
+use crate::target::Target;
+use crate::{job_permit, probe, temp_path, Blob, MAX_DURATION};
+use anyhow::{anyhow, Result as AnyResult};
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{Context as Scaler, Flags};
+use ffmpeg::{codec, encoder, format, frame, media, Dictionary, Packet, Rational};
+use ffmpeg_next as ffmpeg;
+use log::info;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once};
+
+static INIT: Once = Once::new();
+
+// Stops the blocking encoder when the awaiting future is dropped, like kill_on_drop does for
+// the subprocess.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+fn fit(w: u32, h: u32, side: u32) -> (u32, u32) {
+    if w >= h {
+        (side, (h * side / w).max(1))
+    } else {
+        ((w * side / h).max(1), side)
+    }
+}
+
+// Keeps only every `step`-th frame, which is how the size target is hit when even the lossy
+// encode is too big.
+fn encode(
+    input: &Path,
+    output: &Path,
+    lossless: bool,
+    step: u32,
+    side: u32,
+    cancel: &AtomicBool,
+) -> AnyResult<()> {
+    let mut ictx = format::input(&input)?;
+    let ist = ictx
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let ist_index = ist.index();
+    let time_base = ist.time_base();
+    let mut decoder = codec::context::Context::from_parameters(ist.parameters())?
+        .decoder()
+        .video()?;
+
+    let (w, h) = fit(decoder.width(), decoder.height(), side);
+    let mut scaler = Scaler::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::YUVA420P,
+        w,
+        h,
+        Flags::LANCZOS,
+    )?;
+
+    let mut octx = format::output_as(&output, "webm")?;
+    let vp9 = encoder::find_by_name("libvpx-vp9").ok_or_else(|| anyhow!("no libvpx-vp9"))?;
+    let mut ost = octx.add_stream(vp9)?;
+    let mut enc = codec::context::Context::new_with_codec(vp9)
+        .encoder()
+        .video()?;
+    enc.set_width(w);
+    enc.set_height(h);
+    enc.set_format(Pixel::YUVA420P);
+    enc.set_time_base(time_base);
+    if let Some(r) = decoder.frame_rate() {
+        enc.set_frame_rate(Some(
+            Rational(r.numerator(), r.denominator() * step as i32).reduce(),
+        ));
+    }
+    let mut opts = Dictionary::new();
+    if lossless {
+        opts.set("lossless", "1");
+    }
+    let mut enc = enc.open_with(opts)?;
+    ost.set_parameters(&enc);
+    octx.write_header()?;
+    let ost_time_base = octx.stream(0).unwrap().time_base();
+
+    let write_packets = |enc: &mut encoder::Video, octx: &mut format::context::Output| {
+        let mut pkt = Packet::empty();
+        while enc.receive_packet(&mut pkt).is_ok() {
+            pkt.set_stream(0);
+            pkt.rescale_ts(time_base, ost_time_base);
+            pkt.write_interleaved(octx)?;
+        }
+        Ok::<_, ffmpeg::Error>(())
+    };
+
+    let mut n = 0;
+    let mut done = false;
+    let mut decoded = frame::Video::empty();
+    let mut scaled = frame::Video::empty();
+    let mut receive_frames = |decoder: &mut ffmpeg::decoder::Video,
+                              enc: &mut encoder::Video,
+                              octx: &mut format::context::Output,
+                              done: &mut bool|
+     -> AnyResult<()> {
+        while !*done && decoder.receive_frame(&mut decoded).is_ok() {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(anyhow!("cancelled"));
+            }
+            let pts = decoded.timestamp().unwrap_or(0);
+            if pts as f64 * f64::from(time_base) >= MAX_DURATION {
+                *done = true;
+                break;
+            }
+            n += 1;
+            if (n - 1) % step != 0 {
+                continue;
+            }
+            scaler.run(&decoded, &mut scaled)?;
+            scaled.set_pts(Some(pts));
+            enc.send_frame(&scaled)?;
+            write_packets(enc, octx)?;
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != ist_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        receive_frames(&mut decoder, &mut enc, &mut octx, &mut done)?;
+        if done {
+            break;
+        }
+    }
+    if !done {
+        decoder.send_eof()?;
+        receive_frames(&mut decoder, &mut enc, &mut octx, &mut done)?;
+    }
+    enc.send_eof()?;
+    write_packets(&mut enc, &mut octx)?;
+    octx.write_trailer()?;
+    info!("libav: encoded {} of {} frames", (n + step - 1) / step, n);
+    Ok(())
+}
+
+pub async fn encode_webm(
+    input: &Path,
+    lossless: bool,
+    step: u32,
+    target: Target,
+) -> AnyResult<Blob> {
+    INIT.call_once(|| ffmpeg::init().expect("ffmpeg init"));
+    let _permit = job_permit().await;
+    let out_path = temp_path()?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let _guard = CancelOnDrop(cancel.clone());
+    let (input, output): (PathBuf, PathBuf) = (input.to_owned(), out_path.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        encode(&input, &output, lossless, step, target.side(), &cancel)
+    })
+    .await??;
+    let info = probe::check_output(&out_path, target).await;
+    Ok(Blob::from_temp(out_path, "webm").await?.with_info(info))
+}
@@ -0,0 +1,177 @@
+// Budgets of each converter run, so that one pathological input cannot starve everyone else. The
+// nice level, the I/O priority and the address space limit are set in the child before it execs.
+// With CGROUP_DIR, each run also gets its own cgroup there, whose memory.max bounds the RSS
+// instead and whose cpu.max bounds the CPU time.
+
+use crate::config;
+use log::{debug, warn};
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use tokio::process::Command;
+
+const CPU_PERIOD: u64 = 100_000;
+// Of removing a cgroup whose processes are still on their way out.
+const RMDIR_RETRIES: u32 = 20;
+const RMDIR_DELAY: Duration = Duration::from_millis(100);
+const IOPRIO_CLASS_BE: i32 = 2;
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+fn has_controllers(dir: &Path) -> io::Result<bool> {
+    let s = fs::read_to_string(dir.join("cgroup.subtree_control"))?;
+    let mut names = s.split_whitespace();
+    Ok(names.clone().any(|c| c == "memory") && names.any(|c| c == "cpu"))
+}
+
+fn detect() -> Option<PathBuf> {
+    let cfg = config::get();
+    let Some(dir) = &cfg.cgroup_dir else {
+        if cfg.job_cpus.is_some() {
+            warn!("JOB_CPUS needs CGROUP_DIR");
+        }
+        return None;
+    };
+    match has_controllers(dir) {
+        Ok(true) => Some(dir.clone()),
+        Ok(false) => {
+            warn!("{}: memory and cpu controllers not enabled", dir.display());
+            None
+        }
+        Err(e) => {
+            warn!("{}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+// Warns about the cgroup up front rather than on the first job.
+pub fn init() {
+    ROOT.get_or_init(detect);
+}
+
+// Removed when dropped after the run, which only works once the child is gone. A child killed on
+// a timeout may not be yet, and the removal is then retried in the background.
+pub struct Cgroup {
+    dir: PathBuf,
+    procs: File,
+}
+
+impl Cgroup {
+    fn create(root: &Path) -> io::Result<Self> {
+        let cfg = config::get();
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = root.join(format!("job-{}-{}", process::id(), n));
+        fs::create_dir(&dir)?;
+        let cg = Self {
+            procs: File::options().write(true).open(dir.join("cgroup.procs"))?,
+            dir,
+        };
+        if let Some(mb) = cfg.job_memory {
+            fs::write(cg.dir.join("memory.max"), (mb << 20).to_string())?;
+            fs::write(cg.dir.join("memory.swap.max"), "0").ok();
+        }
+        if let Some(cpus) = cfg.job_cpus {
+            let quota = (cpus * CPU_PERIOD as f64) as u64;
+            fs::write(
+                cg.dir.join("cpu.max"),
+                format!("{} {}", quota.max(1000), CPU_PERIOD),
+            )?;
+        }
+        Ok(cg)
+    }
+}
+
+fn is_busy(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EBUSY)
+}
+
+fn remove_later(dir: PathBuf) {
+    for _ in 0..RMDIR_RETRIES {
+        thread::sleep(RMDIR_DELAY);
+        match fs::remove_dir(&dir) {
+            Err(e) if is_busy(&e) => continue,
+            Err(e) => debug!("{}: {}", dir.display(), e),
+            Ok(()) => {}
+        }
+        return;
+    }
+    warn!("{}: still busy, left behind", dir.display());
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        match fs::remove_dir(&self.dir) {
+            Ok(()) => {}
+            Err(e) if is_busy(&e) => {
+                let dir = std::mem::take(&mut self.dir);
+                thread::spawn(move || remove_later(dir));
+            }
+            Err(e) => debug!("{}: {}", self.dir.display(), e),
+        }
+    }
+}
+
+// To be kept until the child has exited.
+pub fn apply(cmd: &mut Command) -> Option<Cgroup> {
+    let cfg = config::get();
+    let cgroup = ROOT
+        .get_or_init(detect)
+        .as_deref()
+        .and_then(|root| match Cgroup::create(root) {
+            Ok(cg) => Some(cg),
+            Err(e) => {
+                warn!("cgroup: {}", e);
+                None
+            }
+        });
+    let procs = cgroup.as_ref().map(|cg| cg.procs.as_raw_fd());
+    let nice = cfg.job_nice;
+    let ionice = cfg.job_ionice;
+    let memory = cfg.job_memory.filter(|_| cgroup.is_none());
+    if procs.is_none() && nice.is_none() && ionice.is_none() && memory.is_none() {
+        return None;
+    }
+    // Only async-signal-safe calls are made between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(fd) = procs {
+                // Moves the writer itself.
+                if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(n) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, n) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(level) = ionice {
+                let prio = IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | level as i32;
+                if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(mb) = memory {
+                let lim = libc::rlimit {
+                    rlim_cur: mb << 20,
+                    rlim_max: mb << 20,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &lim) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    cgroup
+}
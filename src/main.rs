@@ -1,65 +1,244 @@
+mod access;
+mod alert;
+mod archive;
+mod aspect;
+mod batch;
+mod bench;
+mod bundle;
+mod cache;
+mod caps;
+mod cli;
+mod clone;
+mod config;
+mod content;
+#[cfg(feature = "cutout")]
+mod cutout;
+mod duplicates;
+#[cfg(feature = "emoji")]
+mod emoji;
+mod error;
+mod export;
+mod ffjob;
+mod formats;
+mod frames;
+mod grid;
+mod help;
+mod history;
+mod hwaccel;
+mod i18n;
+mod inflight;
+mod jobs;
+#[cfg(feature = "libav")]
+mod libav;
+mod limits;
+mod nsfw;
+mod ocr;
+mod offers;
+mod options;
+mod payments;
+mod pending;
+mod pixel;
+mod plan;
+mod presets;
+mod probe;
+mod psd;
+mod quota;
+mod responses;
+mod restore;
+mod retry;
+mod selftest;
+mod settings;
+mod smartcrop;
+mod sources;
+mod stash;
+mod store;
+mod style;
+mod target;
+mod tempdir;
+mod text;
+mod thumb;
+mod upscale;
+mod userdata;
+mod vector;
+mod watermark;
+mod worker;
+
+use access::Actor;
 use anyhow::{bail, Result as AnyResult};
 use bytes::Bytes;
-use image::imageops::FilterType;
+use clap::Parser;
+use cli::Cli;
+use error::{user_message, BotError};
+use ffjob::FfmpegJob;
+use futures::StreamExt;
+use hwaccel::HwAccel;
+use i18n::Msg;
+use image::imageops::{self, FilterType};
 use image::io::Reader as ImageReader;
-use image::{GenericImageView, ImageOutputFormat};
+use image::{DynamicImage, GenericImageView, RgbaImage};
 use log::{error, info, warn};
+use options::VideoEdit;
+use pending::{Pending, Segment};
+use probe::{StickerKind, VideoInfo};
+use responses::ResponseTracker;
+use sources::Source;
+use stash::Stashed;
+use std::future::Future;
 use std::io;
 use std::io::Cursor;
 use std::path::Path;
 use std::process::{Output, Stdio};
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
-use teloxide::net::Download;
+use target::Target;
+use teloxide::adaptors::throttle::Limits;
+use teloxide::adaptors::Throttle;
+use teloxide::dptree;
+use teloxide::net::{self, Download};
 use teloxide::prelude::*;
-use teloxide::types::{File as TgFile, InputFile, StickerFormat};
-use tempfile::{NamedTempFile, TempPath};
+use teloxide::requests::Request as _;
+use teloxide::types::{
+    File as TgFile, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery, InlineQueryResult,
+    InlineQueryResultCachedDocument, InputFile, PreCheckoutQuery, StickerFormat, UserId,
+};
+use tempfile::TempPath;
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::join;
-use tokio::process::Command;
+use tokio::process::{ChildStderr, Command};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{field, info_span, Instrument, Span};
+use tracing_subscriber::EnvFilter;
+use upscale::Upscaler;
 use webp::Encoder as WebpEncoder;
 
-const MAX_SIZE: u32 = 10 << 20;
-const MAX_OUTPUT_WEBM_SIZE: usize = 256 * 1000;
+// Inputs read into memory, images and zips, are limited to what Telegram's servers hand out, while
+// videos are streamed to disk and may go up to the configured size.
+const MAX_MEMORY_INPUT: u32 = 20 << 20;
+const MAX_OUTPUT_WEBP_SIZE: u64 = 512 * 1000;
+// Tried in order when the output is too big, before scaling down.
+const WEBP_QUALITIES: [u8; 6] = [90, 80, 70, 60, 50, 40];
+// Longest side that tiny images are scaled up to when the webp encoder fails on them.
+const FALLBACK_MIN_SIDE: u32 = 128;
+// Encoder outputs above this are uploaded straight from their temporary files.
+const MAX_MEMORY_BLOB_SIZE: u64 = 1 << 20;
+
+// Of a child's stderr kept for the error.
+const MAX_STDERR_SIZE: usize = 16 << 10;
 
-const FFMPEG: &str = "ffmpeg";
+// Default background of mp4s.
+const WHITE: [u8; 3] = [255, 255, 255];
 
-const FFMPEG_ARGS: (&[&str], &[&str]) = (
-    &["-hide_banner", "-t", "3", "-i"],
-    &[
-        "-vf",
-        "scale=w=512:h=512:force_original_aspect_ratio=decrease",
-        "-c:v",
-        "libvpx-vp9",
-        "-f",
-        "webm",
-        "-an",
-        "-",
-    ],
-);
+// Longest video sticker allowed, in seconds.
+const MAX_DURATION: f64 = 3.0;
 
-const FFMPEG_ARGS_WEBM_TO_GIF: (&[&str], &[&str]) =
-    (&["-hide_banner", "-i"], &["-c:v", "gif", "-f", "gif", "-"]);
+// Outgoing requests are queued to stay under Telegram's flood limits, since a single conversion
+// can reply with several documents.
+type AppBot = Throttle<Bot>;
+
+// Read off the token, which starts with it.
+fn bot_id(bot: &AppBot) -> u64 {
+    let token = bot.inner().token();
+    token
+        .split(':')
+        .next()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
 
-const TGS_TO_GIF: &str = "tgs_to_gif.sh";
+// Cheap to clone, so that a result can be shared by identical requests.
+#[derive(Debug, Clone)]
+enum BlobData {
+    Memory(Bytes),
+    File(Arc<TempPath>, u64),
+}
 
 #[derive(Debug, Clone)]
 struct Blob {
-    data: Bytes,
+    data: BlobData,
     ext: &'static str,
+    // Known parameters of the result, for reporting.
+    info: Option<VideoInfo>,
+    // What had to be done to the result, told to the user.
+    note: Option<Msg>,
+    // Of the picture plain stickers were made from, for finding them again.
+    hash: Option<u64>,
 }
 
 impl Blob {
     pub fn new<T: Into<Bytes>>(data: T, ext: &'static str) -> Self {
         Self {
-            data: data.into(),
+            data: BlobData::Memory(data.into()),
             ext,
+            info: None,
+            note: None,
+            hash: None,
+        }
+    }
+
+    pub fn with_info(mut self, info: Option<VideoInfo>) -> Self {
+        self.info = info;
+        self
+    }
+
+    pub fn with_note(mut self, note: Option<Msg>) -> Self {
+        self.note = note;
+        self
+    }
+
+    pub fn with_hash(mut self, hash: Option<u64>) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    pub fn report(&self, target: Target) -> Option<String> {
+        let kind = StickerKind::from_ext(self.ext)?;
+        Some(probe::report(
+            kind,
+            target,
+            self.ext,
+            self.info.as_ref()?,
+            self.len(),
+        ))
+    }
+
+    // Takes over an output file, reading it back into memory if it is small enough.
+    pub async fn from_temp(path: TempPath, ext: &'static str) -> io::Result<Self> {
+        let n = tokio::fs::metadata(&path).await?.len();
+        Ok(if n > MAX_MEMORY_BLOB_SIZE {
+            Self {
+                data: BlobData::File(Arc::new(path), n),
+                ext,
+                info: None,
+                note: None,
+                hash: None,
+            }
+        } else {
+            Self::new(tokio::fs::read(&path).await?, ext)
+        })
+    }
+
+    pub async fn bytes(&self) -> io::Result<Bytes> {
+        match &self.data {
+            BlobData::Memory(b) => Ok(b.clone()),
+            BlobData::File(path, _) => Ok(tokio::fs::read(&**path).await?.into()),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        match &self.data {
+            BlobData::Memory(b) => b.len() as u64,
+            BlobData::File(_, n) => *n,
         }
     }
 
-    pub fn into_input_file(self, base: Option<&str>) -> InputFile {
-        let n = self.data.len();
-        let f = InputFile::memory(self.data);
+    // The returned InputFile refers to the temporary file if any, so the blob must outlive it.
+    pub fn input_file(&self, base: Option<&str>) -> InputFile {
+        let f = match &self.data {
+            BlobData::Memory(b) => InputFile::memory(b.clone()),
+            BlobData::File(path, _) => InputFile::file(path.to_path_buf()),
+        };
         let mut out_name;
         if let Some(s) = base {
             out_name = s.to_owned();
@@ -68,129 +247,593 @@ impl Blob {
             out_name = "out.".to_owned();
         };
         out_name.push_str(self.ext);
-        info!("sending {} B as {}", n, out_name);
+        info!("sending {} B as {}", self.len(), out_name);
         f.file_name(out_name)
     }
 }
 
-async fn wait_output(cmd: &mut Command) -> io::Result<Output> {
-    let ch = cmd.kill_on_drop(true).spawn()?;
-    match tokio::time::timeout(Duration::from_secs(60), ch.wait_with_output()).await {
-        Ok(r) => r,
+static JOBS: OnceLock<Semaphore> = OnceLock::new();
+// Taken in turn before waiting for a permit by all but premium users, so that at most one of the
+// others is ever ahead of those waiting with priority.
+static GATE: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+
+tokio::task_local! {
+    static PRIORITY: bool;
+}
+
+async fn job_permit() -> SemaphorePermit<'static> {
+    let jobs = JOBS.get_or_init(|| Semaphore::new(config::get().max_jobs));
+    if PRIORITY.try_with(|&p| p).unwrap_or(false) {
+        return jobs.acquire().await.unwrap();
+    }
+    let _turn = GATE.get_or_init(Default::default).lock().await;
+    jobs.acquire().await.unwrap()
+}
+
+// Keeps the end of a stream, which is where ffmpeg tells why it failed.
+async fn read_tail(r: Option<ChildStderr>) -> Vec<u8> {
+    let Some(mut r) = r else {
+        return vec![];
+    };
+    let mut tail = Vec::new();
+    let mut buf = [0; 4096];
+    while let Ok(n @ 1..) = r.read(&mut buf).await {
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > MAX_STDERR_SIZE * 2 {
+            tail.drain(..tail.len() - MAX_STDERR_SIZE);
+        }
+    }
+    if tail.len() > MAX_STDERR_SIZE {
+        tail.drain(..tail.len() - MAX_STDERR_SIZE);
+    }
+    tail
+}
+
+// The stderr of the child is captured into the output, up to MAX_STDERR_SIZE from the end. The
+// child is killed after `timeout`, which is one of those in the config for its kind.
+async fn wait_output(cmd: &mut Command, timeout: Duration) -> AnyResult<Output> {
+    run_child(cmd, None, timeout).await
+}
+
+// Like wait_output, with the chunks from `input` written to the stdin of the child as they come.
+async fn wait_output_fed(
+    cmd: &mut Command,
+    input: UnboundedReceiver<Bytes>,
+    timeout: Duration,
+) -> AnyResult<Output> {
+    run_child(cmd.stdin(Stdio::piped()), Some(input), timeout).await
+}
+
+async fn run_child(
+    cmd: &mut Command,
+    input: Option<UnboundedReceiver<Bytes>>,
+    timeout: Duration,
+) -> AnyResult<Output> {
+    let _permit = job_permit().await;
+    let _cgroup = limits::apply(cmd);
+    let mut ch = cmd.kill_on_drop(true).stderr(Stdio::piped()).spawn()?;
+    let stderr = ch.stderr.take();
+    let stdin = ch.stdin.take();
+    let feed = async move {
+        let (Some(mut w), Some(mut rx)) = (stdin, input) else {
+            return;
+        };
+        while let Some(b) = rx.recv().await {
+            // The child stopping early is told by its status.
+            if w.write_all(&b).await.is_err() {
+                break;
+            }
+        }
+        // Dropping stdin closes it.
+    };
+    let run = async move {
+        let (out, tail, ()) = join!(ch.wait_with_output(), read_tail(stderr), feed);
+        out.map(|out| Output {
+            stderr: tail,
+            ..out
+        })
+    };
+    match tokio::time::timeout(timeout, run).await {
+        Ok(r) => Ok(r?),
         Err(_) => {
             // kill_on_drop takes effect hopefully.
-            Err(io::Error::new(io::ErrorKind::TimedOut, "child timed out"))
+            bail!(BotError::Timeout {
+                secs: timeout.as_secs()
+            })
         }
     }
 }
 
-async fn temp_file() -> io::Result<(TempPath, File)> {
-    let path = NamedTempFile::new()?.into_temp_path();
+// Gives up on a download after the download_timeout of the config.
+async fn download_within<T, E>(f: impl Future<Output = Result<T, E>>) -> AnyResult<T>
+where
+    anyhow::Error: From<E>,
+{
+    match tokio::time::timeout(config::get().download_timeout, f).await {
+        Ok(r) => Ok(r?),
+        Err(_) => bail!(BotError::DownloadTimeout),
+    }
+}
+
+// With the global options from the config.
+fn ffmpeg() -> Command {
+    let cfg = config::get();
+    let mut cmd = Command::new(&cfg.ffmpeg);
+    cmd.args(&cfg.ffmpeg_args);
+    cmd
+}
+
+fn temp_path() -> AnyResult<TempPath> {
+    tempdir::path()
+}
+
+async fn temp_file() -> AnyResult<(TempPath, File)> {
+    let path = temp_path()?;
     let f = File::create(&path).await?;
     Ok((path, f))
 }
 
-async fn process_image(file: Vec<u8>) -> AnyResult<Blob> {
+fn decode_image(file: Vec<u8>) -> AnyResult<DynamicImage> {
+    if psd::sniff(&file) {
+        return psd::flatten(&file);
+    }
+    if let Some(kind) = formats::sniff(&file) {
+        info!("decoding {:?}", kind);
+        return formats::decode(kind, &file);
+    }
+    // The image crate ignores it, and the outputs carry no metadata to keep it in.
+    let orientation = formats::orientation(&file);
     match ImageReader::new(Cursor::new(file))
         .with_guessed_format()
         .unwrap()
         .decode()
     {
         Ok(img) => {
-            info!("got img of {:?}", img.dimensions());
-            let img = img.resize(512, 512, FilterType::Lanczos3);
-            // webp::Encoder sometimes fails with Unimplemented when inputting small images.
-            return Ok(match WebpEncoder::from_image(&img) {
-                Ok(webp) => {
-                    let mem = webp.encode_lossless();
-                    Blob::new(mem.to_vec(), "webp")
-                }
-                Err(e) => {
-                    warn!("webp: {}, falling back to png", e);
-                    let mut v = Cursor::new(Vec::with_capacity(60000));
-                    img.write_to(&mut v, ImageOutputFormat::Png)?;
-                    Blob::new(v.into_inner(), "png")
-                }
-            });
+            info!(
+                "got img of {:?}, orientation {}",
+                img.dimensions(),
+                orientation
+            );
+            Ok(formats::orient(img, orientation))
         }
         Err(e) => {
             info!("decode failed: {}", e);
-            bail!("File is not an image.")
+            bail!(BotError::NotAnImage)
         }
     }
 }
 
-// Passing a mp4 video from pipe sometimes causes failure in codecs detection of ffmpeg, so we have
-// to use a temporary file.
-async fn process_video(file: &Path) -> AnyResult<Blob> {
-    // FIXME: output could be still too big even when lossy, try specify a bit rate?
-    // FIXME: current implementation often has to run ffmpeg twice, try to avoid the lossless
-    //        attempt in such cases.
+// Centers the image in a transparent square canvas.
+fn pad_square(img: &DynamicImage, side: u32) -> DynamicImage {
+    let mut canvas = RgbaImage::new(side, side);
+    let (w, h) = img.dimensions();
+    imageops::overlay(
+        &mut canvas,
+        &img.to_rgba8(),
+        (side as i64 - w as i64) / 2,
+        (side as i64 - h as i64) / 2,
+    );
+    DynamicImage::ImageRgba8(canvas)
+}
+
+struct FittedWebp {
+    data: Vec<u8>,
+    dimensions: (u32, u32),
+    // What had to be done, when the chosen encoding did not fit.
+    note: Option<Msg>,
+}
 
-    let mut lossy = false;
+// Lowers the quality, and then the size, until the webp fits. Errors are from the encoder, while
+// None means nothing fits.
+fn fit_webp(img: &DynamicImage, quality: Option<u8>) -> Result<Option<FittedWebp>, String> {
+    let encode = |img: &DynamicImage, q: Option<u8>| -> Result<Vec<u8>, String> {
+        let webp = WebpEncoder::from_image(img).map_err(|e| e.to_string())?;
+        Ok(match q {
+            Some(q) => webp.encode(q as f32),
+            None => webp.encode_lossless(),
+        }
+        .to_vec())
+    };
+    let fits = |mem: &Vec<u8>| mem.len() as u64 <= MAX_OUTPUT_WEBP_SIZE;
+    let data = encode(img, quality)?;
+    if fits(&data) {
+        return Ok(Some(FittedWebp {
+            data,
+            dimensions: img.dimensions(),
+            note: None,
+        }));
+    }
+    info!("output of {} B too big, going lossy", data.len());
+    let mut q = quality.unwrap_or(100);
+    for &x in WEBP_QUALITIES.iter().filter(|&&x| x < q) {
+        q = x;
+        let data = encode(img, Some(q))?;
+        if fits(&data) {
+            return Ok(Some(FittedWebp {
+                data,
+                dimensions: img.dimensions(),
+                note: Some(Msg::new("note-compressed").arg("quality", q)),
+            }));
+        }
+    }
+    let mut img = img.clone();
     loop {
-        let mut cmd = Command::new(FFMPEG);
-        let mut cmd = cmd.args(FFMPEG_ARGS.0).arg(file);
-        if !lossy {
-            cmd = cmd.arg("-lossless").arg("1");
+        let (w, h) = img.dimensions();
+        if w.max(h) <= 64 {
+            return Ok(None);
+        }
+        img = img.resize(w * 4 / 5, h * 4 / 5, FilterType::Lanczos3);
+        let data = encode(&img, Some(q))?;
+        if fits(&data) {
+            let (w, h) = img.dimensions();
+            return Ok(Some(FittedWebp {
+                data,
+                dimensions: (w, h),
+                note: Some(
+                    Msg::new("note-scaled")
+                        .arg("width", w)
+                        .arg("height", h)
+                        .arg("quality", q),
+                ),
+            }));
+        }
+    }
+}
+
+// What is done when the webp encoder fails on an image, given by WEBP_FALLBACK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum WebpFallback {
+    // Encodes the pixels again as RGBA, lossy.
+    #[default]
+    Lossy,
+    // Tries again at FALLBACK_MIN_SIDE, for images too small for the encoder.
+    Upscale,
+    Png,
+    // Tells the user instead.
+    Fail,
+}
+
+impl WebpFallback {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "lossy" => Some(Self::Lossy),
+            "upscale" => Some(Self::Upscale),
+            "png" => Some(Self::Png),
+            "fail" => Some(Self::Fail),
+            _ => None,
         }
-        let out = wait_output(cmd.args(FFMPEG_ARGS.1).stdout(Stdio::piped())).await?;
+    }
+}
 
-        if !out.status.success() {
-            error!("ffmpeg failed: {:?}", out.status);
-            bail!("ffmpeg")
+// Another go after the encoder failed on `img`, None meaning a png is sent.
+fn refit_webp(img: &DynamicImage, quality: Option<u8>) -> AnyResult<Option<FittedWebp>> {
+    let (img, quality, note) = match config::get().webp_fallback {
+        WebpFallback::Png => return Ok(None),
+        WebpFallback::Fail => bail!(BotError::WebpFailed),
+        WebpFallback::Lossy => {
+            let q = quality.unwrap_or(WEBP_QUALITIES[0]);
+            let note = Msg::new("note-webp-lossy").arg("quality", q);
+            (DynamicImage::ImageRgba8(img.to_rgba8()), Some(q), note)
         }
-        if !lossy && out.stdout.len() > MAX_OUTPUT_WEBM_SIZE {
-            lossy = true;
-            info!("retrying with lossy");
-        } else {
-            return Ok(Blob::new(out.stdout, "webm"));
+        WebpFallback::Upscale => {
+            let (w, h) = img.dimensions();
+            if w.max(h) >= FALLBACK_MIN_SIDE {
+                return Ok(None);
+            }
+            let img = img.resize(FALLBACK_MIN_SIDE, FALLBACK_MIN_SIDE, FilterType::Lanczos3);
+            let (w, h) = img.dimensions();
+            let note = Msg::new("note-webp-upscaled")
+                .arg("width", w)
+                .arg("height", h);
+            (img, quality, note)
+        }
+    };
+    match fit_webp(&img, quality) {
+        Ok(Some(f)) => Ok(Some(FittedWebp {
+            note: Some(note),
+            ..f
+        })),
+        Ok(None) => bail!(BotError::StaticTooBig),
+        Err(e) => {
+            warn!("webp again: {}", e);
+            Ok(None)
         }
     }
 }
 
-async fn ffmpeg_to_gif(data: &[u8]) -> AnyResult<Blob> {
-    // Using a pipe for ffmpeg stdin sometimes causes deadlock here.
-    let (path, mut tmp) = temp_file().await?;
-    tmp.write_all(data).await?;
-    drop(tmp);
+// Lossless unless a quality is given.
+fn encode_static(img: &DynamicImage, quality: Option<u8>) -> AnyResult<Blob> {
+    let has_alpha = img.color().has_alpha() && img.pixels().any(|(_, _, p)| p[3] < 255);
+    let info = |(width, height)| {
+        Some(VideoInfo {
+            width,
+            height,
+            has_alpha,
+            ..Default::default()
+        })
+    };
+    // webp::Encoder sometimes fails with Unimplemented when inputting small images.
+    match fit_webp(img, quality) {
+        Ok(Some(f)) => Ok(Blob::new(f.data, "webp")
+            .with_info(info(f.dimensions))
+            .with_note(f.note)),
+        Ok(None) => bail!(BotError::StaticTooBig),
+        Err(e) => {
+            let policy = config::get().webp_fallback;
+            warn!("webp: {}, falling back to {:?}", e, policy);
+            if let Some(f) = refit_webp(img, quality)? {
+                return Ok(Blob::new(f.data, "webp")
+                    .with_info(info(f.dimensions))
+                    .with_note(f.note));
+            }
+            Ok(Blob::new(export::png(img)?, "png")
+                .with_info(info(img.dimensions()))
+                .with_note(Some(Msg::new("note-png"))))
+        }
+    }
+}
 
-    let out = wait_output(
-        Command::new(FFMPEG)
-            .args(FFMPEG_ARGS_WEBM_TO_GIF.0)
-            .arg(&path)
-            .args(FFMPEG_ARGS_WEBM_TO_GIF.1)
-            .stdout(Stdio::piped()),
-    )
-    .await?;
-    if !out.status.success() {
-        error!("ffmpeg failed: {:?}", out.status);
-        bail!("ffmpeg")
+// For pictures looked at on the task of a request, as decoding may take a while.
+async fn decode_blocking(file: Vec<u8>) -> AnyResult<DynamicImage> {
+    tokio::task::spawn_blocking(move || decode_image(file)).await?
+}
+
+// Vector inputs are rendered at `side`.
+async fn load_image(file: Vec<u8>, side: u32) -> AnyResult<DynamicImage> {
+    match vector::sniff(&file) {
+        Some(vector::Kind::Svg) => vector::render_svg(&file, side),
+        Some(vector::Kind::Pdf) => vector::render_pdf(file, side).await,
+        None => decode_image(file),
+    }
+}
+
+async fn process_image(file: Vec<u8>, edit: &VideoEdit) -> AnyResult<Blob> {
+    let img = load_image(file, edit.target().side()).await?;
+    let hash = edit.is_plain().then(|| duplicates::hash(&img));
+    Ok(process_decoded(img, edit).await?.with_hash(hash))
+}
+
+async fn process_decoded(img: DynamicImage, edit: &VideoEdit) -> AnyResult<Blob> {
+    let side = edit.target().side();
+    if edit.thumb {
+        return thumb::image(edit.adjust_image(img));
+    }
+    let img = if edit.smartcrop {
+        smartcrop::crop(img)
+    } else {
+        img
+    };
+    let img = upscale::apply(img, side, edit.upscaler()).await?;
+    let fit = upscale::fit_side(&img, side, edit.upscaler());
+    let mut img = pixel::resize(&img, fit, edit.pixel, edit.colors);
+    if edit.cutout {
+        #[cfg(feature = "cutout")]
+        {
+            img = cutout::remove_background(img).await?;
+        }
+        #[cfg(not(feature = "cutout"))]
+        bail!(BotError::CutoutUnavailable);
+    }
+    img = edit.adjust_image(img);
+    if let Some(s) = edit.style {
+        img = style::apply(img, s);
+    }
+    if edit.pad || edit.target().square() {
+        img = pad_square(&img, side);
+    }
+    img = edit.fill_background(img);
+    if let Some(o) = &edit.text {
+        let mut rgba = img.to_rgba8();
+        text::draw(&mut rgba, o)?;
+        img = DynamicImage::ImageRgba8(rgba);
+    }
+    if let Some(w) = &edit.watermark {
+        let mut rgba = img.to_rgba8();
+        watermark::apply(&mut rgba, w)?;
+        img = DynamicImage::ImageRgba8(rgba);
+    }
+    match edit.target() {
+        Target::Discord => export::discord_image(&img),
+        Target::Whatsapp => export::whatsapp_image(&img),
+        Target::Signal => export::signal_image(&img),
+        _ => encode_static(&img, edit.quality),
+    }
+}
+
+async fn encode_webm(file: &Path, lossless: bool, edit: &VideoEdit) -> AnyResult<Blob> {
+    // libav only does plain encodes, edits and padding go through the ffmpeg filter graph.
+    #[cfg(feature = "libav")]
+    if edit.is_empty() && edit.watermark.is_none() && !edit.target().square() {
+        return libav::encode_webm(file, lossless, 1, edit.target()).await;
+    }
+    if let Some(hw) = hwaccel::get() {
+        match run_ffmpeg_webm(file, lossless, edit, Some(hw)).await {
+            Ok(b) => return Ok(b),
+            Err(e) => warn!("{:?} failed, falling back to libvpx-vp9: {:?}", hw, e),
+        }
+    }
+    run_ffmpeg_webm(file, lossless, edit, None).await
+}
+
+// Passing a mp4 video from pipe sometimes causes failure in codecs detection of ffmpeg, so we have
+// to use a temporary file.
+async fn run_ffmpeg_webm(
+    file: &Path,
+    lossless: bool,
+    edit: &VideoEdit,
+    hw: Option<HwAccel>,
+) -> AnyResult<Blob> {
+    let target = edit.target();
+    FfmpegJob::new(Input::File(file), "webm")
+        .hw(hw)
+        .edit(edit, target.side(), edit.pad || target.square())
+        .await?
+        .duration(MAX_DURATION)
+        .vp9(lossless)
+        .args(["-f", "webm", "-an"])
+        .check(target)
+        .run()
+        .await
+}
+
+async fn process_video(file: &Path, edit: &VideoEdit) -> AnyResult<Blob> {
+    if edit.webp {
+        return export::animated_webp(file, edit).await;
+    }
+    if !edit.target().is_telegram() {
+        return export::encode_video(file, edit).await;
+    }
+    if edit.lossy {
+        return encode_webm(file, false, edit).await;
+    }
+    let max_size = edit.target().max_webm_size();
+    // FIXME: output could be still too big even when lossy, try specify a bit rate?
+
+    // Both encodes run at once when the job limit allows. The lossless one is preferred, and
+    // whichever is no longer needed gets dropped, which kills its ffmpeg.
+    let lossless = encode_webm(file, true, edit);
+    let lossy = encode_webm(file, false, edit);
+    tokio::pin!(lossless, lossy);
+    let blob = tokio::select! {
+        // Polled first so that it gets the job permit first when only one is left.
+        biased;
+        r = &mut lossless => {
+            match r {
+                Ok(b) if b.len() <= max_size => return Ok(b),
+                Ok(b) => info!("lossless output of {} B too big, waiting for lossy", b.len()),
+                Err(e) => warn!("lossless: {:?}", e),
+            }
+            lossy.await
+        }
+        r = &mut lossy => match r {
+            Ok(fallback) => match lossless.await {
+                Ok(b) if b.len() <= max_size => Ok(b),
+                Ok(_) => Ok(fallback),
+                Err(e) => {
+                    warn!("lossless: {:?}", e);
+                    Ok(fallback)
+                }
+            },
+            // The lossless one may still fit, and only fails the conversion if it fails too.
+            Err(e) => {
+                warn!("lossy: {:?}", e);
+                lossless.await
+            }
+        }
+    }?;
+
+    // A result still too big, which libav can shrink by dropping frames.
+    #[cfg(feature = "libav")]
+    if edit.is_empty()
+        && edit.watermark.is_none()
+        && !edit.target().square()
+        && blob.len() > max_size
+    {
+        info!("lossy output of {} B too big, dropping frames", blob.len());
+        return libav::encode_webm(file, false, 2, edit.target()).await;
     }
-    Ok(Blob::new(out.stdout, "gif"))
+    Ok(blob)
+}
+
+// A local Bot API server started with --local answers getFile with absolute paths on its own disk,
+// which are read directly when this host sees them too, as with a shared volume.
+fn local_path(f: &TgFile) -> Option<&Path> {
+    let p = Path::new(&f.path);
+    (config::get().api_url.is_some() && p.is_absolute() && p.exists()).then_some(p)
+}
+
+// For requests and set jobs alike, refusing what is too large to hold in memory.
+async fn download_mem(bot: &AppBot, f: &TgFile) -> AnyResult<Vec<u8>> {
+    if f.size > MAX_MEMORY_INPUT {
+        bail!(BotError::TooLarge)
+    }
+    if let Some(p) = local_path(f) {
+        info!("reading {} B from {}", f.size, p.display());
+        return Ok(tokio::fs::read(p).await?);
+    }
+    let mut v = Vec::with_capacity(f.size as usize);
+    download_within(bot.download_file(&f.path, &mut v)).await?;
+    info!("download_mem: {} B", v.len());
+    Ok(v)
+}
+
+// Where a converter reads from.
+enum Input<'a> {
+    File(&'a Path),
+    // Fed to stdin while it is still being downloaded.
+    Pipe(UnboundedReceiver<Bytes>),
+}
+
+// Whether ffmpeg reads the container reliably from a pipe, judging by its magic. Passing mp4 from
+// pipe sometimes breaks the codec detection, as its index may come last.
+fn pipe_safe(head: &[u8]) -> bool {
+    head.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) || head.starts_with(b"GIF8")
+}
+
+async fn ffmpeg_to_gif(input: Input<'_>) -> AnyResult<Blob> {
+    FfmpegJob::new(input, "gif")
+        .args(&config::get().gif_args)
+        .run()
+        .await
+}
+
+// H.264 for places that take neither webm nor transparency, so the transparent parts are filled.
+async fn ffmpeg_to_mp4(file: &Path, bg: [u8; 3]) -> AnyResult<Blob> {
+    let fill = VideoEdit {
+        bg: Some(bg),
+        ..Default::default()
+    }
+    .background_filter();
+    FfmpegJob::new(Input::File(file), "mp4")
+        // The native vp9 decoder drops the alpha channel.
+        .input_args(["-c:v", "libvpx-vp9"])
+        .filter("scale=trunc(iw/2)*2:trunc(ih/2)*2")
+        .filter(&fill)
+        .filter("format=yuv420p")
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .args(["-movflags", "+faststart", "-an", "-f", "mp4"])
+        .run()
+        .await
 }
 
 async fn tgs_to_gif(file: &Path) -> AnyResult<Blob> {
+    let out_path = temp_path()?;
+    let cfg = config::get();
     let out = wait_output(
-        Command::new(TGS_TO_GIF)
+        Command::new(&cfg.tgs_to_gif)
             .arg(file)
-            .args(["--output", "-"])
-            .stdout(Stdio::piped()),
+            .arg("--output")
+            .arg(&out_path),
+        cfg.tgs_timeout,
     )
     .await?;
     if !out.status.success() {
         error!("tgs_to_gif failed: {:?}", out.status);
         bail!("tgs_to_gif")
     }
-    Ok(Blob::new(out.stdout, "gif"))
+    Ok(Blob::from_temp(out_path, "gif").await?)
 }
 
 #[derive(Debug, Clone)]
-struct Request<'a> {
+struct Request {
     msg: Message,
-    bot: Bot,
-    caption: Option<&'a str>,
-    base: Option<&'a str>,
+    bot: AppBot,
+    caption: Option<String>,
+    base: Option<String>,
+    edit: VideoEdit,
+    // What is being converted, recorded for the results.
+    source: Option<Source>,
+    // Of the file being converted, to share the run with identical requests.
+    unique_id: Option<String>,
+    // The media is in the message replied to rather than in ours.
+    replied: bool,
+    // What kind of media it is, for the history.
+    input: &'static str,
+    // Kept with the results in the history along with any emoji, the words read by /ocr.
+    tags: Vec<String>,
+    lang: &'static str,
+    // Of what is sent, for /delete.
+    responses: Arc<ResponseTracker>,
 }
 
 #[derive(Debug, Clone)]
@@ -198,136 +841,1910 @@ enum Op {
     Image,
     Video,
     Sticker(StickerFormat),
+    // A frame of a sticker, at the given second.
+    Still(StickerFormat, f64),
+    // A sticker played at another speed or backwards, given with /speed, /reverse or /boomerang.
+    Replay(StickerFormat),
+    Zip,
+    // A zip from /archive, sent with /restore as the caption.
+    Restore,
+}
+
+impl Op {
+    fn input(&self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Video => "video",
+            Self::Sticker(_) | Self::Still(..) | Self::Replay(_) => "sticker",
+            Self::Zip | Self::Restore => "zip",
+        }
+    }
+}
+
+// "/still" or "/still 1.5", sent in reply to a sticker.
+fn parse_still(text: &str) -> Option<f64> {
+    let mut words = text.split_whitespace();
+    let cmd = words.next()?;
+    if cmd.split('@').next() != Some("/still") {
+        return None;
+    }
+    match words.next() {
+        Some(s) => s
+            .strip_suffix('s')
+            .unwrap_or(s)
+            .parse()
+            .ok()
+            .filter(|t: &f64| t.is_finite() && *t >= 0.0),
+        None => Some(0.0),
+    }
+}
+
+const REPLAY_COMMANDS: [&str; 5] = ["/speed", "/slow", "/fast", "/reverse", "/boomerang"];
+
+// Those that act on media they are replied to, which is then converted with them as its caption.
+const MEDIA_COMMANDS: [&str; 7] = [
+    "/convert", "/plan", "/check", "/thumb", "/cutout", "/pad", "/ocr",
+];
+
+// Every command, which presets are not named after as captions take commands too.
+const COMMANDS: [&str; 57] = [
+    "/start",
+    "/help",
+    "/bench",
+    "/selftest",
+    "/ban",
+    "/unban",
+    "/reload",
+    "/tier",
+    "/nsfw",
+    "/quota",
+    "/donate",
+    "/premium",
+    "/pad",
+    "/check",
+    "/plan",
+    "/target",
+    "/quality",
+    "/lang",
+    "/upscale",
+    "/nogif",
+    "/mp4",
+    "/watermark",
+    "/pixel",
+    "/filter",
+    "/bg",
+    "/smartcrop",
+    "/grid",
+    "/frames",
+    "/emoji",
+    "/discord",
+    "/whatsapp",
+    "/signal",
+    "/webp",
+    "/thumb",
+    "/convert",
+    "/cutout",
+    "/ocr",
+    "/still",
+    "/speed",
+    "/slow",
+    "/fast",
+    "/reverse",
+    "/boomerang",
+    "/restore",
+    "/archive",
+    "/preset",
+    "/chatsettings",
+    "/export",
+    "/forgetme",
+    "/delete",
+    "/history",
+    "/favs",
+    "/find",
+    "/fav",
+    "/tag",
+    "/retry",
+    "/clone",
+];
+
+fn has_media(msg: &Message) -> bool {
+    msg.document().is_some() || msg.photo().is_some() || msg.animation().is_some()
 }
 
-impl<'a> Request<'a> {
+fn is_command(text: &str, cmd: &str) -> bool {
+    text.split_whitespace()
+        .next()
+        .map_or(false, |c| c.split('@').next() == Some(cmd))
+}
+
+impl Request {
+    fn new(msg: Message, bot: AppBot) -> Self {
+        let code = msg.from().and_then(|u| u.language_code.as_deref());
+        let lang = i18n::lang_for(Actor::of(&msg), code);
+        let responses = Arc::new(ResponseTracker::new(&msg));
+        Self {
+            lang,
+            msg,
+            bot,
+            caption: None,
+            base: None,
+            edit: VideoEdit::default(),
+            source: None,
+            unique_id: None,
+            replied: false,
+            input: "",
+            tags: vec![],
+            responses,
+        }
+    }
+
+    // The message holding what is converted.
+    fn media(&self) -> &Message {
+        match self.msg.reply_to_message() {
+            Some(r) if self.replied => r,
+            _ => &self.msg,
+        }
+    }
+
+    // Runs `f` once for requests in flight with the same file and options.
+    async fn dedup(&self, what: &str, f: impl Future<Output = AnyResult<Blob>>) -> AnyResult<Blob> {
+        match &self.unique_id {
+            Some(id) => inflight::run(format!("{} {} {:?}", id, what, self.edit), f).await,
+            None => f.await,
+        }
+    }
+
+    fn gif_key(&self) -> Option<String> {
+        let args = &config::get().gif_args;
+        self.unique_id
+            .as_ref()
+            .map(|id| format!("{} gif {:?}", id, args))
+    }
+
+    // GIFs of stickers are cached too, as the same ones keep coming.
+    async fn cached_gif(&self, f: impl Future<Output = AnyResult<Blob>>) -> AnyResult<Blob> {
+        let Some(key) = self.gif_key() else {
+            return f.await;
+        };
+        if let Some(b) = cache::get(&key, "gif").await {
+            return Ok(b);
+        }
+        let b = self.dedup("gif", f).await?;
+        cache::put(&key, &b).await;
+        Ok(b)
+    }
+
     async fn download_mem(&self, f: TgFile) -> AnyResult<Vec<u8>> {
-        let mut v = Vec::with_capacity(f.size as usize);
-        self.bot.download_file(&f.path, &mut v).await?;
-        info!("download_mem: {} B", v.len());
-        Ok(v)
+        download_mem(&self.bot, &f).await
     }
 
+    // Copied even when local, as the server owns its file.
     async fn download_tmp(&self, f: TgFile) -> AnyResult<TempPath> {
+        if let Some(p) = local_path(&f) {
+            let path = temp_path()?;
+            tokio::fs::copy(p, &path).await?;
+            info!("copied {} B from {}", f.size, p.display());
+            return Ok(path);
+        }
         let (path, mut tmp) = temp_file().await?;
-        self.bot.download_file(&f.path, &mut tmp).await?;
+        download_within(self.bot.download_file(&f.path, &mut tmp)).await?;
         drop(tmp);
         info!("download_tmp: {} B", f.size);
         Ok(path)
     }
 
-    async fn handle_image(&self, f: TgFile) -> AnyResult<Blob> {
-        let v = self.download_mem(f).await?;
-        process_image(v).await
+    // Unless it was downloaded already to be looked at.
+    async fn fetch_mem(&self, f: TgFile, data: Option<Vec<u8>>) -> AnyResult<Vec<u8>> {
+        match data {
+            Some(v) => Ok(v),
+            None => self.download_mem(f).await,
+        }
+    }
+
+    async fn handle_image(&self, f: TgFile, data: Option<Vec<u8>>) -> AnyResult<()> {
+        let v = self.fetch_mem(f, data).await?;
+        self.convert_image(v).await
     }
 
-    async fn handle_video(&self, f: TgFile) -> AnyResult<Blob> {
-        let path = self.download_tmp(f).await?;
-        process_video(&path).await
+    async fn convert_image(&self, v: Vec<u8>) -> AnyResult<()> {
+        if let Some(grid) = self.edit.grid {
+            // Rendered large, as each cell still has to fill a sticker.
+            let side = self.edit.target().side() * grid.0.max(grid.1);
+            let img = load_image(v, side).await?;
+            return self
+                .send_raw(grid::convert(img, grid, &self.edit).await?)
+                .await;
+        }
+        if psd::sniff(&v) {
+            return self.handle_psd(v).await;
+        }
+        if let Some(grid) = self.too_long(&v) {
+            return self.offer_aspect(v, grid).await;
+        }
+        let b = self
+            .dedup("image", worker::process_image(v, &self.edit))
+            .await?;
+        if let Some(e) = self.find_duplicate(&b) {
+            return self.offer_duplicate(b, e).await;
+        }
+        self.send_image(b).await
     }
 
-    async fn handle_sticker(&self, f: TgFile, fmt: StickerFormat) -> AnyResult<()> {
-        match fmt {
-            StickerFormat::Raster => {
-                self.send_raw(Blob::new(self.download_mem(f).await?, "webp"))
-                    .await
+    // How it would be split, for pictures converted without options that are too long a sticker.
+    fn too_long(&self, v: &[u8]) -> Option<(u32, u32)> {
+        if !self.edit.is_passthrough() {
+            return None;
+        }
+        let (w, h) = ImageReader::new(Cursor::new(v))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+        aspect::split(w, h)
+    }
+
+    async fn offer_aspect(&self, v: Vec<u8>, grid: (u32, u32)) -> AnyResult<()> {
+        let pieces = grid.0 * grid.1;
+        let offer = aspect::Offer {
+            data: v,
+            edit: self.edit.clone(),
+            base: self.base.clone(),
+            grid,
+        };
+        let token = aspect::OFFERS.insert(self.msg.clone(), self.sender(), offer);
+        let buttons: Vec<_> = [
+            (aspect::Choice::Crop, Msg::new("aspect-crop")),
+            (
+                aspect::Choice::Split,
+                Msg::new("aspect-split").arg("n", pieces),
+            ),
+            (aspect::Choice::AsIs, Msg::new("aspect-as-is")),
+        ]
+        .into_iter()
+        .map(|(choice, label)| {
+            InlineKeyboardButton::callback(self.tr(&label), aspect::callback_data(token, choice))
+        })
+        .collect();
+        let m = self
+            .bot
+            .send_message(self.msg.chat.id, self.tr(&Msg::new("aspect-advice")))
+            .reply_markup(InlineKeyboardMarkup::new(
+                buttons.into_iter().map(|b| vec![b]),
+            ))
+            .reply_to_message_id(self.msg.id)
+            .allow_sending_without_reply(true)
+            .await?;
+        self.responses.record(m.id);
+        Ok(())
+    }
+
+    async fn handle_aspect(mut self, o: aspect::Offer, choice: aspect::Choice) -> Option<Msg> {
+        self.edit = o.edit;
+        self.base = o.base;
+        self.input = "image";
+        let r = match choice {
+            aspect::Choice::Crop => {
+                self.edit.smartcrop = true;
+                self.convert_image(o.data).await
             }
-            StickerFormat::Animated => {
-                let path = self.download_tmp(f).await?;
-                self.send_raw(tgs_to_gif(&path).await?).await
+            aspect::Choice::Split => {
+                self.edit.grid = Some(o.grid);
+                self.convert_image(o.data).await
             }
-            StickerFormat::Video => {
-                let data = bytes::Bytes::from(self.download_mem(f).await?);
-                let (r1, r2) = join!(self.send_raw(Blob::new(data.clone(), "webm")), async move {
-                    self.send_raw(ffmpeg_to_gif(&data).await?).await
-                });
-                r1?;
-                r2
+            aspect::Choice::AsIs => self.encode_image(o.data).await,
+        };
+        if let Err(e) = r {
+            error!("handle_aspect: {:?}", e);
+            alert::failure(&self.context("aspect"), &e);
+            return Some(self.error_message(&e));
+        }
+        None
+    }
+
+    async fn encode_image(&self, v: Vec<u8>) -> AnyResult<()> {
+        let b = self
+            .dedup("image", worker::process_image(v, &self.edit))
+            .await?;
+        self.send_image(b).await
+    }
+
+    fn find_duplicate(&self, b: &Blob) -> Option<history::Entry> {
+        history::similar(bot_id(&self.bot), self.actor()?, b.hash?)
+    }
+
+    async fn offer_duplicate(&self, b: Blob, e: history::Entry) -> AnyResult<()> {
+        let age = e.age();
+        let offer = duplicates::Offer {
+            blob: b,
+            edit: self.edit.clone(),
+            base: self.base.clone(),
+            file_id: e.file_id,
+        };
+        let token = duplicates::OFFERS.insert(self.msg.clone(), self.sender(), offer);
+        let buttons: Vec<_> = [
+            (duplicates::Choice::Resend, "dup-resend"),
+            (duplicates::Choice::Convert, "dup-convert"),
+        ]
+        .into_iter()
+        .map(|(choice, label)| {
+            InlineKeyboardButton::callback(
+                self.tr(&Msg::new(label)),
+                duplicates::callback_data(token, choice),
+            )
+        })
+        .collect();
+        let m = self
+            .bot
+            .send_message(
+                self.msg.chat.id,
+                self.tr(&Msg::new("dup-found").arg("age", age)),
+            )
+            .reply_markup(InlineKeyboardMarkup::new([buttons]))
+            .reply_to_message_id(self.msg.id)
+            .allow_sending_without_reply(true)
+            .await?;
+        self.responses.record(m.id);
+        Ok(())
+    }
+
+    async fn handle_duplicate(
+        mut self,
+        o: duplicates::Offer,
+        choice: duplicates::Choice,
+    ) -> Option<Msg> {
+        self.edit = o.edit;
+        self.base = o.base;
+        self.input = "image";
+        let r = match choice {
+            duplicates::Choice::Resend => {
+                let r = self
+                    .bot
+                    .send_document(self.msg.chat.id, InputFile::file_id(o.file_id))
+                    .reply_to_message_id(self.msg.id)
+                    .allow_sending_without_reply(true)
+                    .await;
+                match r {
+                    Ok(m) => {
+                        self.responses.record(m.id);
+                        Ok(())
+                    }
+                    Err(e) => Err(BotError::from(e).into()),
+                }
             }
+            duplicates::Choice::Convert => self.send_image(o.blob).await,
+        };
+        if let Err(e) = r {
+            error!("handle_duplicate: {:?}", e);
+            alert::failure(&self.context("duplicate"), &e);
+            return Some(self.error_message(&e));
         }
+        None
     }
 
-    async fn handle_media(&self, file_id: &str, op: Op) -> AnyResult<()> {
-        let f = self.bot.get_file(file_id).await?;
-        if f.size > MAX_SIZE {
-            bail!("File too big")
+    async fn send_image(&self, b: Blob) -> AnyResult<()> {
+        if self.edit.target() == Target::Whatsapp && !self.edit.thumb {
+            let tray = export::tray_icon(&decode_image(b.bytes().await?.to_vec())?)?;
+            return self.send_whatsapp(b, tray).await;
         }
-        match op {
-            Op::Image => self.send(self.handle_image(f).await?).await,
-            Op::Video => self.send(self.handle_video(f).await?).await,
-            Op::Sticker(fmt) => self.handle_sticker(f, fmt).await,
+        #[cfg(feature = "emoji")]
+        if self.edit.target() == Target::Sticker
+            && !self.edit.thumb
+            && self.media().sticker().is_none()
+            && emoji::is_enabled()
+        {
+            let img = decode_image(b.bytes().await?.to_vec())?;
+            self.send(b).await?;
+            self.suggest_emoji(img).await;
+            return Ok(());
         }
+        self.send(b).await
     }
 
-    async fn send_raw(&self, b: Blob) -> AnyResult<()> {
-        let f = self.get_input_file(b);
-        let mut p = self.bot.send_document(self.msg.chat.id, f);
-        if let Some(s) = self.caption {
-            p.caption = Some(s.to_string());
-        }
-        p.reply_to_message_id = Some(self.msg.id);
-        p.allow_sending_without_reply = Some(true);
-        p.disable_content_type_detection = Some(true);
-        if let Err(e) = p.await {
-            error!("send_document: {}", e);
-            bail!("Failed to send file.")
+    // Offered under the result just sent, if the model has any.
+    #[cfg(feature = "emoji")]
+    async fn suggest_emoji(&self, img: DynamicImage) {
+        let Some(result) = self.responses.last() else {
+            return;
+        };
+        let suggested = emoji::suggest(img).await;
+        if suggested.is_empty() {
+            return;
+        }
+        let buttons: Vec<_> = suggested
+            .iter()
+            .map(|e| InlineKeyboardButton::callback(e.clone(), emoji::callback_data(e)))
+            .collect();
+        let r = self
+            .bot
+            .send_message(self.msg.chat.id, self.tr(&Msg::new("emoji-suggest")))
+            .reply_to_message_id(result)
+            .reply_markup(InlineKeyboardMarkup::new([buttons]))
+            .await;
+        match r {
+            Ok(m) => self.responses.record(m.id),
+            Err(e) => warn!("send_message: {:?}", e),
         }
-        Ok(())
     }
 
-    async fn send(&self, b: Blob) -> AnyResult<()> {
-        let f = self.get_input_file(b);
-        let mut p = self.bot.send_document(self.msg.chat.id, f);
-        if let Some(s) = self.caption {
-            p.caption = Some(s.to_string());
-        }
-        p.reply_to_message_id = Some(self.msg.id);
-        p.allow_sending_without_reply = Some(true);
-        if let Err(e) = p.await {
-            error!("send_document: {}", e);
-            bail!("Failed to send file.")
+    // Sends the flattened image, then offers the layers when there is more than one.
+    async fn handle_psd(&self, data: Vec<u8>) -> AnyResult<()> {
+        let names = psd::layer_names(&data)?;
+        let b = worker::process_image(data.clone(), &self.edit).await?;
+        self.send_image(b).await?;
+        if names.len() < 2 {
+            return Ok(());
         }
+        let doc = psd::Document {
+            data: Arc::new(data),
+            edit: self.edit.clone(),
+            base: self.base.clone(),
+        };
+        let token = psd::DOCUMENTS.insert(self.msg.clone(), self.sender(), doc);
+        let buttons: Vec<_> = names
+            .iter()
+            .enumerate()
+            .take(psd::MAX_LAYERS)
+            .map(|(i, name)| {
+                let label = if name.is_empty() {
+                    self.tr(&Msg::new("psd-layer").arg("n", i + 1))
+                } else {
+                    name.clone()
+                };
+                InlineKeyboardButton::callback(label, psd::callback_data(token, i))
+            })
+            .collect();
+        let m = self
+            .bot
+            .send_message(self.msg.chat.id, self.tr(&Msg::new("psd-layers")))
+            .reply_markup(InlineKeyboardMarkup::new(
+                buttons.chunks(2).map(|c| c.to_vec()),
+            ))
+            .reply_to_message_id(self.msg.id)
+            .allow_sending_without_reply(true)
+            .await?;
+        self.responses.record(m.id);
         Ok(())
     }
 
-    fn get_input_file(&self, blob: Blob) -> InputFile {
-        blob.into_input_file(self.base)
+    async fn handle_layer(mut self, doc: &psd::Document, i: usize) -> Option<Msg> {
+        self.edit = doc.edit.clone();
+        self.base = doc.base.clone();
+        info!("converting layer {} of psd", i);
+        let r = match psd::layer(&doc.data, i) {
+            Ok(img) => match process_decoded(img, &self.edit).await {
+                Ok(b) => self.send_image(b).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = r {
+            error!("handle_layer: {:?}", e);
+            alert::failure(&self.context("psd layer"), &e);
+            return Some(self.error_message(&e));
+        }
+        None
     }
 
-    async fn handler(mut self) -> &'static str {
-        let ch = &self.msg.chat;
-        info!(
-            "from {} {} (@{} {})",
-            ch.first_name().unwrap_or(""),
-            ch.last_name().unwrap_or(""),
-            ch.username().unwrap_or(""),
-            ch.id.0
-        );
-        let msg = &self.msg;
-        let mut op = Op::Image;
-        let (file_id, size, file_name) = if let Some(doc) = msg.document() {
-            info!(
-                "got document {} of {} bytes",
-                doc.file_name.as_deref().unwrap_or(""),
-                doc.file.size
-            );
-            if let Some(s) = &doc.file_name {
-                if s.ends_with(".gif") {
-                    op = Op::Video;
+    async fn send_video(&self, b: Blob, file: &Path) -> AnyResult<()> {
+        // Not a sticker, so there is nothing to report on.
+        if self.edit.webp {
+            return self.send_raw(b).await;
+        }
+        if self.edit.target() == Target::Whatsapp {
+            let tray = export::video_tray_icon(file, &self.edit).await?;
+            return self.send_whatsapp(b, tray).await;
+        }
+        self.send(b).await
+    }
+
+    // Sends the sticker itself, followed by a pack of it to import into WhatsApp.
+    async fn send_whatsapp(&self, b: Blob, tray: Blob) -> AnyResult<()> {
+        let title = self
+            .base
+            .as_deref()
+            .and_then(|s| s.rsplit_once('.'))
+            .map_or("Stickers", |(s, _)| s);
+        let author = self
+            .msg
+            .from()
+            .map_or_else(|| "sticker-bot".to_owned(), |u| u.full_name());
+        let pack = export::wastickers(&b, &tray, title, &author).await?;
+        self.send(b).await?;
+        self.send_raw(pack).await
+    }
+
+    async fn handle_video(&mut self, f: TgFile) -> AnyResult<()> {
+        let path = self.download_tmp(f).await?;
+        self.handle_video_file(path).await
+    }
+
+    async fn handle_video_file(&mut self, path: TempPath) -> AnyResult<()> {
+        let info = probe::probe(&path).await.unwrap_or_default();
+        info!("input: {:?}", info);
+        if self.edit.thumb {
+            return self
+                .send(thumb::video(&path, &info, &self.edit).await?)
+                .await;
+        }
+        if let Some(n) = self.edit.frames {
+            for img in frames::extract(&path, &info, n).await? {
+                let b = process_decoded(img, &self.edit).await?;
+                self.send_image(b).await?;
+            }
+            return Ok(());
+        }
+        if self.edit.is_empty() {
+            // Leave some slack for container rounding.
+            if let Some(d) = info.duration.filter(|&d| d > MAX_DURATION + 0.1) {
+                let cfg = config::get();
+                if !cfg.auto_speedup || d > MAX_DURATION * cfg.max_auto_speed {
+                    return self.ask_segment(path, info).await;
                 }
+                self.speed_up(d);
             }
-            (&doc.file.id, doc.file.size, doc.file_name.as_ref())
-        } else if let Some(sizes) = msg.photo() {
-            let ph = sizes
-                .iter()
-                .find(|ph| ph.width >= 512 || ph.height >= 512)
-                .unwrap_or_else(|| sizes.last().unwrap());
+        }
+        self.cap_fps(&info);
+        self.convert_video(path).await
+    }
+
+    // The video is kept for /retry when ffmpeg runs out of time.
+    async fn convert_video(&self, path: TempPath) -> AnyResult<()> {
+        let b = match self.dedup("video", process_video(&path, &self.edit)).await {
+            Ok(b) => b,
+            Err(e) => return Err(self.stash_on_timeout(e, path)),
+        };
+        self.send_video(b, &path).await
+    }
+
+    fn stash_on_timeout(&self, e: anyhow::Error, path: TempPath) -> anyhow::Error {
+        let Some(&BotError::Timeout { secs }) = e.downcast_ref::<BotError>() else {
+            return e;
+        };
+        let s = Stashed::new(self.msg.clone(), path, self.base.clone(), self.edit.clone());
+        stash::insert(bot_id(&self.bot), self.msg.chat.id, self.sender(), s);
+        BotError::RetryableTimeout { secs }.into()
+    }
+
+    async fn handle_retry(mut self, s: &Stashed, lossy: bool) -> Option<Msg> {
+        self.edit = s.edit.clone();
+        self.edit.retry = true;
+        self.edit.lossy |= lossy;
+        self.base = s.base.clone();
+        info!("retrying with {:?}", self.edit);
+        let r = match process_video(&s.path, &self.edit).await {
+            Ok(b) => self.send_video(b, &s.path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = r {
+            error!("handle_retry: {:?}", e);
+            alert::failure(&self.context("retry"), &e);
+            return Some(self.error_message(&e));
+        }
+        None
+    }
+
+    fn cap_fps(&mut self, info: &VideoInfo) {
+        self.edit.cap_fps(info);
+    }
+
+    fn speed_up(&mut self, duration: f64) {
+        let speed = duration / MAX_DURATION;
+        info!("speeding up {:.1} s video by {:.2}x", duration, speed);
+        self.edit.speed = Some(speed);
+        self.caption = Some(
+            self.tr(&Msg::new("sped-up")
+                .arg("speed", format!("{:.2}", speed))
+                .arg("max", MAX_DURATION)),
+        );
+    }
+
+    async fn ask_segment(&self, path: TempPath, info: VideoInfo) -> AnyResult<()> {
+        let duration = info.duration.unwrap_or_default();
+        let p = Pending {
+            path,
+            info,
+            base: self.base.clone(),
+            edit: self.edit.clone(),
+        };
+        let token = pending::PENDING.insert(self.msg.clone(), self.sender(), p);
+        let buttons: Vec<_> = Segment::ALL
+            .iter()
+            .map(|&(seg, label)| {
+                InlineKeyboardButton::callback(
+                    self.tr(&Msg::new(label)),
+                    pending::callback_data(token, seg),
+                )
+            })
+            .collect();
+        let text = self.tr(&Msg::new("ask-segment")
+            .arg("duration", format!("{:.1}", duration))
+            .arg("max", MAX_DURATION));
+        let m = self
+            .bot
+            .send_message(self.msg.chat.id, text)
+            .reply_markup(InlineKeyboardMarkup::new(
+                buttons.chunks(2).map(|c| c.to_vec()),
+            ))
+            .reply_to_message_id(self.msg.id)
+            .allow_sending_without_reply(true)
+            .await?;
+        self.responses.record(m.id);
+        Ok(())
+    }
+
+    async fn handle_segment(mut self, p: &Pending, seg: Segment) -> Option<Msg> {
+        let d = p.info.duration.unwrap_or_default();
+        let trim = |a: f64| Some((a, a + MAX_DURATION));
+        self.edit = p.edit.clone();
+        match seg {
+            Segment::First => self.edit.trim = trim(0.0),
+            Segment::Middle => self.edit.trim = trim((d - MAX_DURATION) / 2.0),
+            Segment::Last => self.edit.trim = trim(d - MAX_DURATION),
+            Segment::Fit => self.speed_up(d),
+        }
+        self.cap_fps(&p.info);
+        self.base = p.base.clone();
+        info!("converting {:?} of {:.1} s video", seg, d);
+        let r = match process_video(&p.path, &self.edit).await {
+            Ok(b) => self.send_video(b, &p.path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = r {
+            error!("handle_segment: {:?}", e);
+            alert::failure(&self.context("video segment"), &e);
+            return Some(self.error_message(&e));
+        }
+        None
+    }
+
+    async fn handle_sticker(
+        &self,
+        f: TgFile,
+        fmt: StickerFormat,
+        data: Option<Vec<u8>>,
+    ) -> AnyResult<()> {
+        match fmt {
+            StickerFormat::Raster => {
+                self.send_raw(Blob::new(self.fetch_mem(f, data).await?, "webp"))
+                    .await
+            }
+            StickerFormat::Animated => {
+                let b = self
+                    .cached_gif(async move {
+                        let path = self.download_tmp(f).await?;
+                        tgs_to_gif(&path).await
+                    })
+                    .await?;
+                self.send_raw(b).await
+            }
+            StickerFormat::Video => self.handle_video_sticker(f).await,
+        }
+    }
+
+    // Replies with the webm itself and a GIF of it. The GIF is made while the download is still
+    // going when the container can be piped, and from a temporary file otherwise.
+    async fn handle_video_sticker(&self, f: TgFile) -> AnyResult<()> {
+        let s = self.settings();
+        if s.mp4.is_some() {
+            // From a temporary file, as a pipe only feeds one encoder.
+            let path = self.download_tmp(f).await?;
+            return self.video_sticker_file(&path).await;
+        }
+        if s.no_gif {
+            let webm = Blob::new(self.download_mem(f).await?, "webm");
+            return self.send_raw(webm).await;
+        }
+        let cached = match self.gif_key() {
+            Some(key) => cache::get(&key, "gif").await,
+            None => None,
+        };
+        if let Some(gif) = cached {
+            let webm = Blob::new(self.download_mem(f).await?, "webm");
+            let (r1, r2) = join!(self.send_raw(webm), self.send_raw(gif));
+            r1?;
+            return r2;
+        }
+        if let Some(p) = local_path(&f) {
+            let data = tokio::fs::read(p).await?;
+            return self.send_video_sticker(data, p).await;
+        }
+        let mut stream = self.bot.download_file_stream(&f.path);
+        let mut data = Vec::with_capacity(f.size as usize);
+        let head = download_within(async { stream.next().await.transpose() })
+            .await?
+            .unwrap_or_default();
+        data.extend_from_slice(&head);
+        if !pipe_safe(&head) {
+            download_within(async {
+                while let Some(b) = stream.next().await {
+                    data.extend_from_slice(&b?);
+                }
+                Ok::<_, anyhow::Error>(())
+            })
+            .await?;
+            info!("download_mem: {} B, not piped", data.len());
+            let (path, mut tmp) = temp_file().await?;
+            tmp.write_all(&data).await?;
+            drop(tmp);
+            return self.send_video_sticker(data, &path).await;
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        tx.send(head).ok();
+        let download = async move {
+            download_within(async {
+                while let Some(b) = stream.next().await {
+                    let b = b?;
+                    data.extend_from_slice(&b);
+                    // Left unread when the GIF is shared with an identical request.
+                    tx.send(b).ok();
+                }
+                Ok::<_, anyhow::Error>(())
+            })
+            .await?;
+            drop(tx);
+            info!("download_mem: {} B, piped", data.len());
+            self.send_raw(Blob::new(data, "webm")).await
+        };
+        let gif = async move {
+            let b = self.cached_gif(ffmpeg_to_gif(Input::Pipe(rx))).await?;
+            self.send_raw(b).await
+        };
+        let (r1, r2) = join!(download, gif);
+        r1?;
+        r2
+    }
+
+    // What handle_video_sticker does with a file at hand, with an mp4 too when asked for.
+    async fn video_sticker_file(&self, path: &Path) -> AnyResult<()> {
+        let s = self.settings();
+        let data = tokio::fs::read(path).await?;
+        let rest = async {
+            if s.no_gif {
+                self.send_raw(Blob::new(data, "webm")).await
+            } else {
+                self.send_video_sticker(data, path).await
+            }
+        };
+        let Some(bg) = s.mp4 else {
+            return rest.await;
+        };
+        let mp4 = async { self.send_raw(ffmpeg_to_mp4(path, bg).await?).await };
+        let (r1, r2) = join!(rest, mp4);
+        r1?;
+        r2
+    }
+
+    async fn send_video_sticker(&self, data: Vec<u8>, path: &Path) -> AnyResult<()> {
+        let (r1, r2) = join!(self.send_raw(Blob::new(data, "webm")), async {
+            let b = self.cached_gif(ffmpeg_to_gif(Input::File(path))).await?;
+            self.send_raw(b).await
+        });
+        r1?;
+        r2
+    }
+
+    async fn handle_still(&self, f: TgFile, fmt: StickerFormat, t: f64) -> AnyResult<()> {
+        let b = match fmt {
+            StickerFormat::Raster => {
+                let img = decode_image(self.download_mem(f).await?)?;
+                Blob::new(export::png(&img)?, "png")
+            }
+            StickerFormat::Animated => {
+                let gif = tgs_to_gif(&self.download_tmp(f).await?).await?;
+                let (path, mut tmp) = temp_file().await?;
+                tmp.write_all(&gif.bytes().await?).await?;
+                drop(tmp);
+                frames::still(&path, t, false).await?
+            }
+            StickerFormat::Video => frames::still(&self.download_tmp(f).await?, t, true).await?,
+        };
+        self.send_raw(b).await
+    }
+
+    // Lottie is played through the frames of its GIF, which are retimed and reordered like those
+    // of any video.
+    async fn handle_replay(&mut self, f: TgFile, fmt: StickerFormat) -> AnyResult<()> {
+        let path = match fmt {
+            StickerFormat::Raster => bail!(BotError::StaticReplay),
+            StickerFormat::Animated => {
+                let gif = self
+                    .cached_gif(async move {
+                        let path = self.download_tmp(f).await?;
+                        tgs_to_gif(&path).await
+                    })
+                    .await?;
+                let (path, mut tmp) = temp_file().await?;
+                tmp.write_all(&gif.bytes().await?).await?;
+                drop(tmp);
+                path
+            }
+            StickerFormat::Video => {
+                self.edit.vp9 = true;
+                self.download_tmp(f).await?
+            }
+        };
+        let info = probe::probe(&path).await.unwrap_or_default();
+        self.cap_fps(&info);
+        self.convert_video(path).await
+    }
+
+    async fn handle_check(&self, f: TgFile, kind: StickerKind, ext: &str) -> AnyResult<()> {
+        let size = f.size as u64;
+        let info = match kind {
+            StickerKind::Static => probe::inspect_image(&self.download_mem(f).await?)?,
+            StickerKind::Animated => probe::inspect_tgs(&self.download_mem(f).await?)?,
+            StickerKind::Video => {
+                let path = self.download_tmp(f).await?;
+                let Some(info) = probe::probe(&path).await else {
+                    bail!(BotError::BadVideo)
+                };
+                info
+            }
+        };
+        self.reply_text(probe::check_report(
+            kind,
+            self.edit.target(),
+            ext,
+            &info,
+            size,
+        ))
+        .await
+    }
+
+    // Documents go by what they hold rather than by their names, which may lie, such as a .gif
+    // that is really an mp4.
+    async fn handle_document(&mut self, f: TgFile, op: Op, data: Option<Vec<u8>>) -> AnyResult<()> {
+        let path = match data {
+            Some(v) => {
+                let path = temp_path()?;
+                tokio::fs::write(&path, v).await?;
+                path
+            }
+            None => self.download_tmp(f).await?,
+        };
+        let mut head = [0; content::HEAD_SIZE];
+        let n = File::open(&path).await?.read(&mut head).await?;
+        let real = content::sniff(&head[..n]);
+        let claimed = self.base_ext().unwrap_or("").to_ascii_lowercase();
+        // Those named .tgs that are not gzipped Lottie are told so rather than decoded as images.
+        if real.map_or(claimed == "tgs", |c| c.ext == "tgs") {
+            if claimed != "tgs" {
+                self.note_mismatch(&claimed, "tgs");
+            }
+            self.set_source_kind(sources::Kind::Tgs);
+            return self.handle_tgs_file(path).await;
+        }
+        let op = match real {
+            Some(c) if c.video != matches!(op, Op::Video) => {
+                self.note_mismatch(&claimed, c.ext);
+                if c.video {
+                    self.set_source_kind(sources::Kind::Video);
+                    Op::Video
+                } else {
+                    self.set_source_kind(sources::Kind::Image);
+                    Op::Image
+                }
+            }
+            _ => op,
+        };
+        let ext = real.map_or(claimed.as_str(), |c| c.ext);
+        let sticker = self.is_sticker_file(&path, ext).await?;
+        match op {
+            Op::Video if !caps::get().ffmpeg => bail!(BotError::VideoUnavailable),
+            Op::Video if sticker => self.video_sticker_file(&path).await,
+            Op::Video => self.handle_video_file(path).await,
+            _ => {
+                if tokio::fs::metadata(&path).await?.len() > MAX_MEMORY_INPUT as u64 {
+                    bail!(BotError::TooLarge)
+                }
+                let data = tokio::fs::read(&path).await?;
+                if sticker {
+                    return self.send_raw(Blob::new(data, "webp")).await;
+                }
+                self.convert_image(data).await
+            }
+        }
+    }
+
+    fn note_mismatch(&mut self, claimed: &str, real: &str) {
+        warn!("document named .{} holds {}", claimed, real);
+        self.caption = Some(
+            self.tr(&Msg::new("content-mismatch")
+                .arg("claimed", claimed)
+                .arg("real", real)),
+        );
+    }
+
+    // So that replies to a document convert it the way it was.
+    fn set_source_kind(&mut self, kind: sources::Kind) {
+        if let Some(mut src) = self.source.take() {
+            src.kind = kind;
+            sources::insert(
+                bot_id(&self.bot),
+                self.msg.chat.id,
+                self.msg.id,
+                src.clone(),
+            );
+            self.source = Some(src);
+        }
+    }
+
+    // An animated sticker sent as a file is given back as the sticker would be, as a GIF, unless
+    // there is something to do with it, which is then done to the GIF.
+    async fn handle_tgs_file(&mut self, path: TempPath) -> AnyResult<()> {
+        probe::inspect_tgs(&tokio::fs::read(&path).await?)?;
+        let caps = caps::get();
+        if !caps.tgs_to_gif {
+            bail!(BotError::TgsUnavailable)
+        }
+        let gif = self.cached_gif(tgs_to_gif(&path)).await?;
+        if self.edit.is_passthrough() {
+            return self.send_raw(gif).await;
+        }
+        if !caps.ffmpeg {
+            bail!(BotError::VideoUnavailable)
+        }
+        let (path, mut tmp) = temp_file().await?;
+        tmp.write_all(&gif.bytes().await?).await?;
+        drop(tmp);
+        self.handle_video_file(path).await
+    }
+
+    // Whether a document is the file of a sticker, as some clients save them, that is valid as it
+    // is, so that it is given back as the sticker itself would be rather than converted again.
+    async fn is_sticker_file(&self, path: &Path, ext: &str) -> AnyResult<bool> {
+        let kind = match ext {
+            "webp" => StickerKind::Static,
+            "webm" => StickerKind::Video,
+            _ => return Ok(false),
+        };
+        let target = self.edit.target();
+        let size = tokio::fs::metadata(path).await?.len();
+        if !self.edit.is_passthrough() || size > kind.max_size(target) {
+            return Ok(false);
+        }
+        let info = match kind {
+            StickerKind::Video => probe::probe(path).await,
+            _ => probe::inspect_image(&tokio::fs::read(path).await?).ok(),
+        };
+        Ok(info.map_or(false, |v| {
+            probe::violations(kind, target, &v, size).is_empty()
+        }))
+    }
+
+    async fn handle_plan(&self, f: TgFile, op: Op) -> AnyResult<()> {
+        let p = match op {
+            Op::Image if self.base_ext() == Some("tgs") => {
+                plan::tgs(&self.download_tmp(f).await?, &self.edit).await?
+            }
+            Op::Image | Op::Sticker(StickerFormat::Raster) => {
+                plan::image(self.download_mem(f).await?, &self.edit).await?
+            }
+            Op::Video | Op::Sticker(StickerFormat::Video) => {
+                plan::video(&self.download_tmp(f).await?, &self.edit).await?
+            }
+            Op::Sticker(StickerFormat::Animated) => {
+                plan::tgs(&self.download_tmp(f).await?, &self.edit).await?
+            }
+            _ => bail!(BotError::PlanUsage),
+        };
+        self.reply_text(p.to_string()).await
+    }
+
+    async fn handle_zip(&mut self, f: TgFile) -> AnyResult<()> {
+        let data = self.download_mem(f).await?;
+        let max = match self.is_premium() {
+            true => batch::MAX_ENTRIES,
+            false => config::get().batch_entries,
+        };
+        let (b, summary) = batch::convert_zip(data, &self.edit, max).await?;
+        let summary: Vec<_> = summary.iter().map(|m| self.tr(m)).collect();
+        self.caption = Some(summary.join(" "));
+        self.send_raw(b).await
+    }
+
+    async fn handle_restore(&self, f: TgFile) -> AnyResult<()> {
+        if self.sender().is_none() {
+            bail!(BotError::NoOwner)
+        }
+        let msg = self.submit(jobs::Task::Restore { file_id: f.id });
+        self.reply_text(self.tr(&msg)).await
+    }
+
+    // Jobs on whole sets run in the background and reply when done.
+    fn submit(&self, task: jobs::Task) -> Msg {
+        let job = jobs::Job::new(&self.msg, self.sender(), self.lang, task);
+        match jobs::submit(&self.bot, job) {
+            Ok(()) => Msg::new("job-queued"),
+            Err(e) => {
+                error!("jobs: {:?}", e);
+                Msg::new("error-generic")
+            }
+        }
+    }
+
+    async fn reply_text(&self, s: String) -> AnyResult<()> {
+        let m = self
+            .bot
+            .send_message(self.msg.chat.id, s)
+            .reply_to_message_id(self.msg.id)
+            .allow_sending_without_reply(true)
+            .await?;
+        self.responses.record(m.id);
+        Ok(())
+    }
+
+    // Refuses explicit input where the filter applies. Pictures and static stickers are looked at
+    // whole, and the rest by their thumbnail, without which they are let through.
+    async fn check_nsfw(&self, whole: Option<DynamicImage>) -> AnyResult<()> {
+        let img = match whole {
+            Some(img) => img,
+            None => {
+                let m = self.media();
+                let thumb = m
+                    .document()
+                    .and_then(|d| d.thumb.as_ref())
+                    .or_else(|| m.animation().and_then(|a| a.thumb.as_ref()))
+                    .or_else(|| m.sticker().and_then(|s| s.thumb.as_ref()));
+                let Some(thumb) = thumb else {
+                    return Ok(());
+                };
+                let t = self.bot.get_file(&thumb.file.id).await?;
+                decode_blocking(self.download_mem(t).await?).await?
+            }
+        };
+        if nsfw::is_explicit(img).await? {
+            bail!(BotError::Nsfw)
+        }
+        Ok(())
+    }
+
+    // Puts the text of a picture or a static sticker in the caption of the result, and its words
+    // among the tags.
+    async fn read_text(&mut self, img: DynamicImage) -> AnyResult<()> {
+        let text = ocr::read(img).await?;
+        let line = if text.is_empty() {
+            self.tr(&Msg::new("ocr-none"))
+        } else {
+            self.tags = ocr::tags(&text);
+            text
+        };
+        self.caption = Some(match self.caption.take() {
+            Some(c) => format!("{}\n{}", c, line),
+            None => line,
+        });
+        Ok(())
+    }
+
+    async fn handle_media(&mut self, file_id: &str, op: Op) -> AnyResult<()> {
+        let f = self.bot.get_file(file_id).await?;
+        if f.size > self.max_file_size() {
+            bail!(BotError::TooLarge)
+        }
+        if self.edit.check {
+            let (kind, ext) = match &op {
+                Op::Sticker(StickerFormat::Raster) => (StickerKind::Static, "webp"),
+                Op::Sticker(StickerFormat::Animated) => (StickerKind::Animated, "tgs"),
+                Op::Sticker(StickerFormat::Video) => (StickerKind::Video, "webm"),
+                _ => {
+                    let ext = self.base_ext().unwrap_or("");
+                    match StickerKind::from_ext(ext) {
+                        Some(kind) => (kind, ext),
+                        None => bail!(BotError::CheckUsage),
+                    }
+                }
+            };
+            return self.handle_check(f, kind, ext).await;
+        }
+        let caps = caps::get();
+        match op {
+            Op::Video
+            | Op::Sticker(StickerFormat::Video)
+            | Op::Still(StickerFormat::Video | StickerFormat::Animated, _)
+            | Op::Replay(_)
+                if !caps.ffmpeg =>
+            {
+                bail!(BotError::VideoUnavailable)
+            }
+            Op::Sticker(StickerFormat::Animated)
+            | Op::Still(StickerFormat::Animated, _)
+            | Op::Replay(StickerFormat::Animated)
+                if !caps.tgs_to_gif =>
+            {
+                bail!(BotError::TgsUnavailable)
+            }
+            _ => {}
+        }
+        if self.edit.plan {
+            return self.handle_plan(f, op).await;
+        }
+        if let Some(actor) = self.actor() {
+            quota::check(actor)?;
+        }
+        let filtered = nsfw::applies(&self.msg.chat, self.actor());
+        let raster = matches!(op, Op::Image | Op::Sticker(StickerFormat::Raster));
+        if self.edit.ocr && !raster {
+            bail!(BotError::OcrUsage)
+        }
+        // Pictures looked at before converting are only downloaded and decoded once.
+        let data = if raster && (self.edit.ocr || filtered && f.size <= MAX_MEMORY_INPUT) {
+            Some(self.download_mem(f.clone()).await?)
+        } else {
+            None
+        };
+        let mut img = match &data {
+            Some(v) if self.edit.ocr => Some(decode_blocking(v.clone()).await?),
+            Some(v) => decode_blocking(v.clone()).await.ok(),
+            None => None,
+        };
+        if filtered {
+            let whole = if self.edit.ocr {
+                img.clone()
+            } else {
+                img.take()
+            };
+            self.check_nsfw(whole).await?;
+        }
+        if let (true, Some(img)) = (self.edit.ocr, img) {
+            self.read_text(img).await?;
+        }
+        let r = match op {
+            Op::Image | Op::Video if self.media().document().is_some() => {
+                self.handle_document(f, op, data).await
+            }
+            Op::Image => self.handle_image(f, data).await,
+            Op::Video => self.handle_video(f).await,
+            Op::Sticker(fmt) => self.handle_sticker(f, fmt, data).await,
+            Op::Still(fmt, t) => self.handle_still(f, fmt, t).await,
+            Op::Replay(fmt) => self.handle_replay(f, fmt).await,
+            Op::Zip => self.handle_zip(f).await,
+            Op::Restore => self.handle_restore(f).await,
+        };
+        if let (Ok(()), Some(actor)) = (&r, self.actor()) {
+            quota::take(actor);
+        }
+        r
+    }
+
+    async fn send_raw(&self, b: Blob) -> AnyResult<()> {
+        self.finalize_send(b, true).await
+    }
+
+    async fn send(&self, b: Blob) -> AnyResult<()> {
+        self.finalize_send(b, false).await
+    }
+
+    async fn finalize_send(&self, b: Blob, raw: bool) -> AnyResult<()> {
+        let f = self.get_input_file(&b);
+        let report = if !raw && (self.edit.report || config::get().report) {
+            b.report(self.edit.target())
+        } else {
+            None
+        };
+        let note = b.note.as_ref().map(|m| self.tr(m));
+        let lines: Vec<_> = [self.caption.clone(), note, report]
+            .into_iter()
+            .flatten()
+            .collect();
+        let caption = (!lines.is_empty()).then(|| lines.join("\n"));
+        let r = retry::with_backoff("send_document", || {
+            let mut p = self.bot.send_document(self.msg.chat.id, f.clone());
+            p.caption = caption.clone();
+            p.reply_to_message_id = Some(self.msg.id);
+            p.allow_sending_without_reply = Some(true);
+            if raw {
+                p.disable_content_type_detection = Some(true);
+            }
+            p.send()
+        })
+        .await;
+        if let Ok(m) = &r {
+            self.responses.record(m.id);
+        }
+        if let (Ok(m), Some(s)) = (&r, &self.source) {
+            sources::insert(bot_id(&self.bot), m.chat.id, m.id, s.clone());
+        }
+        if let (Some(doc), Some(actor)) = (r.as_ref().ok().and_then(|m| m.document()), self.actor())
+        {
+            let emoji = self.media().sticker().and_then(|s| s.emoji.clone());
+            let hash = b.hash.filter(|_| self.input == "image");
+            history::record(
+                bot_id(&self.bot),
+                actor,
+                self.input,
+                b.ext,
+                doc.file.id.clone(),
+                emoji.into_iter().chain(self.tags.iter().cloned()).collect(),
+                hash,
+            );
+        }
+        if let Err(e) = r {
+            error!("send_document: {}", e);
+            if retry::is_too_large(&e) {
+                bail!(BotError::ResultTooBig)
+            }
+            bail!(BotError::from(e))
+        }
+        Ok(())
+    }
+
+    fn get_input_file(&self, blob: &Blob) -> InputFile {
+        blob.input_file(self.base.as_deref())
+    }
+
+    fn base_ext(&self) -> Option<&str> {
+        self.base.as_deref()?.rsplit_once('.').map(|(_, ext)| ext)
+    }
+
+    // The user behind the message, if there is one.
+    fn sender(&self) -> Option<UserId> {
+        self.actor()?.user()
+    }
+
+    fn actor(&self) -> Option<Actor> {
+        Actor::of(&self.msg)
+    }
+
+    fn is_premium(&self) -> bool {
+        self.actor().map_or(false, quota::is_premium)
+    }
+
+    fn max_file_size(&self) -> u32 {
+        let cfg = config::get();
+        match cfg.premium_file_size {
+            Some(n) if self.is_premium() => n.max(cfg.max_file_size),
+            _ => cfg.max_file_size,
+        }
+    }
+
+    // Of the actor, as the chat's profile has them.
+    fn settings(&self) -> settings::Settings {
+        settings::merged(self.actor(), self.msg.chat.id)
+    }
+
+    // Who sent what, for alerts.
+    fn context(&self, what: &str) -> String {
+        let who = match (self.msg.from(), self.actor()) {
+            (Some(u), Some(Actor::User(id))) => format!("{} ({})", u.full_name(), id.0),
+            (_, Some(Actor::Chat(id))) => format!("chat {}", id.0),
+            _ => "unknown sender".to_owned(),
+        };
+        let mut s = format!("{} from {} in {}", what, who, self.msg.chat.id.0);
+        if let Some(base) = &self.base {
+            s.push_str(&format!(", {}", base));
+        }
+        s
+    }
+
+    fn error_message(&self, e: &anyhow::Error) -> Msg {
+        user_message(e, self.sender().map_or(false, access::is_admin))
+    }
+
+    fn tr(&self, m: &Msg) -> String {
+        m.tr(self.lang)
+    }
+
+    async fn command(&self, text: &str) -> Option<Msg> {
+        let mut args = text.split_whitespace();
+        let cmd = args.next().unwrap_or("");
+        let cmd = cmd.split('@').next().unwrap_or(cmd);
+        let id = match cmd {
+            "/start" | "/help" => {
+                let id = if cmd == "/start" { "start" } else { "help" };
+                if let Err(e) = self.send_help(id).await {
+                    error!("help: {:?}", e);
+                }
+                return None;
+            }
+            "/bench" => {
+                if !self.sender().map_or(false, access::is_admin) {
+                    return None;
+                }
+                let runs = args.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+                let results = bench::summary(&bench::run(runs).await);
+                return Some(Msg::new("bench").arg("results", results));
+            }
+            "/selftest" => {
+                if !self.sender().map_or(false, access::is_admin) {
+                    return None;
+                }
+                let checks = selftest::run().await;
+                let results = selftest::summary(&checks);
+                return Some(Msg::new("selftest").arg("results", results));
+            }
+            "/ban" | "/unban" => {
+                if !self.sender().map_or(false, access::is_admin) {
+                    return None;
+                }
+                let Some(id) = args.next().and_then(|s| s.parse().ok()) else {
+                    return Some(Msg::new("usage-ban"));
+                };
+                let r = if cmd == "/ban" {
+                    access::ban(UserId(id))
+                } else {
+                    access::unban(UserId(id))
+                };
+                match r {
+                    Ok(true) => "done",
+                    Ok(false) => "nothing-changed",
+                    Err(e) => {
+                        error!("{}: {}", cmd, e);
+                        "ban-save-failed"
+                    }
+                }
+            }
+            "/reload" => {
+                if !self.sender().map_or(false, access::is_admin) {
+                    return None;
+                }
+                return Some(match config::reload() {
+                    Ok(kept) => {
+                        worker::retire();
+                        if kept.is_empty() {
+                            Msg::new("reloaded")
+                        } else {
+                            Msg::new("reloaded-restart").arg("vars", kept.join(", "))
+                        }
+                    }
+                    Err(e) => {
+                        error!("/reload: {}", e);
+                        Msg::new("reload-failed").arg("error", e)
+                    }
+                });
+            }
+            "/tier" => {
+                if !self.sender().map_or(false, access::is_admin) {
+                    return None;
+                }
+                let id = args.next().and_then(|s| s.parse().ok());
+                let tier = args.next().and_then(|s| s.parse().ok());
+                let (Some(id), Some(tier)) = (id, tier.filter(|&t| t < quota::tiers())) else {
+                    let max = quota::tiers().saturating_sub(1);
+                    return Some(Msg::new("usage-tier").arg("max", max));
+                };
+                match quota::set_tier(Actor::User(UserId(id)), tier) {
+                    Ok(()) => "done",
+                    Err(e) => {
+                        error!("/tier: {}", e);
+                        "settings-save-failed"
+                    }
+                }
+            }
+            "/nsfw" => {
+                if !self.sender().map_or(false, access::is_admin) {
+                    return None;
+                }
+                let chat = args.next().and_then(|s| s.parse().ok());
+                let on = match args.next() {
+                    Some("on") => Some(true),
+                    Some("off") => Some(false),
+                    Some("default") => None,
+                    _ => return Some(Msg::new("usage-nsfw")),
+                };
+                let Some(chat) = chat else {
+                    return Some(Msg::new("usage-nsfw"));
+                };
+                match nsfw::set_override(chat, on) {
+                    Ok(()) => "done",
+                    Err(e) => {
+                        error!("/nsfw: {}", e);
+                        "settings-save-failed"
+                    }
+                }
+            }
+            "/quota" => return Some(quota::status(self.actor()?)),
+            "/donate" => {
+                let Some(stars) = config::get().donate_stars else {
+                    return Some(Msg::new("payments-off"));
+                };
+                let max = payments::MAX_STARS;
+                let stars = match args.next().map(str::parse::<u32>) {
+                    None => stars,
+                    Some(Ok(n)) if (1..=max).contains(&n) => n,
+                    Some(_) => return Some(Msg::new("usage-donate").arg("max", max)),
+                };
+                return self.send_invoice(payments::Payload::Donation, stars).await;
+            }
+            "/premium" => {
+                let Some(stars) = config::get().premium_stars else {
+                    return Some(Msg::new("payments-off"));
+                };
+                let days = config::get().premium_days;
+                return self
+                    .send_invoice(payments::Payload::Premium { days }, stars)
+                    .await;
+            }
+            "/pad" => "help-pad",
+            "/check" => "help-check",
+            "/plan" => "help-plan",
+            "/target" => {
+                let actor = self.actor()?;
+                let Some(target) = args.next().and_then(Target::parse) else {
+                    return Some(Msg::new("usage-target"));
+                };
+                self.save_settings(cmd, actor, |s| s.target = target)
+            }
+            "/quality" => {
+                let actor = self.actor()?;
+                let quality = match args.next() {
+                    Some("off" | "lossless") => None,
+                    Some(s) => match s.parse::<u8>().ok().filter(|q| (1..=100).contains(q)) {
+                        Some(q) => Some(q),
+                        None => return Some(Msg::new("usage-quality")),
+                    },
+                    None => return Some(Msg::new("usage-quality")),
+                };
+                self.save_settings(cmd, actor, |s| s.quality = quality)
+            }
+            "/lang" => {
+                let actor = self.actor()?;
+                let lang = match args.next().map(|s| (s, i18n::supported(s))) {
+                    Some(("auto", _)) => None,
+                    Some((_, Some(l))) => Some(l.to_owned()),
+                    _ => return Some(Msg::new("usage-lang").arg("langs", i18n::names())),
+                };
+                self.save_settings(cmd, actor, |s| s.lang = lang)
+            }
+            "/upscale" => {
+                let actor = self.actor()?;
+                let Some(up) = args.next().and_then(Upscaler::parse) else {
+                    return Some(Msg::new("usage-upscale"));
+                };
+                self.save_settings(cmd, actor, |s| s.upscale = up)
+            }
+            "/nogif" => {
+                let actor = self.actor()?;
+                let no_gif = !settings::get(actor).no_gif;
+                match self.save_settings(cmd, actor, |s| s.no_gif = no_gif) {
+                    "done" if no_gif => "gif-off",
+                    "done" => "gif-on",
+                    id => id,
+                }
+            }
+            "/mp4" => {
+                let actor = self.actor()?;
+                let mp4 = match args.next() {
+                    Some("off") => None,
+                    Some(s) => match options::parse_color(s) {
+                        Some(c) => Some(c),
+                        None => return Some(Msg::new("usage-mp4")),
+                    },
+                    None => Some(WHITE),
+                };
+                match self.save_settings(cmd, actor, |s| s.mp4 = mp4) {
+                    "done" if mp4.is_some() => "mp4-on",
+                    "done" => "mp4-off",
+                    id => id,
+                }
+            }
+            "/watermark" => {
+                let actor = self.actor()?;
+                if !config::get().user_watermarks || !text::is_available() {
+                    return Some(Msg::new("watermark-unavailable"));
+                }
+                let text = args.collect::<Vec<_>>().join(" ");
+                let mark = match text.as_str() {
+                    "" => return Some(Msg::new("usage-watermark")),
+                    "off" => None,
+                    s if s.chars().count() > watermark::MAX_TEXT_LEN => {
+                        return Some(Msg::new("usage-watermark"))
+                    }
+                    s => Some(s.to_owned()),
+                };
+                self.save_settings(cmd, actor, |s| s.watermark = mark)
+            }
+            "/pixel" => "help-pixel",
+            "/filter" => "help-filter",
+            "/bg" => "help-bg",
+            "/smartcrop" => "help-smartcrop",
+            "/grid" => "help-grid",
+            "/frames" => "help-frames",
+            "/emoji" => "help-emoji",
+            "/discord" => "help-discord",
+            "/whatsapp" => "help-whatsapp",
+            "/signal" => "help-signal",
+            "/webp" => "help-webp",
+            "/thumb" => "help-thumb",
+            "/convert" => "help-convert",
+            "/cutout" => "help-cutout",
+            "/ocr" => "help-ocr",
+            "/still" => "help-still",
+            "/speed" | "/slow" | "/fast" => "help-speed",
+            "/reverse" | "/boomerang" => "help-reverse",
+            "/restore" => "help-restore",
+            "/archive" => {
+                let Some(name) = args.next().and_then(archive::parse_link) else {
+                    return Some(Msg::new("usage-archive"));
+                };
+                let name = name.to_owned();
+                return Some(self.submit(jobs::Task::Archive { name }));
+            }
+            "/preset" => return self.preset(args),
+            "/chatsettings" => return self.chat_settings(args).await,
+            "/export" => return self.export().await,
+            "/forgetme" => {
+                let actor = self.actor()?;
+                if args.next() != Some("confirm") {
+                    return Some(Msg::new("forgetme-confirm"));
+                }
+                match userdata::forget(actor) {
+                    Ok(()) => "forgot",
+                    Err(e) => {
+                        error!("/forgetme: {:?}", e);
+                        "settings-save-failed"
+                    }
+                }
+            }
+            "/delete" => {
+                let (Some(r), Some(actor)) = (self.msg.reply_to_message(), self.actor()) else {
+                    return Some(Msg::new("usage-delete"));
+                };
+                let chat = self.msg.chat.id;
+                let Some(ids) = responses::take(bot_id(&self.bot), chat, r.id, actor) else {
+                    return Some(Msg::new("usage-delete"));
+                };
+                for id in ids.into_iter().chain([self.msg.id]) {
+                    if let Err(e) = self.bot.delete_message(chat, id).await {
+                        warn!("delete_message: {:?}", e);
+                    }
+                }
+                return None;
+            }
+            "/history" => return self.send_list(history::List::History).await,
+            "/favs" => return self.send_list(history::List::Favorites).await,
+            "/find" => {
+                let actor = self.actor()?;
+                let words = history::parse_tags(args);
+                let found =
+                    history::search(bot_id(&self.bot), actor, &words, 0, history::FIND_LIMIT);
+                if found.is_empty() {
+                    return Some(Msg::new("find-none"));
+                }
+                let (text, keyboard) = history::render_found(&found, self.lang);
+                let r = self
+                    .bot
+                    .send_message(self.msg.chat.id, text)
+                    .reply_markup(keyboard);
+                if let Err(e) = r.await {
+                    error!("find: {:?}", e);
+                }
+                return None;
+            }
+            // Tagging a result saves it too, as the tags are only of use to find it again.
+            "/fav" | "/tag" => {
+                let actor = self.actor()?;
+                let usage = if cmd == "/tag" {
+                    "usage-tag"
+                } else {
+                    "usage-fav"
+                };
+                let Some(doc) = self.msg.reply_to_message().and_then(|r| r.document()) else {
+                    return Some(Msg::new(usage));
+                };
+                let tags = history::parse_tags(args);
+                if cmd == "/tag" && tags.is_empty() {
+                    return Some(Msg::new(usage));
+                }
+                let name = doc.file_name.as_deref().unwrap_or("");
+                let ext = name.rsplit_once('.').map_or("file", |(_, ext)| ext);
+                if !history::fav(bot_id(&self.bot), actor, ext, doc.file.id.clone(), tags) {
+                    let max = history::List::Favorites.max_entries();
+                    return Some(Msg::new("favs-full").arg("max", max));
+                }
+                "fav-saved"
+            }
+            "/retry" => {
+                let s = match stash::take(bot_id(&self.bot), self.msg.chat.id, self.sender()) {
+                    Ok(s) => s,
+                    Err(e) => return Some(e.msg()),
+                };
+                let lossy = args.next() == Some("lossy");
+                let req = Request::new(s.msg.clone(), self.bot.clone());
+                return req.handle_retry(&s, lossy).await;
+            }
+            "/clone" => {
+                let Some(name) = args.next().and_then(archive::parse_link) else {
+                    return Some(Msg::new("usage-clone"));
+                };
+                let edit = match self.parse_edit(&args.collect::<Vec<_>>().join(" ")) {
+                    Ok(edit) => edit,
+                    Err(e) => return Some(e.msg()),
+                };
+                if self.sender().is_none() {
+                    return Some(BotError::NoOwner.msg());
+                }
+                let name = name.to_owned();
+                return Some(self.submit(jobs::Task::Clone { name, edit }));
+            }
+            _ => "send-media",
+        };
+        Some(Msg::new(id))
+    }
+
+    // With any preset named at the start of the caption expanded.
+    fn parse_edit(&self, caption: &str) -> Result<VideoEdit, BotError> {
+        let s = self.settings();
+        VideoEdit::parse(&presets::expand(caption, &s))
+    }
+
+    // Only to the private chat, as the history would be seen by the whole group otherwise.
+    async fn export(&self) -> Option<Msg> {
+        let actor = self.actor()?;
+        if !self.msg.chat.is_private() {
+            return Some(Msg::new("export-private"));
+        }
+        let data = match userdata::export(actor) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("/export: {:?}", e);
+                return Some(Msg::new("export-failed"));
+            }
+        };
+        let f = InputFile::memory(data).file_name("export.json");
+        let r = self
+            .bot
+            .send_document(self.msg.chat.id, f)
+            .reply_to_message_id(self.msg.id);
+        if let Err(e) = r.await {
+            error!("/export: {:?}", e);
+        }
+        None
+    }
+
+    // In Stars, for which there is no provider token.
+    async fn send_invoice(&self, payload: payments::Payload, stars: u32) -> Option<Msg> {
+        let (title, description) = payload.msgs();
+        let title = self.tr(&title);
+        let r = self
+            .bot
+            .send_invoice(
+                self.msg.chat.id,
+                title.clone(),
+                self.tr(&description),
+                payload.encode(),
+                "",
+                payments::CURRENCY,
+                [payments::price(title, stars)],
+            )
+            .await;
+        if let Err(e) = r {
+            error!("send_invoice: {:?}", e);
+            return Some(BotError::from(e).msg());
+        }
+        None
+    }
+
+    async fn send_list(&self, list: history::List) -> Option<Msg> {
+        let actor = self.actor()?;
+        let Some((text, keyboard)) = history::render(list, bot_id(&self.bot), actor, 0, self.lang)
+        else {
+            return Some(Msg::new(list.empty()));
+        };
+        let r = self
+            .bot
+            .send_message(self.msg.chat.id, text)
+            .reply_markup(keyboard);
+        if let Err(e) = r.await {
+            error!("{:?}: {:?}", list, e);
+        }
+        None
+    }
+
+    // Anonymous admins post as the chat itself, which is then theirs.
+    async fn is_chat_admin(&self) -> bool {
+        match self.actor() {
+            Some(Actor::Chat(id)) => id == self.msg.chat.id,
+            Some(Actor::User(id)) => match self.bot.get_chat_member(self.msg.chat.id, id).await {
+                Ok(m) => m.kind.is_privileged(),
+                Err(e) => {
+                    warn!("get_chat_member: {}", e);
+                    false
+                }
+            },
+            None => false,
+        }
+    }
+
+    async fn chat_settings(&self, mut args: std::str::SplitWhitespace<'_>) -> Option<Msg> {
+        let chat = self.msg.chat.id;
+        if self.msg.chat.is_private() {
+            return Some(Msg::new("chat-only"));
+        }
+        let Some(key) = args.next() else {
+            let p = settings::profile(chat);
+            if p == Default::default() {
+                return Some(Msg::new("chat-profile-empty"));
+            }
+            return Some(Msg::new("chat-profile").arg("profile", p));
+        };
+        if !self.is_chat_admin().await {
+            return Some(Msg::new("chat-admins-only"));
+        }
+        let mut p = settings::profile(chat);
+        let usage = || Some(Msg::new("usage-chatsettings"));
+        // "default" leaves it to each member again.
+        match (key, args.next()) {
+            ("reset", None) => p = Default::default(),
+            ("target", Some("default")) => p.target = None,
+            ("target", Some(t)) => match Target::parse(t) {
+                Some(t) => p.target = Some(t),
+                None => return usage(),
+            },
+            ("quality", Some("default")) => (p.quality, p.lossless) = (None, false),
+            ("quality", Some("lossless")) => (p.quality, p.lossless) = (None, true),
+            ("quality", Some(q)) => match q.parse::<u8>().ok().filter(|q| (1..=100).contains(q)) {
+                Some(q) => (p.quality, p.lossless) = (Some(q), false),
+                None => return usage(),
+            },
+            ("gif", Some("default")) => p.no_gif = None,
+            ("gif", Some("on")) => p.no_gif = Some(false),
+            ("gif", Some("off")) => p.no_gif = Some(true),
+            ("nsfw", Some("default")) => p.nsfw = None,
+            ("nsfw", Some("on")) => p.nsfw = Some(true),
+            ("nsfw", Some("off")) => p.nsfw = Some(false),
+            ("upscale", Some("default")) => p.upscale = None,
+            ("upscale", Some(u)) => match Upscaler::parse(u) {
+                Some(u) => p.upscale = Some(u),
+                None => return usage(),
+            },
+            _ => return usage(),
+        }
+        Some(Msg::new(match settings::update_profile(chat, |x| *x = p) {
+            Ok(()) => "done",
+            Err(e) => {
+                error!("/chatsettings: {}", e);
+                "settings-save-failed"
+            }
+        }))
+    }
+
+    fn preset(&self, mut args: std::str::SplitWhitespace<'_>) -> Option<Msg> {
+        let actor = self.actor()?;
+        let s = settings::get(actor);
+        let Some(name) = args.next() else {
+            let mut names: Vec<_> = presets::BUILTIN.iter().map(|&(n, _)| n).collect();
+            names.extend(s.presets.keys().map(String::as_str));
+            names.sort_unstable();
+            names.dedup();
+            return Some(Msg::new("presets").arg("names", names.join(", ")));
+        };
+        let options = args.collect::<Vec<_>>().join(" ");
+        // Those saved before names were checked can still be removed.
+        let removing = options == "off" && s.presets.contains_key(name);
+        if !presets::valid_name(name) && !removing {
+            return Some(Msg::new("usage-preset"));
+        }
+        let name = name.to_owned();
+        match options.as_str() {
+            "" => Some(match presets::lookup(&s, &name) {
+                Some(options) => Msg::new("preset").arg("name", name).arg("options", options),
+                None => Msg::new("no-preset").arg("name", name),
+            }),
+            "off" => {
+                if !s.presets.contains_key(&name) {
+                    return Some(Msg::new("no-preset").arg("name", name));
+                }
+                let id = self.save_settings("/preset", actor, |s| {
+                    s.presets.remove(&name);
+                });
+                Some(Msg::new(id))
+            }
+            _ => {
+                if options.len() > presets::MAX_OPTIONS_LEN {
+                    return Some(Msg::new("usage-preset"));
+                }
+                if let Err(e) = VideoEdit::parse(&options) {
+                    return Some(e.msg());
+                }
+                if !s.presets.contains_key(&name) && s.presets.len() >= presets::MAX_PRESETS {
+                    return Some(Msg::new("too-many-presets").arg("max", presets::MAX_PRESETS));
+                }
+                let id = self.save_settings("/preset", actor, |s| {
+                    s.presets.insert(name, options);
+                });
+                Some(Msg::new(id))
+            }
+        }
+    }
+
+    async fn send_help(&self, id: &'static str) -> AnyResult<()> {
+        self.bot
+            .send_message(self.msg.chat.id, self.tr(&Msg::new(id)))
+            .reply_markup(help::overview_keyboard())
+            .await?;
+        Ok(())
+    }
+
+    fn save_settings(
+        &self,
+        cmd: &str,
+        actor: Actor,
+        f: impl FnOnce(&mut settings::Settings),
+    ) -> &'static str {
+        match settings::update(actor, f) {
+            Ok(()) => "done",
+            Err(e) => {
+                error!("{}: {}", cmd, e);
+                "settings-save-failed"
+            }
+        }
+    }
+
+    async fn handler(mut self) -> Option<Msg> {
+        let ch = &self.msg.chat;
+        info!(
+            "from {} {} (@{} {})",
+            ch.first_name().unwrap_or(""),
+            ch.last_name().unwrap_or(""),
+            ch.username().unwrap_or(""),
+            ch.id.0
+        );
+        // Whoever was charged gets what they paid for.
+        if let (Some(p), Some(actor)) = (self.msg.successful_payment(), self.actor()) {
+            return Some(payments::paid(actor, p));
+        }
+        if !self.actor().map_or(false, access::is_allowed_actor) {
+            info!("ignoring disallowed sender {:?}", self.actor());
+            return None;
+        }
+        let msg = &self.msg;
+        let reply_source = msg
+            .text()
+            .and(msg.reply_to_message())
+            .and_then(|r| sources::get(bot_id(&self.bot), msg.chat.id, r.id));
+        // Media the bot has not seen, such as in a group or in saved messages, is taken from the
+        // message a command is replied to, so that it need not be forwarded first.
+        self.replied = reply_source.is_none()
+            && msg.text().map_or(false, |t| {
+                MEDIA_COMMANDS
+                    .iter()
+                    .chain(&REPLAY_COMMANDS)
+                    .any(|c| is_command(t, c))
+            })
+            && msg.reply_to_message().map_or(false, has_media);
+        let media = match msg.reply_to_message() {
+            Some(r) if self.replied => r,
+            _ => msg,
+        };
+        let caption = if self.replied {
+            msg.text()
+        } else {
+            msg.caption()
+        };
+        let mut op = Op::Image;
+        let (file_id, unique_id, size, file_name) = if let Some(doc) = media.document() {
+            info!(
+                "got document {} of {} bytes",
+                doc.file_name.as_deref().unwrap_or(""),
+                doc.file.size
+            );
+            if let Some(s) = &doc.file_name {
+                let lower = s.to_ascii_lowercase();
+                // Video sticker files are routed like GIFs, and those that are ready given back.
+                if s.ends_with(".gif") || lower.ends_with(".webm") {
+                    op = Op::Video;
+                } else if lower.ends_with(".zip") {
+                    let restore = caption.map_or(false, |c| is_command(c, "/restore"));
+                    op = if restore { Op::Restore } else { Op::Zip };
+                }
+            }
+            (
+                &doc.file.id,
+                Some(&doc.file.unique_id),
+                doc.file.size,
+                doc.file_name.as_ref(),
+            )
+        } else if let Some(sizes) = media.photo() {
+            let ph = sizes
+                .iter()
+                .find(|ph| ph.width >= 512 || ph.height >= 512)
+                .unwrap_or_else(|| sizes.last().unwrap());
             info!(
                 "got photo of {} x {}, {} B",
                 ph.width, ph.height, ph.file.size
             );
-            (&ph.file.id, ph.file.size, None)
-        } else if let Some(ani) = msg.animation() {
+            (&ph.file.id, Some(&ph.file.unique_id), ph.file.size, None)
+        } else if let Some(ani) = media.animation() {
             info!(
                 "got animation {} of {} x {}, {} s, {} B",
                 ani.file_name.as_deref().unwrap_or(""),
@@ -337,7 +2754,12 @@ impl<'a> Request<'a> {
                 ani.file.size
             );
             op = Op::Video;
-            (&ani.file.id, ani.file.size, ani.file_name.as_ref())
+            (
+                &ani.file.id,
+                Some(&ani.file.unique_id),
+                ani.file.size,
+                ani.file_name.as_ref(),
+            )
         } else if let Some(sti) = msg.sticker() {
             info!(
                 "got {:?} sticker in {} {} of {} x {}, {} B",
@@ -349,56 +2771,525 @@ impl<'a> Request<'a> {
                 sti.file.size
             );
             op = Op::Sticker(sti.format.clone());
-            self.caption = sti.emoji.as_ref().map(|x| x.as_ref());
-            (&sti.file.id, sti.file.size, sti.set_name.as_ref())
-        } else if Some("/start") == msg.text() {
-            return "Hi! Send me an image or a GIF, and I'll convert it for use with @Stickers. Also, I can convert stickers to images or GIFs.";
+            self.caption = sti.emoji.clone();
+            (
+                &sti.file.id,
+                Some(&sti.file.unique_id),
+                sti.file.size,
+                sti.set_name.as_ref(),
+            )
+        } else if let (Some(t), Some(sti)) = (
+            msg.text().and_then(parse_still),
+            msg.reply_to_message().and_then(|r| r.sticker()),
+        ) {
+            info!("still of {:?} sticker at {} s", sti.format, t);
+            op = Op::Still(sti.format.clone(), t);
+            (
+                &sti.file.id,
+                Some(&sti.file.unique_id),
+                sti.file.size,
+                sti.set_name.as_ref(),
+            )
+        } else if let (Some(text), Some(sti)) = (
+            msg.text().filter(|t| {
+                is_command(t, "/plan")
+                    || is_command(t, "/ocr")
+                    || REPLAY_COMMANDS.iter().any(|c| is_command(t, c))
+            }),
+            msg.reply_to_message().and_then(|r| r.sticker()),
+        ) {
+            info!("{:?} sticker with {}", sti.format, text);
+            match self.parse_edit(text) {
+                Ok(edit) => self.edit = edit,
+                Err(e) => return Some(e.msg()),
+            }
+            op = if self.edit.plan || self.edit.ocr {
+                Op::Sticker(sti.format.clone())
+            } else if self.edit.is_replay() {
+                Op::Replay(sti.format.clone())
+            } else {
+                return Some(Msg::new("help-speed"));
+            };
+            (
+                &sti.file.id,
+                Some(&sti.file.unique_id),
+                sti.file.size,
+                sti.set_name.as_ref(),
+            )
+        } else if let (Some(text), Some(src)) = (msg.text(), &reply_source) {
+            info!("reconverting {:?} with {}", src.kind, text);
+            match self.parse_edit(text) {
+                Ok(edit) => self.edit = edit,
+                Err(e) => return Some(e.msg()),
+            }
+            op = match src.kind {
+                sources::Kind::Image => Op::Image,
+                sources::Kind::Video => Op::Video,
+                sources::Kind::Tgs => Op::Replay(StickerFormat::Animated),
+                sources::Kind::Zip => Op::Zip,
+            };
+            (&src.file_id, None, src.size, None)
+        } else if let Some(text) = msg.text() {
+            return self.command(text).await;
         } else {
             info!("invalid: {:#?}", msg);
-            return "Please send an image, a GIF, or a sticker.";
+            return Some(Msg::new("send-media"));
+        };
+        if size > self.max_file_size() {
+            return Some(BotError::TooLarge.msg());
+        }
+        if let (Op::Image | Op::Video | Op::Zip, Some(s)) = (&op, caption) {
+            match self.parse_edit(s) {
+                Ok(edit) => self.edit = edit,
+                Err(e) => return Some(e.msg()),
+            }
+        }
+        let s = self.settings();
+        if self.actor().is_some() {
+            self.edit.target = self.edit.target.or(Some(s.target));
+            if !self.edit.lossless {
+                self.edit.quality = self.edit.quality.or(s.quality);
+            }
+            self.edit.upscale = self.edit.upscale.or(Some(s.upscale));
+        }
+        self.edit.watermark = watermark::get(s.watermark.as_deref());
+        let span = Span::current();
+        span.record("kind", format!("{:?}", op).as_str());
+        span.record("size", size);
+        let kind = match op {
+            Op::Image => Some(sources::Kind::Image),
+            Op::Video => Some(sources::Kind::Video),
+            Op::Zip => Some(sources::Kind::Zip),
+            Op::Sticker(_) | Op::Still(..) | Op::Replay(_) | Op::Restore => None,
         };
-        if size > MAX_SIZE {
-            return "File is too big.";
+        if let Some(kind) = kind {
+            let src = Source {
+                file_id: file_id.clone(),
+                size,
+                kind,
+            };
+            sources::insert(
+                bot_id(&self.bot),
+                self.msg.chat.id,
+                self.msg.id,
+                src.clone(),
+            );
+            self.source = Some(src);
         }
-        self.base = file_name.map(|x| x.as_ref());
-        if let Err(e) = self.handle_media(file_id, op).await {
+        let file_id = file_id.clone();
+        self.input = op.input();
+        self.unique_id = unique_id.cloned();
+        self.base = file_name.cloned();
+        let what = format!("{:?}", op);
+        if let Err(e) = self.handle_media(&file_id, op).await {
             error!("handle: {:?}", e);
-            return e
-                .downcast::<&'static str>()
-                .unwrap_or("Something went wrong.");
+            alert::failure(&self.context(&what), &e);
+            return Some(self.error_message(&e));
         }
-        ""
+        None
     }
 }
 
-#[tokio::main]
-async fn main() {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
-    pretty_env_logger::init();
-
-    let bot = Bot::from_env();
-    info!("bot started: {:?}", bot.client());
-
-    teloxide::repl(bot, |msg: Message, bot: Bot| async move {
-        tokio::spawn(async move {
-            let id = msg.chat.id;
-            let req = Request {
-                msg,
-                bot: bot.clone(),
-                caption: None,
-                base: None,
+// The last word of a request, after which all it sent is remembered for /delete.
+async fn finish(bot: &AppBot, lang: &str, sent: &ResponseTracker, m: Option<Msg>) {
+    if let Some(m) = m {
+        let (text, entities) = m.tr_entities(lang);
+        let mut r = bot.send_message(sent.chat(), text);
+        if !entities.is_empty() {
+            r = r.entities(entities);
+        }
+        if let Some(k) = help::context_keyboard(&m, lang) {
+            r = r.reply_markup(k);
+        }
+        match r.await {
+            Ok(m) => sent.record(m.id),
+            Err(e) => error!("send_message: {:?}", e),
+        }
+    }
+    responses::remember(bot_id(bot), sent);
+}
+
+async fn on_message(bot: AppBot, msg: Message) -> ResponseResult<()> {
+    // The kind and size are filled in once the handler finds the media.
+    let span = info_span!(
+        "update",
+        chat_id = msg.chat.id.0,
+        user_id = msg.from().map(|u| u.id.0),
+        kind = field::Empty,
+        size = field::Empty,
+    );
+    let priority = Actor::of(&msg).map_or(false, quota::is_premium);
+    tokio::spawn(
+        PRIORITY.scope(
+            priority,
+            async move {
+                let req = Request::new(msg, bot.clone());
+                let (lang, sent) = (req.lang, req.responses.clone());
+                let m = req.handler().await;
+                finish(&bot, lang, &sent, m).await;
+            }
+            .instrument(span),
+        ),
+    );
+    // TODO: join the spawned tasks when interrupted?
+    Ok(())
+}
+
+// Topics open in place of the message with the buttons.
+async fn on_help(bot: &AppBot, m: &Message, action: help::Action, lang: &str) -> AnyResult<()> {
+    let (text, keyboard) = match action {
+        help::Action::Overview => (Msg::new("help"), help::overview_keyboard()),
+        help::Action::Open(key) => help::topic(key, lang).unwrap(),
+        help::Action::Demo(key) => return help::demo(bot, m.chat.id, key, lang).await,
+    };
+    bot.edit_message_text(m.chat.id, m.id, text.tr(lang))
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+// Results are sent again into the chat of the list, which need not be where they were made.
+async fn on_history(
+    bot: &AppBot,
+    m: &Message,
+    actor: Actor,
+    (list, action): (history::List, history::Action),
+    lang: &str,
+) -> AnyResult<()> {
+    let bot_id = bot_id(bot);
+    let page = match action {
+        history::Action::Page(page) => page,
+        history::Action::Resend(id) => {
+            let Some(e) = history::find(list, bot_id, actor, id) else {
+                bail!("no entry {} in {:?} of {:?}", id, list, actor)
             };
-            let s = req.handler().await;
-            if !s.is_empty() {
-                if let Err(e) = bot.send_message(id, s).await {
-                    error!("send_message: {:?}", e);
+            bot.send_document(m.chat.id, InputFile::file_id(e.file_id))
+                .await?;
+            return Ok(());
+        }
+        history::Action::Remove(id) => {
+            if !history::unfav(bot_id, actor, id) {
+                bail!("no entry {} in {:?} of {:?}", id, list, actor)
+            }
+            0
+        }
+    };
+    match history::render(list, bot_id, actor, page, lang) {
+        Some((text, keyboard)) => {
+            bot.edit_message_text(m.chat.id, m.id, text)
+                .reply_markup(keyboard)
+                .await?
+        }
+        None => {
+            bot.edit_message_text(m.chat.id, m.id, Msg::new(list.empty()).tr(lang))
+                .await?
+        }
+    };
+    Ok(())
+}
+
+// Telegram waits no more than ten seconds for the answer before giving up on the payment.
+async fn on_pre_checkout_query(bot: AppBot, q: PreCheckoutQuery) -> ResponseResult<()> {
+    let mut answer = bot.answer_pre_checkout_query(&q.id, true);
+    if let Err(id) = payments::check(&q) {
+        let lang = i18n::lang_for(
+            Some(Actor::User(q.from.id)),
+            q.from.language_code.as_deref(),
+        );
+        answer = bot
+            .answer_pre_checkout_query(&q.id, false)
+            .error_message(Msg::new(id).tr(lang));
+    }
+    if let Err(e) = answer.await {
+        error!("answer_pre_checkout_query: {:?}", e);
+    }
+    Ok(())
+}
+
+// Own results only, found by their tags as with /find, so the answer is not cached for others.
+// As all of them are already on Telegram, they are sent by their file ids without any upload.
+async fn on_inline_query(bot: AppBot, q: InlineQuery) -> ResponseResult<()> {
+    let actor = Actor::User(q.from.id);
+    if !access::is_allowed_actor(actor) {
+        return Ok(());
+    }
+    let words = history::parse_tags(q.query.split_whitespace());
+    let skip = q.offset.parse().unwrap_or(0);
+    // One more than is shown tells whether there is another page.
+    let mut found = history::search(bot_id(&bot), actor, &words, skip, history::INLINE_LIMIT + 1);
+    let next = if found.len() > history::INLINE_LIMIT {
+        found.truncate(history::INLINE_LIMIT);
+        (skip + history::INLINE_LIMIT).to_string()
+    } else {
+        String::new()
+    };
+    let results = found.into_iter().map(|(list, e)| {
+        InlineQueryResult::CachedDocument(InlineQueryResultCachedDocument::new(
+            e.key(list),
+            e.title(),
+            e.file_id,
+        ))
+    });
+    if let Err(e) = bot
+        .answer_inline_query(&q.id, results)
+        .is_personal(true)
+        .cache_time(0)
+        .next_offset(next)
+        .await
+    {
+        error!("answer_inline_query: {:?}", e);
+    }
+    Ok(())
+}
+
+async fn on_callback_query(bot: AppBot, q: CallbackQuery) -> ResponseResult<()> {
+    let span = info_span!(
+        "callback",
+        chat_id = q.message.as_ref().map(|m| m.chat.id.0),
+        user_id = q.from.id.0,
+    );
+    tokio::spawn(
+        async move {
+            let data = q.data.as_deref().unwrap_or("");
+            let lang = i18n::lang_for(
+                Some(Actor::User(q.from.id)),
+                q.from.language_code.as_deref(),
+            );
+            if let Some(action) = help::parse_callback_data(data) {
+                if !access::is_allowed_actor(Actor::User(q.from.id)) {
+                    return;
                 }
+                if let Err(e) = bot.answer_callback_query(&q.id).await {
+                    error!("answer_callback_query: {:?}", e);
+                }
+                let Some(m) = &q.message else {
+                    return;
+                };
+                if let Err(e) = on_help(&bot, m, action, lang).await {
+                    error!("help: {:?}", e);
+                }
+                return;
             }
-        });
-        // TODO: join the spawned tasks when interrupted?
-        Ok(())
-    })
-    .await;
+            if let Some(action) = history::parse_callback_data(data) {
+                if !access::is_allowed_actor(Actor::User(q.from.id)) {
+                    return;
+                }
+                let Some(m) = &q.message else {
+                    return;
+                };
+                let r = on_history(&bot, m, Actor::User(q.from.id), action, lang).await;
+                let mut answer = bot.answer_callback_query(&q.id);
+                if let Err(e) = &r {
+                    error!("history: {:?}", e);
+                    answer = answer.text(Msg::new("history-gone").tr(lang));
+                }
+                if let Err(e) = answer.await {
+                    error!("answer_callback_query: {:?}", e);
+                }
+                return;
+            }
+            #[cfg(feature = "emoji")]
+            if let Some(e) = emoji::parse_callback_data(data) {
+                if !access::is_allowed_actor(Actor::User(q.from.id)) {
+                    return;
+                }
+                let doc = q
+                    .message
+                    .as_ref()
+                    .and_then(|m| m.reply_to_message())
+                    .and_then(|r| r.document());
+                // Into the favorites of whoever tapped it, as anyone in a group may.
+                let saved = doc.map(|doc| {
+                    let name = doc.file_name.as_deref().unwrap_or("");
+                    let ext = name.rsplit_once('.').map_or("file", |(_, ext)| ext);
+                    let actor = Actor::User(q.from.id);
+                    history::fav(
+                        bot_id(&bot),
+                        actor,
+                        ext,
+                        doc.file.id.clone(),
+                        vec![e.into()],
+                    )
+                });
+                let msg = match saved {
+                    Some(true) => Msg::new("emoji-saved").arg("emoji", e),
+                    Some(false) => {
+                        let max = history::List::Favorites.max_entries();
+                        Msg::new("favs-full").arg("max", max)
+                    }
+                    None => Msg::new("emoji-gone"),
+                };
+                if let Err(e) = bot.answer_callback_query(&q.id).text(msg.tr(lang)).await {
+                    error!("answer_callback_query: {:?}", e);
+                }
+                return;
+            }
+            if let Some((token, i)) = psd::parse_callback_data(data) {
+                let doc = psd::DOCUMENTS.get(token, q.from.id);
+                let handle = |req: Request, doc: psd::Document| async move {
+                    req.handle_layer(&doc, i).await
+                };
+                on_offer(&bot, &q, lang, doc, true, handle).await;
+                return;
+            }
+            if let Some((token, choice)) = aspect::parse_callback_data(data) {
+                let o = aspect::OFFERS.take(token, q.from.id);
+                let handle = |req: Request, o: aspect::Offer| req.handle_aspect(o, choice);
+                on_offer(&bot, &q, lang, o, false, handle).await;
+                return;
+            }
+            if let Some((token, choice)) = duplicates::parse_callback_data(data) {
+                let o = duplicates::OFFERS.take(token, q.from.id);
+                let handle = |req: Request, o: duplicates::Offer| req.handle_duplicate(o, choice);
+                on_offer(&bot, &q, lang, o, false, handle).await;
+                return;
+            }
+            let Some((token, seg)) = pending::parse_callback_data(data) else {
+                return;
+            };
+            let p = pending::PENDING.take(token, q.from.id);
+            let handle =
+                |req: Request, p: Pending| async move { req.handle_segment(&p, seg).await };
+            on_offer(&bot, &q, lang, p, false, handle).await;
+        }
+        .instrument(span),
+    );
+    Ok(())
+}
+
+// Answers the tap on an offer, telling why when it is gone, and handles the choice as a request
+// for the message it was made for. The keyboard is deleted unless it is to be chosen from again.
+async fn on_offer<T, F, Fut>(
+    bot: &AppBot,
+    q: &CallbackQuery,
+    lang: &str,
+    offer: Result<(Message, T), BotError>,
+    keep: bool,
+    handle: F,
+) where
+    F: FnOnce(Request, T) -> Fut,
+    Fut: Future<Output = Option<Msg>>,
+{
+    let mut answer = bot.answer_callback_query(&q.id);
+    if let Err(e) = &offer {
+        answer = answer.text(e.msg().tr(lang));
+    }
+    if let Err(e) = answer.await {
+        error!("answer_callback_query: {:?}", e);
+    }
+    let Ok((msg, offer)) = offer else {
+        return;
+    };
+    if !keep {
+        if let Some(m) = &q.message {
+            if let Err(e) = bot.delete_message(m.chat.id, m.id).await {
+                warn!("delete_message: {:?}", e);
+            }
+        }
+    }
+    let req = Request::new(msg, bot.clone());
+    let (lang, sent) = (req.lang, req.responses.clone());
+    let m = handle(req, offer).await;
+    finish(bot, lang, &sent, m).await;
+}
+
+// Events of the log crate are forwarded too. RUST_LOG filters as before, and LOG_FORMAT=json
+// writes a json object per line, carrying the spans, for log aggregation. Logs go to stderr, as
+// the stdout of workers carries their results.
+fn init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    if std::env::var("LOG_FORMAT").map_or(false, |s| s == "json") {
+        fmt.json().init();
+    } else {
+        fmt.init();
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    init_logging();
+    config::init();
+    // Workers inherit the PATH with the already verified bundle.
+    if std::env::args().nth(1).as_deref() == Some(worker::ARG) {
+        caps::detect();
+        worker::serve().await;
+        return;
+    }
+    if let Err(e) = bundle::verify() {
+        error!("bundle: {:#}", e);
+        std::process::exit(1);
+    }
+    let cli = Cli::parse();
+    limits::init();
+    hwaccel::detect().await;
+    // Before the stores, which would take those of a bot running on the same host.
+    if cli.is_oneshot() {
+        caps::detect();
+        if let Err(e) = cli.run().await {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    i18n::init();
+    access::init();
+    settings::init();
+    sources::init();
+    history::init();
+    quota::init();
+    nsfw::init();
+    tempdir::init();
+    jobs::init();
+
+    // Several bots can share the process, and with it the job limit and the stores, by giving
+    // their tokens separated by commas.
+    let tokens = std::env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN is not set");
+    let bots: Vec<AppBot> = tokens
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|t| {
+            let bot = Bot::with_client(t, net::client_from_env());
+            let bot = match &config::get().api_url {
+                Some(url) => bot.set_api_url(url.clone()),
+                None => bot,
+            };
+            bot.throttle(Limits::default())
+        })
+        .collect();
+    let Some(first) = bots.first() else {
+        error!("TELOXIDE_TOKEN has no tokens");
+        std::process::exit(1);
+    };
+    // Alerts go through the first bot, which has to be able to write to ERROR_CHAT_ID.
+    alert::init(first.clone());
+    caps::detect();
+    if let Some(addr) = config::get().health_addr {
+        tokio::spawn(selftest::serve(addr));
+    }
+
+    let dispatchers: Vec<_> = bots
+        .into_iter()
+        .map(|bot| {
+            info!("bot {} started: {:?}", bot_id(&bot), bot.inner().client());
+            jobs::resume(&bot);
+            let handler = dptree::entry()
+                .branch(Update::filter_message().endpoint(on_message))
+                .branch(Update::filter_callback_query().endpoint(on_callback_query))
+                .branch(Update::filter_inline_query().endpoint(on_inline_query))
+                .branch(Update::filter_pre_checkout_query().endpoint(on_pre_checkout_query));
+            let mut d = Dispatcher::builder(bot, handler)
+                .enable_ctrlc_handler()
+                .build();
+            tokio::spawn(async move { d.dispatch().await })
+        })
+        .collect();
+    for d in dispatchers {
+        if let Err(e) = d.await {
+            error!("dispatcher: {}", e);
+        }
+    }
 }
@@ -0,0 +1,151 @@
+// Refusing explicit images, for public instances. The classifier, behind the nsfw feature, is an
+// ONNX model given by NSFW_MODEL that takes 224x224 images normalized like ImageNet and gives a
+// score per class, such as one of the open NSFW detectors. NSFW_CLASSES are the indexes of the
+// explicit ones, and input whose share of them reaches NSFW_THRESHOLD is not converted. It is
+// checked in groups, and with NSFW_FILTER=all in private chats too. Chat admins can turn it on
+// or off for their group with /chatsettings nsfw, and the bot's admins for any chat with /nsfw,
+// which goes over what the chat chose. The bot's admins are never checked. Videos and animated
+// stickers are judged by the thumbnail Telegram makes of them, and the files of zips are not.
+
+use crate::access::{self, Actor};
+use crate::config;
+use crate::settings;
+use crate::store::JsonStore;
+use anyhow::Result as AnyResult;
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::io;
+use std::sync::OnceLock;
+use teloxide::types::Chat;
+
+const OVERRIDES_FILE: &str = "nsfw.json";
+
+static OVERRIDES: OnceLock<JsonStore<HashMap<i64, bool>>> = OnceLock::new();
+
+fn overrides() -> &'static JsonStore<HashMap<i64, bool>> {
+    OVERRIDES.get_or_init(|| JsonStore::load(OVERRIDES_FILE))
+}
+
+pub fn init() {
+    overrides();
+}
+
+pub fn is_enabled() -> bool {
+    cfg!(feature = "nsfw") && config::get().nsfw_model.is_some()
+}
+
+// None leaves it to the chat again.
+pub fn set_override(chat: i64, on: Option<bool>) -> io::Result<()> {
+    let s = overrides();
+    let mut chats = s.lock();
+    match on {
+        Some(on) => chats.insert(chat, on),
+        None => chats.remove(&chat),
+    };
+    s.save(&chats)
+}
+
+// Whether what the actor sends in the chat is to be checked.
+pub fn applies(chat: &Chat, actor: Option<Actor>) -> bool {
+    if !is_enabled() || actor.and_then(Actor::user).map_or(false, access::is_admin) {
+        return false;
+    }
+    if let Some(&on) = overrides().lock().get(&chat.id.0) {
+        return on;
+    }
+    settings::profile(chat.id)
+        .nsfw
+        .unwrap_or(!chat.is_private() || config::get().nsfw_everywhere)
+}
+
+pub async fn is_explicit(img: DynamicImage) -> AnyResult<bool> {
+    #[cfg(feature = "nsfw")]
+    {
+        Ok(model::score(img).await? >= config::get().nsfw_threshold)
+    }
+    #[cfg(not(feature = "nsfw"))]
+    {
+        let _ = img;
+        Ok(false)
+    }
+}
+
+#[cfg(feature = "nsfw")]
+mod model {
+    use crate::{config, job_permit};
+    use anyhow::{anyhow, Result as AnyResult};
+    use image::imageops::FilterType;
+    use image::DynamicImage;
+    use log::{info, warn};
+    use ndarray::{Array4, CowArray, Ix2};
+    use ort::{
+        Environment, GraphOptimizationLevel, OrtOwnedTensor, Session, SessionBuilder, Value,
+    };
+    use std::sync::OnceLock;
+
+    const SIDE: u32 = 224;
+    const MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+    const STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+    static SESSION: OnceLock<Result<Session, String>> = OnceLock::new();
+
+    fn session() -> AnyResult<&'static Session> {
+        SESSION
+            .get_or_init(|| {
+                let Some(path) = &config::get().nsfw_model else {
+                    return Err("NSFW_MODEL is not set".to_owned());
+                };
+                let env = Environment::builder()
+                    .with_name("nsfw")
+                    .build()
+                    .map_err(|e| e.to_string())?
+                    .into_arc();
+                let s = SessionBuilder::new(&env)
+                    .and_then(|b| b.with_optimization_level(GraphOptimizationLevel::Level3))
+                    .and_then(|b| b.with_model_from_file(path))
+                    .map_err(|e| e.to_string())?;
+                info!("nsfw: loaded {}", path.display());
+                Ok(s)
+            })
+            .as_ref()
+            .map_err(|e| anyhow!("nsfw: {}", e))
+    }
+
+    // The softmax of the explicit classes put together. Transparent parts are seen as black.
+    fn predict(img: &DynamicImage) -> AnyResult<f32> {
+        let session = session()?;
+        let small = img
+            .resize_exact(SIDE, SIDE, FilterType::Triangle)
+            .to_rgba8();
+        let mut input = Array4::<f32>::zeros((1, 3, SIDE as usize, SIDE as usize));
+        for (x, y, px) in small.enumerate_pixels() {
+            let a = px[3] as f32 / 255.0;
+            for c in 0..3 {
+                let v = px[c] as f32 / 255.0 * a;
+                input[[0, c, y as usize, x as usize]] = (v - MEAN[c]) / STD[c];
+            }
+        }
+        let input = CowArray::from(input.into_dyn());
+        let outputs = session.run(vec![Value::from_array(session.allocator(), &input)?])?;
+        let out: OrtOwnedTensor<f32, _> = outputs[0].try_extract()?;
+        let out = out.view().into_dimensionality::<Ix2>()?;
+        let logits = out.row(0);
+        let max = logits.iter().cloned().fold(f32::MIN, f32::max);
+        let sum: f32 = logits.iter().map(|&v| (v - max).exp()).sum();
+        let mut score = 0.0;
+        for &i in &config::get().nsfw_classes {
+            match logits.get(i) {
+                Some(&v) => score += (v - max).exp() / sum,
+                None => warn!("nsfw: no class {} in {} outputs", i, logits.len()),
+            }
+        }
+        Ok(score)
+    }
+
+    pub async fn score(img: DynamicImage) -> AnyResult<f32> {
+        let _permit = job_permit().await;
+        let score = tokio::task::spawn_blocking(move || predict(&img)).await??;
+        info!("nsfw: scored {:.3}", score);
+        Ok(score)
+    }
+}
@@ -0,0 +1,90 @@
+// Text read from images by tesseract for /ocr, given with the result and kept among its tags, so
+// that memes can be found again by what they say with /find and inline queries. OCR_LANGS names
+// the tesseract languages to read, such as "eng+chi_sim".
+
+use crate::error::BotError;
+use anyhow::Result as AnyResult;
+use image::DynamicImage;
+
+#[cfg(feature = "ocr")]
+pub const TESSERACT: &str = "tesseract";
+// Of the text put in the caption, which Telegram cuts at 1024 along with the rest.
+const MAX_CHARS: usize = 800;
+
+// Transparent parts are made white, as the text of stickers is mostly dark or outlined.
+#[cfg(feature = "ocr")]
+pub async fn read(img: DynamicImage) -> AnyResult<String> {
+    use crate::{config, temp_path, wait_output};
+    use anyhow::bail;
+    use image::ImageOutputFormat;
+    use log::error;
+    use std::io::Cursor;
+    use tokio::process::Command;
+
+    if !crate::caps::get().tesseract {
+        bail!(BotError::OcrUnavailable)
+    }
+    let png = tokio::task::spawn_blocking(move || -> AnyResult<Vec<u8>> {
+        let mut rgba = img.to_rgba8();
+        for px in rgba.pixels_mut() {
+            let a = px[3] as u16;
+            for c in 0..3 {
+                px[c] = ((px[c] as u16 * a + 255 * (255 - a)) / 255) as u8;
+            }
+            px[3] = 255;
+        }
+        let mut v = vec![];
+        DynamicImage::ImageRgba8(rgba)
+            .write_to(&mut Cursor::new(&mut v), ImageOutputFormat::Png)?;
+        Ok(v)
+    })
+    .await??;
+    let path = temp_path()?;
+    tokio::fs::write(&path, png).await?;
+    let cfg = config::get();
+    let out = wait_output(
+        Command::new(TESSERACT)
+            .arg(&path)
+            .arg("stdout")
+            .args(["-l", &cfg.ocr_langs]),
+        cfg.ocr_timeout,
+    )
+    .await?;
+    if !out.status.success() {
+        error!(
+            "tesseract failed: {:?}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim_end()
+        );
+        bail!(BotError::OcrFailed)
+    }
+    Ok(clean(&String::from_utf8_lossy(&out.stdout)))
+}
+
+#[cfg(not(feature = "ocr"))]
+pub async fn read(_img: DynamicImage) -> AnyResult<String> {
+    anyhow::bail!(BotError::OcrUnavailable)
+}
+
+// One line of words per line read, without the blank ones tesseract puts between blocks.
+fn clean(text: &str) -> String {
+    let lines: Vec<_> = text
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|l| !l.is_empty())
+        .collect();
+    let text = lines.join("\n");
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text,
+    }
+}
+
+// The words of the text without the punctuation around them, as tags.
+pub fn tags(text: &str) -> Vec<String> {
+    let words = text
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|w| !w.is_empty());
+    crate::history::parse_tags(words)
+}
@@ -0,0 +1,158 @@
+// Inputs waiting on an inline keyboard for the user to choose what to do with them, such as the
+// segment of a long video or a layer of a document. Each kind has a store of its own, and its
+// buttons carry `prefix:token:code`, the code telling what was chosen.
+
+use crate::error::BotError;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use teloxide::types::{Message, UserId};
+
+const TTL: Duration = Duration::from_secs(10 * 60);
+
+// What is waiting, which tells the user what is gone or not theirs.
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Video,
+    Document,
+    Image,
+}
+
+impl Kind {
+    fn expired(self) -> BotError {
+        match self {
+            Kind::Video => BotError::VideoExpired,
+            Kind::Document => BotError::DocumentExpired,
+            Kind::Image => BotError::ImageExpired,
+        }
+    }
+
+    fn not_yours(self) -> BotError {
+        match self {
+            Kind::Video => BotError::NotYourVideo,
+            Kind::Document => BotError::NotYourDocument,
+            Kind::Image => BotError::NotYourImage,
+        }
+    }
+}
+
+struct Entry<T> {
+    // The message containing the input, which results reply to.
+    msg: Message,
+    user: Option<UserId>,
+    offer: T,
+    created: Instant,
+}
+
+impl<T> Entry<T> {
+    fn is_expired(&self) -> bool {
+        self.created.elapsed() >= TTL
+    }
+}
+
+pub struct Offers<T> {
+    prefix: &'static str,
+    kind: Kind,
+    next: AtomicU64,
+    map: OnceLock<Mutex<HashMap<u64, Entry<T>>>>,
+}
+
+impl<T> Offers<T> {
+    pub const fn new(prefix: &'static str, kind: Kind) -> Self {
+        Self {
+            prefix,
+            kind,
+            next: AtomicU64::new(0),
+            map: OnceLock::new(),
+        }
+    }
+
+    fn map(&self) -> &Mutex<HashMap<u64, Entry<T>>> {
+        self.map.get_or_init(Default::default)
+    }
+
+    // Returns the token to put in the callback data. Expired entries, and what they hold such as
+    // files, are dropped here.
+    pub fn insert(&self, msg: Message, user: Option<UserId>, offer: T) -> u64 {
+        let token = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut map = self.map().lock().unwrap();
+        map.retain(|_, e| !e.is_expired());
+        map.insert(
+            token,
+            Entry {
+                msg,
+                user,
+                offer,
+                created: Instant::now(),
+            },
+        );
+        token
+    }
+
+    pub fn callback_data(&self, token: u64, code: impl Display) -> String {
+        format!("{}:{}:{}", self.prefix, token, code)
+    }
+
+    // The token and the code, for data of this store.
+    pub fn parse_callback_data<'a>(&self, data: &'a str) -> Option<(u64, &'a str)> {
+        let mut it = data.splitn(3, ':');
+        if it.next()? != self.prefix {
+            return None;
+        }
+        let token = it.next()?.parse().ok()?;
+        Some((token, it.next()?))
+    }
+
+    fn check(
+        &self,
+        map: &mut HashMap<u64, Entry<T>>,
+        token: u64,
+        user: UserId,
+    ) -> Result<(), BotError> {
+        match map.get(&token) {
+            None => Err(self.kind.expired()),
+            Some(e) if e.is_expired() => {
+                map.remove(&token);
+                Err(self.kind.expired())
+            }
+            Some(e) if e.user.map_or(false, |u| u != user) => Err(self.kind.not_yours()),
+            Some(_) => Ok(()),
+        }
+    }
+
+    // With the message it was offered for.
+    pub fn take(&self, token: u64, user: UserId) -> Result<(Message, T), BotError> {
+        let mut map = self.map().lock().unwrap();
+        self.check(&mut map, token, user)?;
+        let e = map.remove(&token).unwrap();
+        Ok((e.msg, e.offer))
+    }
+
+    // Leaves it in place until it expires, for offers that can be chosen from again.
+    pub fn get(&self, token: u64, user: UserId) -> Result<(Message, T), BotError>
+    where
+        T: Clone,
+    {
+        let mut map = self.map().lock().unwrap();
+        self.check(&mut map, token, user)?;
+        let e = &map[&token];
+        Ok((e.msg.clone(), e.offer.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_data_round_trip() {
+        let offers: Offers<()> = Offers::new("seg", Kind::Video);
+        let data = offers.callback_data(7, "a:b");
+        assert_eq!(offers.parse_callback_data(&data), Some((7, "a:b")));
+        assert_eq!(offers.parse_callback_data("seg:7"), None);
+        assert_eq!(offers.parse_callback_data("seg:x:a"), None);
+        assert_eq!(offers.parse_callback_data("psd:7:a"), None);
+    }
+}
@@ -0,0 +1,699 @@
+// Directives given in the caption of uploaded media, e.g. "trim=0.5-3.0 crop=center speed=1.5"
+// or "/speed 2x" or "/slow" for videos, or "/pad rotate=90 mirror" or "/emoji" for anything, so
+// that one message does what would otherwise take a command first. Everything after "text:"
+// is drawn over the result. Other words are ignored, while a key=value that does not parse is
+// reported with an example of it. The same options apply to documents, photos, videos and
+// stickers alike.
+
+use crate::config;
+use crate::error::BotError;
+use crate::frames;
+use crate::grid;
+use crate::pixel;
+use crate::probe::VideoInfo;
+use crate::style::Style;
+use crate::target::{self, Target};
+use crate::text::{Overlay, Position};
+use crate::upscale::Upscaler;
+use crate::watermark::Watermark;
+use crate::MAX_DURATION;
+use image::imageops;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Crop {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+// Serialized when handed to a worker.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoEdit {
+    pub trim: Option<(f64, f64)>,
+    // Square crop anchored as given.
+    pub crop: Option<Crop>,
+    pub speed: Option<f64>,
+    // Output frame rate, only set when the input would exceed it.
+    pub fps: Option<u32>,
+    // Letterbox into a transparent 512x512 canvas.
+    pub pad: bool,
+    // Remove the background of images.
+    pub cutout: bool,
+    pub text: Option<Overlay>,
+    // Clockwise, in degrees.
+    pub rotate: u16,
+    // Upside down.
+    pub flip: bool,
+    // Left to right.
+    pub mirror: bool,
+    pub invert: bool,
+    pub grayscale: bool,
+    // Not an edit, only appends a size and validity report to the result.
+    pub report: bool,
+    // Inspect the file against the sticker requirements instead of converting it.
+    pub check: bool,
+    // Tell what converting it would do instead, for /plan.
+    pub plan: bool,
+    // Also read the text of an image, given with the result.
+    pub ocr: bool,
+    // Make a 100x100 sticker set thumbnail instead.
+    pub thumb: bool,
+    // From the caption, or else the user's setting.
+    pub target: Option<Target>,
+    // Animated webp instead of webm.
+    pub webp: bool,
+    // Lossy webp quality for images.
+    pub quality: Option<u8>,
+    // Lossless even when the user's setting is lossy.
+    pub lossless: bool,
+    // Videos skip the lossless encode, which is the slower one.
+    pub lossy: bool,
+    // Fill behind transparent parts, which are kept when unset.
+    pub bg: Option<[u8; 3]>,
+    // Square crop of images around the most detailed region.
+    pub smartcrop: bool,
+    // Rows and columns to cut an image into.
+    pub grid: Option<(u32, u32)>,
+    // Number of still frames to take from a video instead of converting it.
+    pub frames: Option<u32>,
+    // Retro look, from filter=.
+    pub style: Option<Style>,
+    // Pixel art of at most this many pixels across, see pixel.rs.
+    pub pixel: Option<u32>,
+    // Colors to quantize to.
+    pub colors: Option<u16>,
+    // Played backwards.
+    pub reverse: bool,
+    // Played forwards, then backwards.
+    pub boomerang: bool,
+    // Not from the caption, but from the instance or the user's setting.
+    pub watermark: Option<Watermark>,
+    // Of images smaller than the target, from the caption, or else the user's setting.
+    pub upscale: Option<Upscaler>,
+    // The input is a webm sticker, whose alpha only the libvpx decoder keeps.
+    pub vp9: bool,
+    // Run again by /retry, with more time for ffmpeg.
+    pub retry: bool,
+}
+
+// Quality of the "lossy" shorthand.
+const LOSSY_QUALITY: u8 = 80;
+const SLOW_SPEED: f64 = 0.5;
+const FAST_SPEED: f64 = 2.0;
+const VALUE_KEYS: [&str; 14] = [
+    "trim", "crop", "speed", "fps", "rotate", "q", "quality", "bg", "grid", "frames", "pos",
+    "filter", "pixel", "colors",
+];
+// Words taken alone, besides the targets.
+const FLAGS: [&str; 24] = [
+    "pad",
+    "cutout",
+    "flip",
+    "mirror",
+    "invert",
+    "grayscale",
+    "greyscale",
+    "report",
+    "check",
+    "plan",
+    "ocr",
+    "thumb",
+    "webp",
+    "smartcrop",
+    "upscale",
+    "lossy",
+    "lossless",
+    "frames",
+    "pixel",
+    "reverse",
+    "boomerang",
+    "pingpong",
+    "slow",
+    "fast",
+];
+
+// Whether the word means something to the parser, alone or as a key.
+pub fn is_keyword(word: &str) -> bool {
+    FLAGS.contains(&word) || VALUE_KEYS.contains(&word) || Target::parse(word).is_some()
+}
+
+pub fn parse_color(s: &str) -> Option<[u8; 3]> {
+    let named = match s {
+        "white" => Some([255, 255, 255]),
+        "black" => Some([0, 0, 0]),
+        "red" => Some([255, 0, 0]),
+        "green" => Some([0, 128, 0]),
+        "blue" => Some([0, 0, 255]),
+        "yellow" => Some([255, 255, 0]),
+        "gray" | "grey" => Some([128, 128, 128]),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !hex.is_ascii() {
+        return None;
+    }
+    let digit = |i: usize, n: usize| u8::from_str_radix(&hex[i..i + n], 16).ok();
+    match hex.len() {
+        3 => Some([digit(0, 1)? * 17, digit(1, 1)? * 17, digit(2, 1)? * 17]),
+        6 => Some([digit(0, 2)?, digit(2, 2)?, digit(4, 2)?]),
+        _ => None,
+    }
+}
+
+// Points at what is wrong with the option, with an example of it.
+fn bad_option(key: &str, word: &str) -> BotError {
+    let id = match key {
+        "trim" => "bad-trim",
+        "crop" => "bad-crop",
+        "speed" => "bad-speed",
+        "fps" => "bad-fps",
+        "rotate" => "bad-rotate",
+        "q" | "quality" => "bad-quality",
+        "bg" => "bad-bg",
+        "grid" => "bad-grid",
+        "frames" => "bad-frames",
+        "pos" => "bad-pos",
+        "filter" => "bad-filter",
+        "pixel" => "bad-pixel",
+        "colors" => "bad-colors",
+        _ => "bad-option",
+    };
+    BotError::BadOption {
+        id,
+        word: word.to_owned(),
+    }
+}
+
+// "/speed 2x" or "speed 0.5x" is taken as speed=2x or speed=0.5x, and "/fps@bot 30" as fps=30,
+// as commands are written. Frames and pixel, which also stand alone, only take a number after.
+fn words(caption: &str) -> Vec<Cow<'_, str>> {
+    let mut v = vec![];
+    let mut it = caption.split_whitespace().peekable();
+    while let Some(w) = it.next() {
+        let name = match w.strip_prefix('/') {
+            Some(name) => name.split('@').next().unwrap_or(name),
+            None if w == "speed" => w,
+            None => {
+                v.push(Cow::Borrowed(w));
+                continue;
+            }
+        };
+        let takes = |next: &&str| {
+            !next.contains('=')
+                && !next.starts_with('/')
+                && (!matches!(name, "frames" | "pixel")
+                    || next.starts_with(|c: char| c.is_ascii_digit()))
+        };
+        let value = if VALUE_KEYS.contains(&name) {
+            it.next_if(takes)
+        } else {
+            None
+        };
+        match value {
+            Some(value) => v.push(Cow::Owned(format!("{}={}", name, value))),
+            None => v.push(Cow::Borrowed(name)),
+        }
+    }
+    v
+}
+
+const TEXT_KEY: &str = "text:";
+
+// Only where it starts a word, so that one inside another such as "context:" is left alone.
+fn split_text(caption: &str) -> Option<(&str, &str)> {
+    let i = caption.match_indices(TEXT_KEY).map(|(i, _)| i).find(|&i| {
+        caption[..i]
+            .chars()
+            .next_back()
+            .map_or(true, char::is_whitespace)
+    })?;
+    Some((&caption[..i], &caption[i + TEXT_KEY.len()..]))
+}
+
+fn parse_secs(s: &str) -> Option<f64> {
+    s.parse().ok().filter(|x: &f64| x.is_finite() && *x >= 0.0)
+}
+
+impl VideoEdit {
+    pub fn parse(caption: &str) -> Result<Self, BotError> {
+        let mut r = Self::default();
+        let mut position = None;
+        let caption = match split_text(caption) {
+            Some((rest, text)) => {
+                let text = text.trim();
+                if !text.is_empty() {
+                    r.text = Some(Overlay {
+                        text: text.to_owned(),
+                        position: config::get().text_position,
+                    });
+                }
+                rest
+            }
+            None => caption,
+        };
+        for word in words(caption) {
+            let word = word.as_ref();
+            let Some((key, value)) = word.split_once('=') else {
+                match word {
+                    "pad" => r.pad = true,
+                    "cutout" => r.cutout = true,
+                    "flip" => r.flip = true,
+                    "mirror" => r.mirror = true,
+                    "invert" => r.invert = true,
+                    "grayscale" | "greyscale" => r.grayscale = true,
+                    "report" => r.report = true,
+                    "check" => r.check = true,
+                    "plan" => r.plan = true,
+                    "ocr" => r.ocr = true,
+                    "thumb" => r.thumb = true,
+                    "webp" => r.webp = true,
+                    "smartcrop" => r.smartcrop = true,
+                    "upscale" => r.upscale = Some(Upscaler::Model),
+                    "lossy" => {
+                        r.quality = Some(LOSSY_QUALITY);
+                        r.lossy = true;
+                    }
+                    "lossless" => r.lossless = true,
+                    "frames" => r.frames = Some(frames::DEFAULT_COUNT),
+                    "pixel" => r.pixel = Some(pixel::DEFAULT_SIZE),
+                    "reverse" => r.reverse = true,
+                    "boomerang" | "pingpong" => r.boomerang = true,
+                    "slow" => r.speed = Some(SLOW_SPEED),
+                    "fast" => r.speed = Some(FAST_SPEED),
+                    w => {
+                        if let Some(t) = Target::parse(w) {
+                            r.target = Some(t);
+                        }
+                    }
+                }
+                continue;
+            };
+            let bad = || bad_option(key, word);
+            match key {
+                "trim" => {
+                    let (a, b) = value.split_once('-').ok_or_else(bad)?;
+                    let a = parse_secs(a).ok_or_else(bad)?;
+                    let b = parse_secs(b).ok_or_else(bad)?;
+                    if b <= a {
+                        return Err(bad());
+                    }
+                    r.trim = Some((a, b));
+                }
+                "crop" => {
+                    r.crop = Some(match value {
+                        "center" | "square" => Crop::Center,
+                        "top" => Crop::Top,
+                        "bottom" => Crop::Bottom,
+                        "left" => Crop::Left,
+                        "right" => Crop::Right,
+                        _ => return Err(bad()),
+                    })
+                }
+                "speed" => {
+                    let x = value.strip_suffix('x').unwrap_or(value);
+                    r.speed = Some(
+                        parse_secs(x)
+                            .filter(|&x| (0.25..=4.0).contains(&x))
+                            .ok_or_else(bad)?,
+                    );
+                }
+                "fps" => {
+                    let max = config::get().max_fps;
+                    r.fps = Some(
+                        value
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(bad)?
+                            .min(max),
+                    );
+                }
+                "rotate" => {
+                    r.rotate = match value.parse::<i32>().map_err(|_| bad())?.rem_euclid(360) {
+                        n @ (0 | 90 | 180 | 270) => n as u16,
+                        _ => return Err(bad()),
+                    }
+                }
+                "q" | "quality" => {
+                    r.quality = Some(
+                        value
+                            .parse::<u8>()
+                            .ok()
+                            .filter(|q| (1..=100).contains(q))
+                            .ok_or_else(bad)?,
+                    );
+                }
+                "bg" => {
+                    r.bg = match value {
+                        "none" | "transparent" => None,
+                        _ => Some(parse_color(value).ok_or_else(bad)?),
+                    }
+                }
+                "grid" => {
+                    let (rows, cols) = value.split_once(['x', '×']).ok_or_else(bad)?;
+                    let side = |s: &str| {
+                        s.parse::<u32>()
+                            .ok()
+                            .filter(|n| (1..=grid::MAX_CELLS).contains(n))
+                            .ok_or_else(bad)
+                    };
+                    let (rows, cols) = (side(rows)?, side(cols)?);
+                    if rows.checked_mul(cols).map_or(true, |n| n > grid::MAX_CELLS) {
+                        return Err(bad());
+                    }
+                    r.grid = Some((rows, cols));
+                }
+                "frames" => {
+                    r.frames = Some(
+                        value
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|n| (1..=frames::MAX_COUNT).contains(n))
+                            .ok_or_else(bad)?,
+                    );
+                }
+                "filter" => r.style = Some(Style::parse(value).ok_or_else(bad)?),
+                "pixel" => {
+                    r.pixel = Some(
+                        value
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|n| (pixel::MIN_SIZE..=pixel::MAX_SIZE).contains(n))
+                            .ok_or_else(bad)?,
+                    );
+                }
+                "colors" => {
+                    r.colors = Some(
+                        value
+                            .parse::<u16>()
+                            .ok()
+                            .filter(|n| (2..=pixel::MAX_COLORS).contains(n))
+                            .ok_or_else(bad)?,
+                    );
+                }
+                "pos" => position = Some(Position::parse(value).ok_or_else(bad)?),
+                _ => return Err(bad()),
+            }
+        }
+        if let (Some(o), Some(p)) = (&mut r.text, position) {
+            o.position = p;
+        }
+        Ok(r)
+    }
+
+    pub fn adjust_image(&self, mut img: DynamicImage) -> DynamicImage {
+        img = match self.rotate {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            _ => img,
+        };
+        if self.flip {
+            img = img.flipv();
+        }
+        if self.mirror {
+            img = img.fliph();
+        }
+        if self.invert {
+            img.invert();
+        }
+        if self.grayscale {
+            img = img.grayscale();
+        }
+        img
+    }
+
+    // Only set when the input, once sped up, would go over max_fps.
+    pub fn cap_fps(&mut self, info: &VideoInfo) {
+        let max = config::get().max_fps;
+        if self.fps.is_some() {
+            return;
+        }
+        if let Some(fps) = info.fps {
+            if fps * self.speed.unwrap_or(1.0) > max as f64 + 0.01 {
+                self.fps = Some(max);
+            }
+        }
+    }
+
+    pub fn target(&self) -> Target {
+        self.target.unwrap_or_default()
+    }
+
+    pub fn upscaler(&self) -> Upscaler {
+        self.upscale.unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trim.is_none()
+            && self.crop.is_none()
+            && self.speed.is_none()
+            && self.fps.is_none()
+            && !self.pad
+            && self.text.is_none()
+            && self.rotate == 0
+            && !self.flip
+            && !self.mirror
+            && !self.invert
+            && !self.grayscale
+            && self.bg.is_none()
+            && self.style.is_none()
+            && self.pixel.is_none()
+            && self.colors.is_none()
+            && !self.reverse
+            && !self.boomerang
+    }
+
+    // Stickers made without options, which are the ones told apart by what they show.
+    pub fn is_plain(&self) -> bool {
+        self.target() == Target::Sticker && self.is_passthrough()
+    }
+
+    // Changes how an animation plays, which is all a sticker can be given.
+    pub fn is_replay(&self) -> bool {
+        self.speed.is_some() || self.reverse || self.boomerang
+    }
+
+    // Whether a file that already is a valid sticker can be given back as it is.
+    pub fn is_passthrough(&self) -> bool {
+        self.is_empty()
+            && self.watermark.is_none()
+            && self.target().is_telegram()
+            && !self.cutout
+            && !self.thumb
+            && !self.webp
+            && !self.smartcrop
+            && !self.lossy
+            && self.quality.is_none()
+            && self.grid.is_none()
+            && self.frames.is_none()
+    }
+
+    pub fn fill_background(&self, img: DynamicImage) -> DynamicImage {
+        let Some([r, g, b]) = self.bg else {
+            return img;
+        };
+        let (w, h) = img.dimensions();
+        let mut canvas = RgbaImage::from_pixel(w, h, Rgba([r, g, b, 255]));
+        imageops::overlay(&mut canvas, &img.to_rgba8(), 0, 0);
+        DynamicImage::ImageRgba8(canvas)
+    }
+
+    // Appended after scaling and padding, so that the padding is filled too.
+    pub fn background_filter(&self) -> String {
+        let Some([r, g, b]) = self.bg else {
+            return String::new();
+        };
+        format!(
+            ",format=rgba,split[fg][bg];[bg]drawbox=c=0x{:02x}{:02x}{:02x}@1:replace=1:t=fill[bg];[bg][fg]overlay",
+            r, g, b
+        )
+    }
+
+    // Input options selecting the segment to read, and the decoder.
+    pub fn input_args(&self) -> Vec<String> {
+        let mut v = match self.trim {
+            Some((a, b)) => vec![
+                "-ss".to_owned(),
+                a.to_string(),
+                "-t".to_owned(),
+                (b - a).to_string(),
+            ],
+            None => vec![],
+        };
+        if self.vp9 {
+            v.extend(["-c:v".to_owned(), "libvpx-vp9".to_owned()]);
+        }
+        v
+    }
+
+    // Filters to prepend to the scaling filter, each followed by a comma.
+    pub fn filters(&self) -> String {
+        let mut s = String::new();
+        if let Some(crop) = self.crop {
+            let (x, y) = match crop {
+                Crop::Center => ("(iw-ow)/2", "(ih-oh)/2"),
+                Crop::Top => ("(iw-ow)/2", "0"),
+                Crop::Bottom => ("(iw-ow)/2", "ih-oh"),
+                Crop::Left => ("0", "(ih-oh)/2"),
+                Crop::Right => ("iw-ow", "(ih-oh)/2"),
+            };
+            write!(s, "crop=w='min(iw,ih)':h='min(iw,ih)':x={}:y={},", x, y).unwrap();
+        }
+        match self.rotate {
+            90 => s.push_str("transpose=clock,"),
+            180 => s.push_str("hflip,vflip,"),
+            270 => s.push_str("transpose=cclock,"),
+            _ => {}
+        }
+        if self.flip {
+            s.push_str("vflip,");
+        }
+        if self.mirror {
+            s.push_str("hflip,");
+        }
+        if self.invert {
+            s.push_str("negate,");
+        }
+        if self.grayscale {
+            s.push_str("hue=s=0,");
+        }
+        if let Some(style) = self.style {
+            s.push_str(style.ffmpeg_filter());
+        }
+        if self.reverse || self.boomerang {
+            // Reversing holds every frame in memory, so only what the output has room for is read.
+            let mut d = MAX_DURATION * self.speed.unwrap_or(1.0);
+            if self.boomerang {
+                d /= 2.0;
+            }
+            write!(s, "trim=duration={},setpts=PTS-STARTPTS,", d).unwrap();
+            if self.reverse {
+                s.push_str("reverse,");
+            }
+            if self.boomerang {
+                s.push_str("split[bf][bb];[bb]reverse[br];[bf][br]concat=n=2:v=1:a=0,");
+            }
+        }
+        if let Some(speed) = self.speed {
+            write!(s, "setpts=PTS/{},", speed).unwrap();
+        }
+        // After setpts, as speeding up raises the frame rate.
+        if let Some(fps) = self.fps {
+            write!(s, "fps={},", fps).unwrap();
+        }
+        if let Some(n) = self.pixel {
+            write!(
+                s,
+                "scale=w={0}:h={0}:force_original_aspect_ratio=decrease:flags=neighbor,",
+                n
+            )
+            .unwrap();
+        }
+        if let Some(n) = self.colors {
+            write!(
+                s,
+                "format=rgba,split[q0][q1];[q1]palettegen=max_colors={}:reserve_transparent=1[pal];[q0][pal]paletteuse=dither=none,",
+                n
+            )
+            .unwrap();
+        }
+        s
+    }
+
+    // Fits into a square of `side`, without smoothing for pixel art.
+    pub fn scale_filter(&self, side: u32) -> String {
+        match self.pixel {
+            Some(_) => format!(
+                "scale=w={0}:h={0}:force_original_aspect_ratio=decrease:flags=neighbor",
+                side
+            ),
+            None => target::scale_filter(side),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(caption: &str) -> Result<VideoEdit, BotError> {
+        config::init();
+        VideoEdit::parse(caption)
+    }
+
+    fn bad_id(caption: &str) -> &'static str {
+        match parse(caption) {
+            Err(BotError::BadOption { id, .. }) => id,
+            r => panic!("{:?} parsed to {:?}", caption, r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn flags_and_values() {
+        let e = parse("/pad rotate=90 mirror speed 2x trim=0.5-3").unwrap();
+        assert!(e.pad && e.mirror);
+        assert_eq!(e.rotate, 90);
+        assert_eq!(e.speed, Some(2.0));
+        assert_eq!(e.trim, Some((0.5, 3.0)));
+        assert!(parse("some words").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_option_ids() {
+        assert_eq!(bad_id("trim=3-1"), "bad-trim");
+        assert_eq!(bad_id("crop=diagonal"), "bad-crop");
+        assert_eq!(bad_id("speed=9"), "bad-speed");
+        assert_eq!(bad_id("fps=0"), "bad-fps");
+        assert_eq!(bad_id("rotate=45"), "bad-rotate");
+        assert_eq!(bad_id("q=0"), "bad-quality");
+        assert_eq!(bad_id("bg=nope"), "bad-bg");
+        assert_eq!(bad_id("frames=0"), "bad-frames");
+        assert_eq!(bad_id("pos=left"), "bad-pos");
+        assert_eq!(bad_id("colors=1"), "bad-colors");
+        assert_eq!(bad_id("size=3"), "bad-option");
+    }
+
+    #[test]
+    fn grid_bounds() {
+        assert_eq!(parse("grid=2x3").unwrap().grid, Some((2, 3)));
+        assert_eq!(parse("grid=8×8").unwrap().grid, Some((8, 8)));
+        assert_eq!(bad_id("grid=0x3"), "bad-grid");
+        assert_eq!(bad_id("grid=9x8"), "bad-grid");
+        assert_eq!(bad_id("grid=1x65"), "bad-grid");
+        assert_eq!(bad_id("grid=65536x65536"), "bad-grid");
+        assert_eq!(bad_id("grid=3"), "bad-grid");
+    }
+
+    #[test]
+    fn text_overlay() {
+        let e = parse("pad pos=top text:  Hello world ").unwrap();
+        assert!(e.pad);
+        let o = e.text.unwrap();
+        assert_eq!(o.text, "Hello world");
+        assert_eq!(o.position, Position::Top);
+        // Options after it are part of the text.
+        let e = parse("text: rotate=45").unwrap();
+        assert_eq!(e.text.unwrap().text, "rotate=45");
+        assert_eq!(e.rotate, 0);
+        assert!(parse("mirror text:").unwrap().text.is_none());
+    }
+
+    #[test]
+    fn text_only_as_a_word() {
+        assert!(parse("context: mirror").unwrap().text.is_none());
+        assert!(parse("context: mirror").unwrap().mirror);
+        let e = parse("pretext:x\ttext:Hi").unwrap();
+        assert_eq!(e.text.unwrap().text, "Hi");
+        assert_eq!(parse("text:Hi").unwrap().text.unwrap().text, "Hi");
+    }
+}
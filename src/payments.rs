@@ -0,0 +1,110 @@
+// Donations and premium paid in Telegram Stars, which take no payment provider. /donate sends an
+// invoice of DONATE_STARS, or of the amount given, and /premium one of PREMIUM_STARS for
+// PREMIUM_DAYS. Premium lifts the daily quota, takes files up to PREMIUM_FILE_SIZE_MB and zips of
+// a whole pack, and goes ahead of everyone else waiting for a converter. Telegram asks whether an
+// invoice is still good before charging for it, and premium starts once it tells it was paid.
+
+use crate::access::Actor;
+use crate::config;
+use crate::i18n::Msg;
+use crate::quota;
+use log::{error, info};
+use teloxide::types::{LabeledPrice, PreCheckoutQuery, SuccessfulPayment};
+
+pub const CURRENCY: &str = "XTR";
+// The most an invoice in Stars can ask.
+pub const MAX_STARS: u32 = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payload {
+    Donation,
+    Premium { days: u32 },
+}
+
+impl Payload {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once(':') {
+            None if s == "donate" => Some(Self::Donation),
+            Some(("premium", days)) => days.parse().ok().map(|days| Self::Premium { days }),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self) -> String {
+        match self {
+            Self::Donation => "donate".to_owned(),
+            Self::Premium { days } => format!("premium:{}", days),
+        }
+    }
+
+    // The title and the description of the invoice.
+    pub fn msgs(self) -> (Msg, Msg) {
+        match self {
+            Self::Donation => (Msg::new("donate-title"), Msg::new("donate-description")),
+            Self::Premium { days } => (
+                Msg::new("premium-title"),
+                Msg::new("premium-description").arg("days", days),
+            ),
+        }
+    }
+}
+
+pub fn price(label: String, stars: u32) -> LabeledPrice {
+    LabeledPrice::new(label, stars as i32)
+}
+
+// Premium invoices made before PREMIUM_STARS or PREMIUM_DAYS changed are turned down, with the
+// message id of why.
+pub fn check(q: &PreCheckoutQuery) -> Result<(), &'static str> {
+    let cfg = config::get();
+    let payload = Payload::parse(&q.invoice_payload).ok_or("invoice-outdated")?;
+    if q.currency != CURRENCY {
+        return Err("invoice-outdated");
+    }
+    match payload {
+        Payload::Donation if (1..=MAX_STARS as i32).contains(&q.total_amount) => Ok(()),
+        Payload::Premium { days }
+            if cfg.premium_stars.map(|n| n as i32) == Some(q.total_amount)
+                && days == cfg.premium_days =>
+        {
+            Ok(())
+        }
+        _ => Err("invoice-outdated"),
+    }
+}
+
+// What the payer is told once charged.
+pub fn paid(actor: Actor, p: &SuccessfulPayment) -> Msg {
+    info!(
+        "{:?} paid {} {} for {:?}, charge {}",
+        actor, p.total_amount, p.currency, p.invoice_payload, p.telegram_payment_charge_id
+    );
+    match Payload::parse(&p.invoice_payload) {
+        Some(Payload::Premium { days }) => match quota::add_premium(actor, days) {
+            Ok(_) => Msg::new("premium-paid").arg("days", days),
+            Err(e) => {
+                error!(
+                    "premium of {:?}, charge {}: {}",
+                    actor, p.telegram_payment_charge_id, e
+                );
+                Msg::new("premium-save-failed").arg("charge", &p.telegram_payment_charge_id)
+            }
+        },
+        _ => Msg::new("donate-thanks"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_round_trip() {
+        for p in [Payload::Donation, Payload::Premium { days: 30 }] {
+            assert_eq!(Payload::parse(&p.encode()), Some(p));
+        }
+        assert_eq!(Payload::parse("premium:x"), None);
+        assert_eq!(Payload::parse("donate:5"), None);
+        assert_eq!(Payload::parse(""), None);
+    }
+}
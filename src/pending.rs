@@ -0,0 +1,64 @@
+// Downloaded videos waiting for the user to pick which segment to convert.
+
+use crate::offers::{Kind, Offers};
+use crate::options::VideoEdit;
+use crate::probe::VideoInfo;
+use tempfile::TempPath;
+
+pub static PENDING: Offers<Pending> = Offers::new("seg", Kind::Video);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    First,
+    Middle,
+    Last,
+    // Speed up the whole video to fit.
+    Fit,
+}
+
+impl Segment {
+    // With the message ids of their labels.
+    pub const ALL: [(Segment, &'static str); 4] = [
+        (Segment::First, "segment-first"),
+        (Segment::Middle, "segment-middle"),
+        (Segment::Last, "segment-last"),
+        (Segment::Fit, "segment-fit"),
+    ];
+
+    fn code(self) -> char {
+        match self {
+            Segment::First => 'f',
+            Segment::Middle => 'm',
+            Segment::Last => 'l',
+            Segment::Fit => 's',
+        }
+    }
+
+    fn from_code(s: &str) -> Option<Self> {
+        match s {
+            "f" => Some(Segment::First),
+            "m" => Some(Segment::Middle),
+            "l" => Some(Segment::Last),
+            "s" => Some(Segment::Fit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Pending {
+    pub path: TempPath,
+    pub info: VideoInfo,
+    pub base: Option<String>,
+    // Carries what applies besides the segment, like the target.
+    pub edit: VideoEdit,
+}
+
+pub fn callback_data(token: u64, seg: Segment) -> String {
+    PENDING.callback_data(token, seg.code())
+}
+
+pub fn parse_callback_data(data: &str) -> Option<(u64, Segment)> {
+    let (token, code) = PENDING.parse_callback_data(data)?;
+    Some((token, Segment::from_code(code)?))
+}
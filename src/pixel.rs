@@ -0,0 +1,54 @@
+// Pixel art for /pixel: images are brought down to a grid of a few dozen pixels with
+// nearest-neighbor sampling, optionally limited to a palette of colors found by NeuQuant, and
+// scaled back up without smoothing, so that every pixel stays a crisp block where Lanczos would
+// blur it. Videos get the same through ffmpeg, see VideoEdit::filters.
+
+use color_quant::NeuQuant;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+
+pub const DEFAULT_SIZE: u32 = 64;
+pub const MIN_SIZE: u32 = 8;
+pub const MAX_SIZE: u32 = 256;
+pub const MAX_COLORS: u16 = 256;
+// Of NeuQuant, from 1 to 30, lower being slower and better.
+const SAMPLE_FACTOR: i32 = 10;
+
+pub fn quantize(img: &mut RgbaImage, colors: u16) {
+    let nq = NeuQuant::new(SAMPLE_FACTOR, colors as usize, img.as_raw());
+    let map = nq.color_map_rgba();
+    for p in img.pixels_mut() {
+        let i = nq.index_of(&p.0);
+        let a = p[3];
+        p.0.copy_from_slice(&map[i * 4..i * 4 + 4]);
+        // The alpha is kept rather than quantized.
+        p[3] = a;
+    }
+}
+
+// Fits into a square of `side`, the pixel art way when `size` is given.
+pub fn resize(
+    img: &DynamicImage,
+    side: u32,
+    size: Option<u32>,
+    colors: Option<u16>,
+) -> DynamicImage {
+    let Some(size) = size else {
+        let img = img.resize(side, side, FilterType::Lanczos3);
+        let Some(colors) = colors else {
+            return img;
+        };
+        let mut rgba = img.to_rgba8();
+        quantize(&mut rgba, colors);
+        return DynamicImage::ImageRgba8(rgba);
+    };
+    let mut small = img.resize(size, size, FilterType::Nearest).to_rgba8();
+    // Blocks are either there or not.
+    for p in small.pixels_mut() {
+        p[3] = if p[3] >= 128 { 255 } else { 0 };
+    }
+    if let Some(colors) = colors {
+        quantize(&mut small, colors);
+    }
+    DynamicImage::ImageRgba8(small).resize(side, side, FilterType::Nearest)
+}
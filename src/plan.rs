@@ -0,0 +1,312 @@
+// What converting a file would do, found out without sending anything: the detected format, the
+// steps, the encoder and about how big the result gets, for /plan and convert --dry-run. Stills
+// are fitted for real, as that is quick and is where the silent fallback to png happens, while the
+// size of videos is guessed from their parameters.
+
+use crate::error::user_message;
+use crate::hwaccel;
+use crate::options::VideoEdit;
+use crate::target::Target;
+use crate::upscale::{self, Upscaler};
+use crate::{
+    config, encode_static, formats, load_image, pad_square, pixel, probe, psd, temp_file,
+    tgs_to_gif, vector, MAX_DURATION,
+};
+use anyhow::Result as AnyResult;
+use image::GenericImageView;
+use std::fmt;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+// Rough bits per pixel of VP9 on sticker-like inputs, lossless and not.
+const LOSSLESS_BPP: f64 = 0.6;
+const LOSSY_BPP: f64 = 0.06;
+
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub format: String,
+    pub steps: Vec<String>,
+    pub output: String,
+    pub size: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "format: {}", self.format)?;
+        writeln!(f, "pipeline: {}", self.steps.join(" → "))?;
+        write!(f, "output: {}", self.output)?;
+        if let Some(s) = &self.size {
+            write!(f, "\nsize: {}", s)?;
+        }
+        for n in &self.notes {
+            write!(f, "\n- {}", n)?;
+        }
+        Ok(())
+    }
+}
+
+// Between two powers of two, in KB.
+fn band(bytes: f64) -> String {
+    let kb = (bytes / 1000.0).max(1.0);
+    let lo = 2f64.powi(kb.log2().floor() as i32);
+    format!("about {}–{} KB", lo, lo * 2.0)
+}
+
+// Up or down, as both image's resize and ffmpeg's scale filter do.
+fn fit(w: u32, h: u32, side: u32) -> (u32, u32) {
+    let k = side as f64 / w.max(h).max(1) as f64;
+    (
+        ((w as f64 * k).round() as u32).max(1),
+        ((h as f64 * k).round() as u32).max(1),
+    )
+}
+
+// Those shared by stills and videos, in the order they are applied.
+fn edit_steps(edit: &VideoEdit) -> Vec<String> {
+    let mut v = vec![];
+    if edit.crop.is_some() {
+        v.push("crop".to_owned());
+    }
+    if edit.rotate != 0 {
+        v.push(format!("rotate {}°", edit.rotate));
+    }
+    for (on, name) in [
+        (edit.flip, "flip"),
+        (edit.mirror, "mirror"),
+        (edit.invert, "invert"),
+        (edit.grayscale, "grayscale"),
+    ] {
+        if on {
+            v.push(name.to_owned());
+        }
+    }
+    if let Some(s) = edit.style {
+        v.push(format!("{:?} filter", s).to_lowercase());
+    }
+    v
+}
+
+fn overlay_steps(edit: &VideoEdit, v: &mut Vec<String>) {
+    let target = edit.target();
+    if edit.pad || target.square() {
+        v.push(format!("pad to {0}×{0}", target.side()));
+    }
+    if edit.bg.is_some() {
+        v.push("fill background".to_owned());
+    }
+    if edit.text.is_some() {
+        v.push("draw text".to_owned());
+    }
+    if edit.watermark.is_some() {
+        v.push("watermark".to_owned());
+    }
+}
+
+fn image_format(data: &[u8]) -> String {
+    if let Some(k) = vector::sniff(data) {
+        return format!("{:?}", k).to_lowercase();
+    }
+    if psd::sniff(data) {
+        return "psd".to_owned();
+    }
+    if let Some(k) = formats::sniff(data) {
+        return format!("{:?}", k).to_lowercase();
+    }
+    image::guess_format(data).map_or("unknown".to_owned(), |f| format!("{:?}", f).to_lowercase())
+}
+
+pub async fn image(data: Vec<u8>, edit: &VideoEdit) -> AnyResult<Plan> {
+    let target = edit.target();
+    let side = target.side();
+    let mut p = Plan {
+        format: image_format(&data),
+        ..Default::default()
+    };
+    p.steps.push(match vector::sniff(&data) {
+        Some(_) => format!("render at {} px", side),
+        None => "decode".to_owned(),
+    });
+    let img = load_image(data, side).await?;
+    let (w, h) = img.dimensions();
+    p.format = format!("{} {}×{}", p.format, w, h);
+    if edit.smartcrop {
+        p.steps.push("smart crop".to_owned());
+    }
+    if edit.upscaler() == Upscaler::Model && w.max(h) < side {
+        p.steps.push("upscale with the model".to_owned());
+    }
+    let (fw, fh) = fit(w, h, upscale::fit_side(&img, side, edit.upscaler()));
+    p.steps.push(format!("fit into {}×{}", fw, fh));
+    if let Some(n) = edit.pixel {
+        p.steps.push(format!("pixelate to {} px", n));
+    }
+    if edit.cutout {
+        p.steps.push("remove background".to_owned());
+    }
+    p.steps.extend(edit_steps(edit));
+    overlay_steps(edit, &mut p.steps);
+    p.output = match target {
+        Target::Discord => "png for Discord".to_owned(),
+        Target::Whatsapp => "webp for WhatsApp".to_owned(),
+        Target::Signal => "webp for Signal".to_owned(),
+        _ => match edit.quality {
+            Some(q) => format!("webp at quality {}", q),
+            None => "lossless webp".to_owned(),
+        },
+    };
+    if !target.is_telegram() {
+        return Ok(p);
+    }
+    // What is fitted lacks the text and such, which add little, and is upscaled with Lanczos.
+    let fit_side = upscale::fit_side(&img, side, edit.upscaler());
+    let mut img = pixel::resize(&img, fit_side, edit.pixel, edit.colors);
+    if edit.pad || target.square() {
+        img = pad_square(&img, side);
+    }
+    let quality = edit.quality;
+    match tokio::task::spawn_blocking(move || encode_static(&img, quality)).await? {
+        Ok(b) => {
+            if b.ext != "webp" {
+                p.output = b.ext.to_owned();
+            }
+            p.size = Some(band(b.len() as f64));
+            if let Some(m) = &b.note {
+                p.notes.push(m.tr("en"));
+            }
+        }
+        Err(e) => {
+            p.output = "none".to_owned();
+            p.notes.push(user_message(&e, false).tr("en"));
+        }
+    }
+    Ok(p)
+}
+
+fn encoder(edit: &VideoEdit) -> String {
+    let plain = edit.is_empty() && edit.watermark.is_none() && !edit.target().square();
+    if cfg!(feature = "libav") && plain {
+        return "VP9 by libav".to_owned();
+    }
+    match hwaccel::get().and_then(|hw| hw.encoder()) {
+        Some(enc) => format!("VP9 by {}, or else libvpx-vp9", enc),
+        None => "VP9 by libvpx-vp9".to_owned(),
+    }
+}
+
+pub async fn video(path: &Path, edit: &VideoEdit) -> AnyResult<Plan> {
+    let mut edit = edit.clone();
+    let target = edit.target();
+    let mut p = Plan::default();
+    let info = match probe::probe(path).await {
+        Some(info) => info,
+        None => {
+            p.notes.push("ffprobe cannot read it".to_owned());
+            Default::default()
+        }
+    };
+    p.format = format!("{} {}×{}", info.codec, info.width, info.height);
+    if let Some(fps) = info.fps {
+        p.format += &format!(" {:.1} fps", fps);
+    }
+    let mut duration = info.duration.unwrap_or(MAX_DURATION);
+    p.format += &format!(" {:.1} s", duration);
+    if let Some((a, b)) = edit.trim {
+        p.steps.push(format!("trim {}–{} s", a, b));
+        duration = duration.min(b - a);
+    }
+    if edit.is_empty() && duration > MAX_DURATION + 0.1 {
+        let cfg = config::get();
+        if !cfg.auto_speedup || duration > MAX_DURATION * cfg.max_auto_speed {
+            p.steps
+                .push(format!("ask which {} s to keep", MAX_DURATION));
+            duration = MAX_DURATION;
+        } else {
+            edit.speed = Some(duration / MAX_DURATION);
+        }
+    }
+    p.steps.extend(edit_steps(&edit));
+    if edit.reverse {
+        p.steps.push("reverse".to_owned());
+    }
+    if edit.boomerang {
+        p.steps.push("boomerang".to_owned());
+    }
+    if let Some(s) = edit.speed {
+        p.steps.push(format!("speed {:.2}x", s));
+        duration /= s;
+    }
+    edit.cap_fps(&info);
+    if let Some(n) = edit.fps {
+        p.steps.push(format!("{} fps", n));
+    }
+    let side = target.side();
+    let (w, h) = fit(info.width, info.height, side);
+    p.steps.push(format!("fit into {}×{}", w, h));
+    overlay_steps(&edit, &mut p.steps);
+    let duration = duration.min(MAX_DURATION);
+    p.steps.push(format!("cut at {:.1} s", duration));
+
+    if edit.webp {
+        p.output = "animated webp".to_owned();
+        return Ok(p);
+    }
+    match target {
+        Target::Discord | Target::Signal => {
+            p.output = "apng, at lower frame rates until it fits".to_owned();
+            return Ok(p);
+        }
+        Target::Whatsapp => {
+            p.output = "animated webp, at lower qualities until it fits".to_owned();
+            return Ok(p);
+        }
+        _ => {}
+    }
+    let max = target.max_webm_size();
+    let fps = edit.fps.map(f64::from).or(info.fps).unwrap_or(30.0);
+    let (w, h) = if edit.pad || target.square() {
+        (side, side)
+    } else {
+        (w, h)
+    };
+    let pixels = w as f64 * h as f64 * fps * duration;
+    let lossy = pixels * LOSSY_BPP / 8.0;
+    if edit.lossy {
+        p.output = format!("lossy {}", encoder(&edit));
+        p.size = Some(band(lossy));
+    } else {
+        let lossless = pixels * LOSSLESS_BPP / 8.0;
+        p.output = format!(
+            "lossless {}, or lossy over {} KB",
+            encoder(&edit),
+            max / 1000
+        );
+        p.size = Some(band(if lossless <= max as f64 {
+            lossless
+        } else {
+            lossy
+        }));
+    }
+    if lossy > max as f64 {
+        p.notes
+            .push(format!("likely over {} KB even when lossy", max / 1000));
+    }
+    if info.has_alpha && hwaccel::get().and_then(|hw| hw.encoder()).is_some() {
+        p.notes
+            .push("the hardware encoder drops transparency".to_owned());
+    }
+    Ok(p)
+}
+
+// The animation has to be rendered to be seen into.
+pub async fn tgs(path: &Path, edit: &VideoEdit) -> AnyResult<Plan> {
+    let gif = tgs_to_gif(path).await?;
+    let (gif_path, mut f) = temp_file().await?;
+    f.write_all(&gif.bytes().await?).await?;
+    drop(f);
+    let mut p = video(&gif_path, edit).await?;
+    p.format = format!("tgs, rendered to {}", p.format);
+    p.steps
+        .insert(0, format!("render with {}", config::get().tgs_to_gif));
+    Ok(p)
+}
@@ -0,0 +1,79 @@
+// Named sets of caption options. A caption starting with the name of a preset, such as "meme
+// Hello" or "/meme Hello", has it put in place of the name before it is parsed. Users save their
+// own with /preset, which shadow the built-in ones of the same name. Names of options and commands
+// are not taken, as captions made of them would be rewritten.
+
+use crate::options;
+use crate::settings::Settings;
+use std::borrow::Cow;
+
+pub const MAX_PRESETS: usize = 20;
+pub const MAX_NAME_LEN: usize = 32;
+pub const MAX_OPTIONS_LEN: usize = 200;
+
+// The meme one ends in "text:", so that the rest of the caption is drawn.
+pub const BUILTIN: [(&str, &str); 2] = [
+    ("meme", "sticker lossy text:"),
+    ("lossless-archive", "lossless"),
+];
+
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !options::is_keyword(name)
+        && !crate::COMMANDS.iter().any(|c| c[1..] == *name)
+}
+
+// Those saved under names no longer taken are left alone.
+pub fn lookup<'a>(s: &'a Settings, name: &str) -> Option<&'a str> {
+    if !valid_name(name) {
+        return None;
+    }
+    s.presets.get(name).map(String::as_str).or_else(|| {
+        BUILTIN
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, options)| options)
+    })
+}
+
+pub fn expand<'a>(caption: &'a str, s: &Settings) -> Cow<'a, str> {
+    let caption = caption.trim_start();
+    let (first, rest) = caption
+        .split_once(char::is_whitespace)
+        .unwrap_or((caption, ""));
+    let name = first.strip_prefix('/').unwrap_or(first);
+    let name = name.split('@').next().unwrap_or(name);
+    match lookup(s, name) {
+        Some(options) => Cow::Owned(format!("{} {}", options, rest)),
+        None => Cow::Borrowed(caption),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_of_options_and_commands_refused() {
+        assert!(valid_name("meme"));
+        assert!(valid_name("my_preset-2"));
+        for name in [
+            "pad", "sticker", "lossy", "speed", "emoji", "archive", "preset", "q",
+        ] {
+            assert!(!valid_name(name), "{}", name);
+        }
+        assert!(!valid_name("a b"));
+        assert!(!valid_name(""));
+    }
+
+    #[test]
+    fn builtin_names_valid() {
+        for (name, _) in BUILTIN {
+            assert!(valid_name(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::error::BotError;
+use crate::target::Target;
+use crate::{config, wait_output, MAX_DURATION};
+use anyhow::Result as AnyResult;
+use flate2::read::GzDecoder;
+use image::io::Reader as ImageReader;
+use image::GenericImageView;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Write;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoInfo {
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: Option<f64>,
+    pub duration: Option<f64>,
+    pub has_alpha: bool,
+    pub has_audio: bool,
+}
+
+fn parse_rate(s: &str) -> Option<f64> {
+    let (n, d) = s.split_once('/').unwrap_or((s, "1"));
+    let (n, d): (f64, f64) = (n.parse().ok()?, d.parse().ok()?);
+    Some(n / d).filter(|x| x.is_finite() && *x > 0.0)
+}
+
+impl VideoInfo {
+    // Parses the json writer's output, taking the first video stream.
+    fn parse(v: &Value) -> Self {
+        let mut r = Self::default();
+        let streams = v["streams"].as_array().map_or(&[][..], |a| a.as_slice());
+        for s in streams {
+            match s["codec_type"].as_str() {
+                Some("audio") => r.has_audio = true,
+                Some("video") if r.codec.is_empty() => {
+                    r.codec = s["codec_name"].as_str().unwrap_or("").to_owned();
+                    r.width = s["width"].as_u64().unwrap_or(0) as u32;
+                    r.height = s["height"].as_u64().unwrap_or(0) as u32;
+                    r.fps = s["avg_frame_rate"]
+                        .as_str()
+                        .and_then(parse_rate)
+                        .or_else(|| s["r_frame_rate"].as_str().and_then(parse_rate));
+                    // libvpx decodes to yuv420p even when the webm carries an alpha channel.
+                    r.has_alpha = s["pix_fmt"].as_str().map_or(false, |f| {
+                        f.starts_with("yuva") || f.contains("rgba") || f.contains("argb")
+                    }) || s["tags"]["alpha_mode"].as_str() == Some("1");
+                }
+                _ => {}
+            }
+        }
+        // Both the stream and the format may report one, the latter is more reliable.
+        r.duration = v["format"]["duration"]
+            .as_str()
+            .and_then(|s| s.parse().ok());
+        r
+    }
+}
+
+pub async fn probe(file: &Path) -> Option<VideoInfo> {
+    let out = wait_output(
+        Command::new(&config::get().ffprobe)
+            .args(["-v", "error", "-show_entries"])
+            .arg(
+                "stream=codec_type,codec_name,width,height,avg_frame_rate,r_frame_rate,pix_fmt\
+                 :stream_tags=alpha_mode:format=duration",
+            )
+            .args(["-of", "json"])
+            .arg(file)
+            .stdout(Stdio::piped()),
+        config::get().probe_timeout,
+    )
+    .await;
+    match out {
+        Ok(out) if out.status.success() => match serde_json::from_slice(&out.stdout) {
+            Ok(v) => Some(VideoInfo::parse(&v)),
+            Err(e) => {
+                warn!("ffprobe: {}", e);
+                None
+            }
+        },
+        Ok(out) => {
+            warn!("ffprobe failed: {:?}", out.status);
+            None
+        }
+        Err(e) => {
+            warn!("ffprobe: {}", e);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StickerKind {
+    Static,
+    Animated,
+    Video,
+}
+
+impl StickerKind {
+    pub fn from_ext(ext: &str) -> Option<Self> {
+        match ext {
+            "webp" | "png" => Some(Self::Static),
+            "tgs" => Some(Self::Animated),
+            "webm" => Some(Self::Video),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Static => "static sticker",
+            Self::Animated => "animated sticker",
+            Self::Video => "video sticker",
+        }
+    }
+
+    pub fn max_size(self, target: Target) -> u64 {
+        match self {
+            Self::Static => 512 * 1000,
+            Self::Animated => 64 * 1000,
+            Self::Video => target.max_webm_size(),
+        }
+    }
+
+    fn max_fps(self) -> u32 {
+        match self {
+            Self::Animated => 60,
+            _ => config::get().max_fps,
+        }
+    }
+}
+
+// Telegram's sticker requirements that a file of `size` bytes breaks, in words.
+pub fn violations(kind: StickerKind, target: Target, v: &VideoInfo, size: u64) -> Vec<String> {
+    let mut r = Vec::new();
+    let side = target.side();
+    // Lottie canvases are 512x512 even for custom emoji.
+    if kind == StickerKind::Animated {
+        if (v.width, v.height) != (512, 512) {
+            r.push("not 512×512".to_owned());
+        }
+    } else if target == Target::Emoji {
+        if (v.width, v.height) != (side, side) {
+            r.push(format!("not {0}×{0}", side));
+        }
+    } else if v.width > side || v.height > side {
+        r.push(format!("larger than {} px", side));
+    } else if v.width != side && v.height != side {
+        r.push(format!("neither side is {} px", side));
+    }
+    let max_size = kind.max_size(target);
+    if size > max_size {
+        r.push(format!("larger than {} KB", max_size / 1000));
+    }
+    if kind == StickerKind::Static {
+        return r;
+    }
+    if kind == StickerKind::Video {
+        if v.codec != "vp9" {
+            r.push(format!("encoded in {} instead of VP9", v.codec));
+        }
+        if v.has_audio {
+            r.push("has an audio stream".to_owned());
+        }
+    }
+    if v.duration.map_or(false, |d| d > MAX_DURATION + 0.05) {
+        r.push(format!("longer than {} s", MAX_DURATION));
+    }
+    if v.fps.map_or(false, |x| x > kind.max_fps() as f64 + 0.01) {
+        r.push(format!("above {} fps", kind.max_fps()));
+    }
+    r
+}
+
+// A one-line summary such as "512×470 webm, 212 KB, 2.9 s, 30 fps — valid video sticker ✅".
+pub fn report(kind: StickerKind, target: Target, ext: &str, v: &VideoInfo, size: u64) -> String {
+    let mut s = format!(
+        "{}×{} {}, {} KB",
+        v.width,
+        v.height,
+        ext,
+        (size + 500) / 1000
+    );
+    if let Some(d) = v.duration {
+        write!(s, ", {:.1} s", d).unwrap();
+    }
+    if let Some(fps) = v.fps {
+        write!(s, ", {} fps", fps.round()).unwrap();
+    }
+    if v.has_alpha {
+        s.push_str(", transparent");
+    }
+    let bad = violations(kind, target, v, size);
+    if bad.is_empty() {
+        write!(s, " — valid {} ✅", kind.name()).unwrap();
+    } else {
+        write!(s, " — not a valid {}: {} ❌", kind.name(), bad.join(", ")).unwrap();
+    }
+    s
+}
+
+// Lists everything /check found wrong with a file, or that it is fine.
+pub fn check_report(
+    kind: StickerKind,
+    target: Target,
+    ext: &str,
+    v: &VideoInfo,
+    size: u64,
+) -> String {
+    let mut s = format!(
+        "{}×{} {}, {} KB",
+        v.width,
+        v.height,
+        ext,
+        (size + 500) / 1000
+    );
+    if !v.codec.is_empty() {
+        write!(s, ", {}", v.codec).unwrap();
+    }
+    if let Some(d) = v.duration {
+        write!(s, ", {:.2} s", d).unwrap();
+    }
+    if let Some(fps) = v.fps {
+        write!(s, ", {:.2} fps", fps).unwrap();
+    }
+    s.push_str(if v.has_alpha {
+        ", transparent"
+    } else {
+        ", opaque"
+    });
+    let bad = violations(kind, target, v, size);
+    if bad.is_empty() {
+        write!(s, "\n✅ Valid {}.", kind.name()).unwrap();
+    } else {
+        write!(s, "\n❌ Not a valid {}:", kind.name()).unwrap();
+        for b in bad {
+            write!(s, "\n• {}", b).unwrap();
+        }
+    }
+    s
+}
+
+// Reads the Lottie header of a gzipped tgs file.
+pub fn inspect_tgs(data: &[u8]) -> AnyResult<VideoInfo> {
+    let mut json = Vec::new();
+    GzDecoder::new(data)
+        .take(16 << 20)
+        .read_to_end(&mut json)
+        .map_err(|_| BotError::BadTgs)?;
+    let v: Value = serde_json::from_slice(&json).map_err(|_| BotError::BadTgs)?;
+    let fr = v["fr"].as_f64().unwrap_or(0.0);
+    let frames = v["op"].as_f64().unwrap_or(0.0) - v["ip"].as_f64().unwrap_or(0.0);
+    Ok(VideoInfo {
+        codec: "lottie".to_owned(),
+        width: v["w"].as_u64().unwrap_or(0) as u32,
+        height: v["h"].as_u64().unwrap_or(0) as u32,
+        fps: Some(fr).filter(|&x| x > 0.0),
+        duration: Some(frames / fr).filter(|x| x.is_finite()),
+        has_alpha: true,
+        has_audio: false,
+    })
+}
+
+pub fn inspect_image(data: &[u8]) -> AnyResult<VideoInfo> {
+    let img = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .decode()
+        .map_err(|_| BotError::NotAnImage)?;
+    let (width, height) = img.dimensions();
+    Ok(VideoInfo {
+        width,
+        height,
+        has_alpha: img.color().has_alpha(),
+        ..Default::default()
+    })
+}
+
+// Logs the parameters of an encoded video sticker, warning about anything Telegram would reject.
+pub async fn check_output(file: &Path, target: Target) -> Option<VideoInfo> {
+    let v = probe(file).await?;
+    info!("output: {:?}", v);
+    let size = tokio::fs::metadata(file).await.map_or(0, |m| m.len());
+    for s in violations(StickerKind::Video, target, &v, size) {
+        warn!("output is {}", s);
+    }
+    Some(v)
+}
@@ -0,0 +1,68 @@
+// Photoshop documents, flattened by default. Layers can then be picked one by one from an inline
+// keyboard, each becoming its own sticker.
+
+use crate::offers::{Kind, Offers};
+use crate::options::VideoEdit;
+use anyhow::{anyhow, Result as AnyResult};
+use image::{DynamicImage, RgbaImage};
+use psd::Psd;
+use std::sync::Arc;
+
+// Unlike videos waiting for a segment, documents stay until they expire, so that several layers
+// can be picked.
+pub static DOCUMENTS: Offers<Document> = Offers::new("psd", Kind::Document);
+// Telegram allows 100 buttons, and more would hardly be usable anyway.
+pub const MAX_LAYERS: usize = 24;
+
+pub fn sniff(data: &[u8]) -> bool {
+    data.starts_with(b"8BPS")
+}
+
+fn parse(data: &[u8]) -> AnyResult<Psd> {
+    Psd::from_bytes(data).map_err(|e| anyhow!("psd: {}", e))
+}
+
+fn to_image(psd: &Psd, rgba: Vec<u8>) -> AnyResult<DynamicImage> {
+    let img = RgbaImage::from_raw(psd.width(), psd.height(), rgba)
+        .ok_or_else(|| anyhow!("psd: bad size"))?;
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+pub fn flatten(data: &[u8]) -> AnyResult<DynamicImage> {
+    let psd = parse(data)?;
+    to_image(&psd, psd.rgba())
+}
+
+pub fn layer_names(data: &[u8]) -> AnyResult<Vec<String>> {
+    Ok(parse(data)?
+        .layers()
+        .iter()
+        .map(|l| l.name().to_owned())
+        .collect())
+}
+
+// The layer is rendered over the whole canvas, so it keeps its place when stacked with others.
+pub fn layer(data: &[u8], i: usize) -> AnyResult<DynamicImage> {
+    let psd = parse(data)?;
+    let l = psd
+        .layers()
+        .get(i)
+        .ok_or_else(|| anyhow!("psd: no layer {}", i))?;
+    to_image(&psd, l.rgba())
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub data: Arc<Vec<u8>>,
+    pub edit: VideoEdit,
+    pub base: Option<String>,
+}
+
+pub fn callback_data(token: u64, layer: usize) -> String {
+    DOCUMENTS.callback_data(token, layer)
+}
+
+pub fn parse_callback_data(data: &str) -> Option<(u64, usize)> {
+    let (token, layer) = DOCUMENTS.parse_callback_data(data)?;
+    Some((token, layer.parse().ok()?))
+}
@@ -0,0 +1,198 @@
+// Daily conversions per user, for public instances on small servers. QUOTAS gives the limit of
+// each tier separated by commas, such as "20,200,unlimited": everyone starts at the first, and
+// admins grant the others with /tier. Counts start over at midnight UTC. Tiers and counts are kept
+// in a json file keyed like the settings, so that a restart forgets neither, along with when any
+// premium bought with /premium runs out, which lifts the limit. Admins have no limit, and without
+// QUOTAS nobody has.
+
+use crate::access::{self, Actor};
+use crate::config;
+use crate::error::BotError;
+use crate::i18n::Msg;
+use crate::store::JsonStore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const QUOTA_FILE: &str = "quota.json";
+const DAY_SECS: u64 = 86400;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    tier: usize,
+    // Days since the epoch, of when `used` was counted.
+    day: u64,
+    used: u32,
+    // When premium paid for with /premium runs out, in seconds since the epoch.
+    premium_until: u64,
+}
+
+impl Usage {
+    fn used_today(&self, day: u64) -> u32 {
+        if self.day == day {
+            self.used
+        } else {
+            0
+        }
+    }
+}
+
+type Store = JsonStore<HashMap<i64, Usage>>;
+
+static STORE: OnceLock<Store> = OnceLock::new();
+
+fn store() -> &'static Store {
+    STORE.get_or_init(|| JsonStore::load(QUOTA_FILE))
+}
+
+pub fn init() {
+    store();
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn tiers() -> usize {
+    config::get().quotas.len()
+}
+
+fn is_exempt(actor: Actor) -> bool {
+    tiers() == 0 || actor.user().map_or(false, access::is_admin)
+}
+
+// Tiers granted before QUOTAS lost some get the last one left.
+fn limit(quotas: &[Option<u32>], tier: usize) -> Option<u32> {
+    quotas[tier.min(quotas.len() - 1)]
+}
+
+// Such as "3 h 12 min", from now to midnight UTC.
+pub fn until_reset() -> String {
+    until_reset_from(now())
+}
+
+fn until_reset_from(now: u64) -> String {
+    let left = DAY_SECS - now % DAY_SECS;
+    let (h, m) = (left / 3600, (left % 3600 + 59) / 60);
+    match (h, m) {
+        (h, 60) => format!("{} h", h + 1),
+        (0, m) => format!("{} min", m),
+        (h, 0) => format!("{} h", h),
+        (h, m) => format!("{} h {} min", h, m),
+    }
+}
+
+pub fn usage(actor: Actor) -> Usage {
+    store()
+        .lock()
+        .get(&actor.key())
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub fn is_premium(actor: Actor) -> bool {
+    usage(actor).premium_until > now()
+}
+
+// From when it runs out, if it has not yet. Returns the new end.
+pub fn add_premium(actor: Actor, days: u32) -> io::Result<u64> {
+    let s = store();
+    let mut users = s.lock();
+    let u = users.entry(actor.key()).or_default();
+    u.premium_until = u.premium_until.max(now()) + days as u64 * DAY_SECS;
+    let until = u.premium_until;
+    s.save(&users)?;
+    Ok(until)
+}
+
+// Before converting anything, which is only counted once it is sent.
+pub fn check(actor: Actor) -> Result<(), BotError> {
+    if is_exempt(actor) {
+        return Ok(());
+    }
+    let u = usage(actor);
+    if u.premium_until > now() {
+        return Ok(());
+    }
+    match limit(&config::get().quotas, u.tier) {
+        Some(limit) if u.used_today(now() / DAY_SECS) >= limit => {
+            Err(BotError::QuotaExceeded { limit })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn take(actor: Actor) {
+    if is_exempt(actor) {
+        return;
+    }
+    let day = now() / DAY_SECS;
+    let s = store();
+    let mut users = s.lock();
+    let u = users.entry(actor.key()).or_default();
+    u.used = u.used_today(day) + 1;
+    u.day = day;
+    drop(users);
+    s.save_later();
+}
+
+pub fn set_tier(actor: Actor, tier: usize) -> io::Result<()> {
+    let s = store();
+    let mut users = s.lock();
+    users.entry(actor.key()).or_default().tier = tier;
+    s.save(&users)
+}
+
+// For /quota.
+pub fn status(actor: Actor) -> Msg {
+    let u = usage(actor);
+    let used = u.used_today(now() / DAY_SECS);
+    if u.premium_until > now() {
+        let days = (u.premium_until - now() + DAY_SECS - 1) / DAY_SECS;
+        return Msg::new("quota-premium")
+            .arg("used", used)
+            .arg("days", days);
+    }
+    if is_exempt(actor) {
+        return Msg::new("quota-none");
+    }
+    match limit(&config::get().quotas, u.tier) {
+        Some(limit) => Msg::new("quota")
+            .arg("used", used)
+            .arg("limit", limit)
+            .arg("tier", u.tier)
+            .arg("reset", until_reset()),
+        None => Msg::new("quota-unlimited")
+            .arg("used", used)
+            .arg("tier", u.tier),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_of_tiers() {
+        let quotas = [Some(20), Some(200), None];
+        assert_eq!(limit(&quotas, 0), Some(20));
+        assert_eq!(limit(&quotas, 1), Some(200));
+        assert_eq!(limit(&quotas, 2), None);
+        // Granted before QUOTAS lost tiers.
+        assert_eq!(limit(&quotas[..2], 5), Some(200));
+    }
+
+    #[test]
+    fn until_reset_rounds_up() {
+        assert_eq!(until_reset_from(0), "24 h");
+        assert_eq!(until_reset_from(DAY_SECS - 30), "1 min");
+        assert_eq!(until_reset_from(DAY_SECS - 3599), "1 h");
+        assert_eq!(until_reset_from(DAY_SECS - 2 * 3600 - 600), "2 h 10 min");
+        assert_eq!(until_reset_from(DAY_SECS - 3 * 3600 - 10), "3 h 1 min");
+    }
+}
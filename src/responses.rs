@@ -0,0 +1,98 @@
+// The messages sent in answer to a request, collected by a ResponseTracker as the request goes,
+// and then remembered for the most recent requests, so that /delete in reply to any of them takes
+// away all of them. Requests answered in several goes, such as a video cut once a segment is
+// picked, have their answers put together. Only whoever sent the request may delete them.
+
+use crate::access::Actor;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use teloxide::types::{ChatId, Message, MessageId};
+
+const MAX_REQUESTS: usize = 2000;
+
+#[derive(Debug)]
+pub struct ResponseTracker {
+    chat: ChatId,
+    request: MessageId,
+    owner: Option<Actor>,
+    sent: Mutex<Vec<MessageId>>,
+}
+
+impl ResponseTracker {
+    pub fn new(msg: &Message) -> Self {
+        Self {
+            chat: msg.chat.id,
+            request: msg.id,
+            owner: Actor::of(msg),
+            sent: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn chat(&self) -> ChatId {
+        self.chat
+    }
+
+    pub fn record(&self, id: MessageId) {
+        self.sent.lock().unwrap().push(id);
+    }
+
+    pub fn last(&self) -> Option<MessageId> {
+        self.sent.lock().unwrap().last().copied()
+    }
+
+    pub fn sent(&self) -> Vec<MessageId> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+struct Responses {
+    bot: u64,
+    chat: ChatId,
+    request: MessageId,
+    owner: Option<Actor>,
+    sent: Vec<MessageId>,
+}
+
+static RECENT: OnceLock<Mutex<VecDeque<Responses>>> = OnceLock::new();
+
+fn recent() -> &'static Mutex<VecDeque<Responses>> {
+    RECENT.get_or_init(Default::default)
+}
+
+pub fn remember(bot: u64, t: &ResponseTracker) {
+    let sent = t.sent();
+    if sent.is_empty() {
+        return;
+    }
+    let mut recent = recent().lock().unwrap();
+    if let Some(r) = recent
+        .iter_mut()
+        .find(|r| r.bot == bot && r.chat == t.chat && r.request == t.request)
+    {
+        r.sent.extend(sent);
+        return;
+    }
+    if recent.len() >= MAX_REQUESTS {
+        recent.pop_front();
+    }
+    recent.push_back(Responses {
+        bot,
+        chat: t.chat,
+        request: t.request,
+        owner: t.owner,
+        sent,
+    });
+}
+
+// All sent along with `msg`, which are then forgotten. None if it is not known, or not the
+// actor's.
+pub fn take(bot: u64, chat: ChatId, msg: MessageId, actor: Actor) -> Option<Vec<MessageId>> {
+    let mut recent = recent().lock().unwrap();
+    let i = recent
+        .iter()
+        .position(|r| r.bot == bot && r.chat == chat && r.sent.contains(&msg))?;
+    if recent[i].owner != Some(actor) {
+        return None;
+    }
+    recent.remove(i).map(|r| r.sent)
+}
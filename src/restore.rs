@@ -0,0 +1,179 @@
+// Rebuilding a sticker set from a zip made by /archive, for /restore, and making new sets in
+// general, as for /clone. Sets are made for the sender as <name>_by_<bot>, as Telegram requires of
+// sets made by bots. Stickers are added in order and the first failure stops the run, so the set
+// always holds a prefix of the source and running it again resumes after the stickers it has.
+
+use crate::archive::{sha256_hex, Format, Manifest, MANIFEST};
+use crate::error::BotError;
+use crate::{decode_image, export, retry, AppBot};
+use anyhow::{bail, Result as AnyResult};
+use log::{info, warn};
+use std::io::{Cursor, Read};
+use teloxide::prelude::*;
+use teloxide::requests::Request as _;
+use teloxide::types::{InputFile, InputSticker, StickerType, UserId};
+use teloxide::RequestError;
+use zip::ZipArchive;
+
+// Names of sets are at most 64 characters, including the suffix.
+const MAX_NAME_LEN: usize = 64;
+const DEFAULT_EMOJI: &str = "🙂";
+
+pub struct Restored {
+    pub name: String,
+    pub added: usize,
+    pub total: usize,
+}
+
+impl Restored {
+    pub fn complete(&self) -> bool {
+        self.added == self.total
+    }
+}
+
+fn read(zip: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> AnyResult<Vec<u8>> {
+    let Ok(mut f) = zip.by_name(name) else {
+        bail!(BotError::BadArchive)
+    };
+    let mut v = Vec::new();
+    f.read_to_end(&mut v)?;
+    Ok(v)
+}
+
+// With `tag` between the name and the suffix when given, to keep apart copies of a set.
+pub fn set_name(name: &str, tag: &str, bot: &str) -> String {
+    let base = name.rsplit_once("_by_").map_or(name, |(base, _)| base);
+    let suffix = if tag.is_empty() {
+        format!("_by_{}", bot)
+    } else {
+        format!("_{}_by_{}", tag, bot)
+    };
+    let mut s: String = base
+        .chars()
+        .take(MAX_NAME_LEN.saturating_sub(suffix.len()))
+        .collect();
+    s.push_str(&suffix);
+    s
+}
+
+pub struct NewSet {
+    pub user: UserId,
+    pub name: String,
+    pub title: String,
+    pub custom_emoji: bool,
+}
+
+impl NewSet {
+    // Stickers in a set of the same name, left by an earlier run.
+    pub async fn existing(&self, bot: &AppBot) -> AnyResult<usize> {
+        match bot.get_sticker_set(&self.name).await {
+            Ok(set) => {
+                info!("{} has {} stickers already", self.name, set.stickers.len());
+                Ok(set.stickers.len())
+            }
+            Err(RequestError::Api(_)) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Creates the set with the first sticker.
+    pub async fn add(
+        &self,
+        bot: &AppBot,
+        first: bool,
+        sticker: InputSticker,
+        emoji: &str,
+    ) -> Result<(), RequestError> {
+        let emoji = if emoji.is_empty() {
+            DEFAULT_EMOJI
+        } else {
+            emoji
+        };
+        if first {
+            retry::with_backoff("create_new_sticker_set", || {
+                let mut req = bot.create_new_sticker_set(
+                    self.user,
+                    &self.name,
+                    &self.title,
+                    sticker.clone(),
+                    emoji,
+                );
+                if self.custom_emoji {
+                    req = req.sticker_type(StickerType::CustomEmoji);
+                }
+                req.send()
+            })
+            .await?;
+        } else {
+            retry::with_backoff("add_sticker_to_set", || {
+                bot.add_sticker_to_set(self.user, &self.name, sticker.clone(), emoji)
+                    .send()
+            })
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+// Uploads of static stickers have to be PNG.
+pub fn input_sticker(format: Format, data: Vec<u8>) -> AnyResult<InputSticker> {
+    Ok(match format {
+        Format::Static => {
+            let png = export::png(&decode_image(data)?)?;
+            InputSticker::Png(InputFile::memory(png).file_name("sticker.png"))
+        }
+        Format::Animated => InputSticker::Tgs(InputFile::memory(data).file_name("sticker.tgs")),
+        Format::Video => InputSticker::Webm(InputFile::memory(data).file_name("sticker.webm")),
+    })
+}
+
+pub async fn restore(bot: &AppBot, user: UserId, data: Vec<u8>) -> AnyResult<Restored> {
+    let Ok(mut zip) = ZipArchive::new(Cursor::new(data)) else {
+        bail!(BotError::BadZip)
+    };
+    let Ok(manifest) = serde_json::from_slice::<Manifest>(&read(&mut zip, MANIFEST)?) else {
+        bail!(BotError::BadArchive)
+    };
+    if manifest.stickers.is_empty() {
+        bail!(BotError::BadArchive)
+    }
+    // Everything is checked before the set is touched.
+    let mut files = Vec::with_capacity(manifest.stickers.len());
+    for e in &manifest.stickers {
+        let data = read(&mut zip, &e.file)?;
+        if sha256_hex(&data) != e.sha256 {
+            warn!("restore: {} does not match its hash", e.file);
+            bail!(BotError::BadArchive)
+        }
+        files.push(data);
+    }
+    let me = bot.get_me().await?;
+    let set = NewSet {
+        user,
+        name: set_name(&manifest.name, "", me.username()),
+        title: manifest.title,
+        custom_emoji: manifest.custom_emoji,
+    };
+    let mut r = Restored {
+        added: set.existing(bot).await?.min(files.len()),
+        total: files.len(),
+        name: set.name.clone(),
+    };
+    for (e, data) in manifest.stickers.iter().zip(files).skip(r.added) {
+        let sticker = input_sticker(e.format, data)?;
+        if let Err(err) = set.add(bot, r.added == 0, sticker, &e.emoji).await {
+            warn!("restore {}: {}: {}", r.name, e.file, err);
+            // Nothing to resume from.
+            if r.added == 0 {
+                bail!(BotError::from(err))
+            }
+            break;
+        }
+        r.added += 1;
+    }
+    info!(
+        "restored {} of {} stickers into {}",
+        r.added, r.total, r.name
+    );
+    Ok(r)
+}
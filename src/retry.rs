@@ -0,0 +1,55 @@
+use log::warn;
+use std::future::Future;
+use std::time::Duration;
+use teloxide::{ApiError, RequestError};
+
+const MAX_ATTEMPTS: u32 = 5;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+// Errors that will not go away by sending the same request again.
+pub fn is_permanent(e: &RequestError) -> bool {
+    !matches!(
+        e,
+        RequestError::RetryAfter(_) | RequestError::Network(_) | RequestError::Io(_)
+    )
+}
+
+pub fn is_too_large(e: &RequestError) -> bool {
+    matches!(e, RequestError::Api(ApiError::RequestEntityTooLarge))
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY)
+}
+
+// Runs `f` until it succeeds, fails permanently or runs out of attempts. Flood waits are honored
+// as requested by Telegram, other transient errors back off exponentially.
+pub async fn with_backoff<T, F, Fut>(what: &str, mut f: F) -> Result<T, RequestError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, RequestError>>,
+{
+    let mut attempt = 0;
+    loop {
+        let e = match f().await {
+            Ok(r) => return Ok(r),
+            Err(e) => e,
+        };
+        attempt += 1;
+        if is_permanent(&e) || attempt >= MAX_ATTEMPTS {
+            return Err(e);
+        }
+        let delay = match &e {
+            RequestError::RetryAfter(d) => *d,
+            _ => backoff(attempt - 1),
+        };
+        warn!(
+            "{}: {}, retrying in {:?} ({}/{})",
+            what, e, delay, attempt, MAX_ATTEMPTS
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
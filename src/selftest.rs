@@ -0,0 +1,194 @@
+// Runs tiny generated inputs through each converter, for /selftest and the /healthz endpoint, so
+// that a broken ffmpeg or lottie install shows up while running rather than only at startup.
+
+use crate::options::VideoEdit;
+use crate::worker::process_image;
+use crate::{ffmpeg_to_gif, process_video, temp_file, tgs_to_gif, Input};
+use anyhow::{anyhow, Result as AnyResult};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use image::codecs::gif::GifEncoder;
+use image::{Delay, DynamicImage, Frame, ImageOutputFormat, Rgba, RgbaImage};
+use log::{error, info, warn};
+use std::fmt::Write as _;
+use std::io::{Cursor, Write};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex};
+
+// Results are reused for this long, so that frequent probes do not keep ffmpeg busy.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+// A filled circle fading in over a second.
+const LOTTIE: &str = r#"{"v":"5.7.4","fr":30,"ip":0,"op":30,"w":512,"h":512,"ddd":0,"assets":[],"layers":[{"ddd":0,"ind":1,"ty":4,"nm":"dot","sr":1,"ks":{"o":{"a":1,"k":[{"t":0,"s":[0]},{"t":30,"s":[100]}]},"r":{"a":0,"k":0},"p":{"a":0,"k":[256,256,0]},"a":{"a":0,"k":[0,0,0]},"s":{"a":0,"k":[100,100,100]}},"ao":0,"shapes":[{"ty":"el","nm":"circle","p":{"a":0,"k":[0,0]},"s":{"a":0,"k":[200,200]}},{"ty":"fl","nm":"fill","c":{"a":0,"k":[1,0.5,0,1]},"o":{"a":0,"k":100}}],"ip":0,"op":30,"st":0,"bm":0}]}"#;
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<Duration, String>,
+}
+
+fn frame(i: u32) -> RgbaImage {
+    RgbaImage::from_fn(64, 48, |x, y| {
+        let a = if x < 8 { 0 } else { 255 };
+        Rgba([(x * 4) as u8, (y * 5) as u8, (i * 80) as u8, a])
+    })
+}
+
+fn png() -> AnyResult<Vec<u8>> {
+    let mut v = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(frame(0)).write_to(&mut v, ImageOutputFormat::Png)?;
+    Ok(v.into_inner())
+}
+
+fn gif() -> AnyResult<Vec<u8>> {
+    let mut v = Vec::new();
+    {
+        let mut enc = GifEncoder::new(&mut v);
+        let delay = Delay::from_numer_denom_ms(100, 1);
+        enc.encode_frames((0..3).map(|i| Frame::from_parts(frame(i), 0, 0, delay)))?;
+    }
+    Ok(v)
+}
+
+pub fn tgs() -> AnyResult<Vec<u8>> {
+    let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+    enc.write_all(LOTTIE.as_bytes())?;
+    Ok(enc.finish()?)
+}
+
+async fn check_image() -> AnyResult<()> {
+    process_image(png()?, &VideoEdit::default()).await?;
+    Ok(())
+}
+
+// Also returns the webm, which the next check converts back.
+async fn check_gif() -> AnyResult<Vec<u8>> {
+    let (path, mut f) = temp_file().await?;
+    f.write_all(&gif()?).await?;
+    drop(f);
+    let b = process_video(&path, &VideoEdit::default()).await?;
+    Ok(b.bytes().await?.to_vec())
+}
+
+async fn check_tgs() -> AnyResult<()> {
+    let (path, mut f) = temp_file().await?;
+    f.write_all(&tgs()?).await?;
+    drop(f);
+    tgs_to_gif(&path).await?;
+    Ok(())
+}
+
+fn check<T>(name: &'static str, start: Instant, r: &AnyResult<T>) -> Check {
+    let result = match r {
+        Ok(_) => Ok(start.elapsed()),
+        Err(e) => {
+            warn!("selftest: {}: {:?}", name, e);
+            Err(format!("{:#}", e))
+        }
+    };
+    Check { name, result }
+}
+
+pub async fn run() -> Vec<Check> {
+    let t = Instant::now();
+    let image = check("image", t, &check_image().await);
+    let t = Instant::now();
+    let webm = check_gif().await;
+    let gif = check("gif", t, &webm);
+    let t = Instant::now();
+    let r = match webm {
+        Ok(webm) => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tx.send(webm.into()).ok();
+            drop(tx);
+            ffmpeg_to_gif(Input::Pipe(rx)).await
+        }
+        Err(_) => Err(anyhow!("skipped, as the gif check failed")),
+    };
+    let webm = check("webm", t, &r);
+    let t = Instant::now();
+    let tgs = check("tgs", t, &check_tgs().await);
+    vec![image, gif, webm, tgs]
+}
+
+pub fn healthy(checks: &[Check]) -> bool {
+    checks.iter().all(|c| c.result.is_ok())
+}
+
+pub fn summary(checks: &[Check]) -> String {
+    let mut s = String::new();
+    for c in checks {
+        match &c.result {
+            Ok(d) => writeln!(s, "✅ {}: {} ms", c.name, d.as_millis()),
+            Err(e) => writeln!(s, "❌ {}: {}", c.name, e),
+        }
+        .unwrap();
+    }
+    s.truncate(s.trim_end().len());
+    s
+}
+
+static LAST: OnceLock<Mutex<Option<(Instant, Vec<Check>)>>> = OnceLock::new();
+
+// Held while running, so that probes arriving meanwhile wait for the same results.
+async fn cached() -> Vec<Check> {
+    let mut last = LAST.get_or_init(Default::default).lock().await;
+    if let Some((t, checks)) = &*last {
+        if t.elapsed() < CACHE_TTL {
+            return checks.clone();
+        }
+    }
+    let checks = run().await;
+    *last = Some((Instant::now(), checks.clone()));
+    checks
+}
+
+// A bare HTTP/1.1 responder: GET /healthz answers 200 when every check passes, 503 otherwise.
+pub async fn serve(addr: SocketAddr) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("healthz: {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("healthz listening on {}", addr);
+    loop {
+        let mut sock = match listener.accept().await {
+            Ok((sock, _)) => sock,
+            Err(e) => {
+                warn!("healthz: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let n = sock.read(&mut buf).await.unwrap_or(0);
+            let req = String::from_utf8_lossy(&buf[..n]);
+            let (status, body) = if req.starts_with("GET /healthz ") {
+                let checks = cached().await;
+                let status = if healthy(&checks) {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                (status, summary(&checks) + "\n")
+            } else {
+                ("404 Not Found", String::new())
+            };
+            let resp = format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            if let Err(e) = sock.write_all(resp.as_bytes()).await {
+                warn!("healthz: {}", e);
+            }
+        });
+    }
+}
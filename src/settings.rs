@@ -0,0 +1,152 @@
+// Per-user preferences set by commands, persisted as a json object keyed by user id. Channels
+// and anonymous admins get theirs under the chat id. Groups may also have a profile set by their
+// admins with /chatsettings, kept apart by chat id, which takes the place of what each member set.
+
+use crate::access::Actor;
+use crate::store::JsonStore;
+use crate::target::Target;
+use crate::upscale::Upscaler;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::sync::OnceLock;
+use teloxide::types::ChatId;
+
+const SETTINGS_FILE: &str = "settings.json";
+const PROFILES_FILE: &str = "chats.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub target: Target,
+    // Lossy webp quality for static stickers, lossless when unset.
+    pub quality: Option<u8>,
+    // Set with /lang, or else taken from the client.
+    pub lang: Option<String>,
+    // Video stickers are sent back as webm only, without the GIF.
+    pub no_gif: bool,
+    // Of images smaller than a sticker, set with /upscale.
+    pub upscale: Upscaler,
+    // Also an mp4 of video stickers, on this background.
+    pub mp4: Option<[u8; 3]>,
+    // Drawn onto every output with USER_WATERMARKS, unless the instance has its own.
+    pub watermark: Option<String>,
+    // Caption options saved under a name with /preset.
+    pub presets: BTreeMap<String, String>,
+}
+
+// What a chat's admins set for everyone in it, with None and false leaving it to each member.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub target: Option<Target>,
+    pub quality: Option<u8>,
+    pub lossless: bool,
+    pub no_gif: Option<bool>,
+    pub upscale: Option<Upscaler>,
+    // Whether explicit images are refused, which is not a setting of members.
+    pub nsfw: Option<bool>,
+}
+
+impl Profile {
+    fn apply(&self, s: &mut Settings) {
+        s.target = self.target.unwrap_or(s.target);
+        if self.lossless {
+            s.quality = None;
+        } else if self.quality.is_some() {
+            s.quality = self.quality;
+        }
+        s.no_gif = self.no_gif.unwrap_or(s.no_gif);
+        s.upscale = self.upscale.unwrap_or(s.upscale);
+    }
+}
+
+// As given to /chatsettings, such as "target=emoji lossless gif=off".
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut v = vec![];
+        if let Some(t) = self.target {
+            v.push(format!("target={:?}", t).to_lowercase());
+        }
+        if let Some(q) = self.quality {
+            v.push(format!("quality={}", q));
+        }
+        if self.lossless {
+            v.push("lossless".to_owned());
+        }
+        if let Some(no_gif) = self.no_gif {
+            v.push(format!("gif={}", if no_gif { "off" } else { "on" }));
+        }
+        if let Some(up) = self.upscale {
+            v.push(format!("upscale={:?}", up).to_lowercase());
+        }
+        if let Some(nsfw) = self.nsfw {
+            v.push(format!("nsfw={}", if nsfw { "on" } else { "off" }));
+        }
+        f.write_str(&v.join(" "))
+    }
+}
+
+type Store<T> = JsonStore<HashMap<i64, T>>;
+
+static STORE: OnceLock<Store<Settings>> = OnceLock::new();
+static PROFILES: OnceLock<Store<Profile>> = OnceLock::new();
+
+fn entry<T: Clone + Default>(s: &Store<T>, key: i64) -> T {
+    s.lock().get(&key).cloned().unwrap_or_default()
+}
+
+fn update_entry<T>(s: &Store<T>, key: i64, f: impl FnOnce(&mut T)) -> io::Result<()>
+where
+    T: Default + Serialize,
+{
+    let mut entries = s.lock();
+    f(entries.entry(key).or_default());
+    s.save(&entries)
+}
+
+fn store() -> &'static Store<Settings> {
+    STORE.get_or_init(|| JsonStore::load(SETTINGS_FILE))
+}
+
+fn profiles() -> &'static Store<Profile> {
+    PROFILES.get_or_init(|| JsonStore::load(PROFILES_FILE))
+}
+
+pub fn init() {
+    store();
+    profiles();
+}
+
+pub fn get(actor: Actor) -> Settings {
+    entry(store(), actor.key())
+}
+
+pub fn update(actor: Actor, f: impl FnOnce(&mut Settings)) -> io::Result<()> {
+    update_entry(store(), actor.key(), f)
+}
+
+pub fn forget(actor: Actor) -> io::Result<()> {
+    let s = store();
+    let mut entries = s.lock();
+    if entries.remove(&actor.key()).is_none() {
+        return Ok(());
+    }
+    s.save(&entries)
+}
+
+pub fn profile(chat: ChatId) -> Profile {
+    entry(profiles(), chat.0)
+}
+
+pub fn update_profile(chat: ChatId, f: impl FnOnce(&mut Profile)) -> io::Result<()> {
+    update_entry(profiles(), chat.0, f)
+}
+
+// Those of the actor, with the chat's profile in place of what it sets.
+pub fn merged(actor: Option<Actor>, chat: ChatId) -> Settings {
+    let mut s = actor.map(get).unwrap_or_default();
+    profile(chat).apply(&mut s);
+    s
+}
@@ -0,0 +1,57 @@
+// Square crops around the busiest part of an image, for wide screenshots and photos where
+// fitting the whole frame leaves the subject tiny. Busyness is the gradient energy of a small
+// grayscale copy, which favors detailed subjects over flat backgrounds and sky.
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+
+// Side of the copy that is analyzed.
+const ANALYSIS_SIDE: u32 = 256;
+
+// Energy of each column, or each row when `rows`, of the grayscale image.
+fn profile(img: &DynamicImage, rows: bool) -> Vec<u64> {
+    let g = img.to_luma8();
+    let (w, h) = g.dimensions();
+    let mut out = vec![0; if rows { h } else { w } as usize];
+    for y in 1..h {
+        for x in 1..w {
+            let p = g.get_pixel(x, y)[0] as i32;
+            let dx = (p - g.get_pixel(x - 1, y)[0] as i32).unsigned_abs();
+            let dy = (p - g.get_pixel(x, y - 1)[0] as i32).unsigned_abs();
+            out[if rows { y } else { x } as usize] += (dx + dy) as u64;
+        }
+    }
+    out
+}
+
+// Start of the `len` long window with the most energy.
+fn best_window(profile: &[u64], len: usize) -> usize {
+    let mut sum: u64 = profile[..len].iter().sum();
+    let (mut best, mut best_sum) = (0, sum);
+    for i in len..profile.len() {
+        sum = sum + profile[i] - profile[i - len];
+        if sum > best_sum {
+            (best, best_sum) = (i + 1 - len, sum);
+        }
+    }
+    best
+}
+
+pub fn crop(img: DynamicImage) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    if w == h {
+        return img;
+    }
+    let small = img.resize(ANALYSIS_SIDE, ANALYSIS_SIDE, FilterType::Triangle);
+    let (sw, sh) = small.dimensions();
+    let side = w.min(h);
+    if w > h {
+        let len = (sh as usize).min(sw as usize);
+        let x = best_window(&profile(&small, false), len) as u32 * w / sw;
+        img.crop_imm(x.min(w - side), 0, side, side)
+    } else {
+        let len = (sw as usize).min(sh as usize);
+        let y = best_window(&profile(&small, true), len) as u32 * h / sh;
+        img.crop_imm(0, y.min(h - side), side, side)
+    }
+}
@@ -0,0 +1,94 @@
+// Which media each of the user's uploads and our results came from, so that replying to either
+// with new options converts the same media again without uploading it again. Kept in a json file
+// with the most recent entries only. File ids only work with the bot that saw them, so entries are
+// kept apart per bot.
+
+use crate::store::JsonStore;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::OnceLock;
+use teloxide::types::{ChatId, MessageId};
+
+const SOURCES_FILE: &str = "sources.json";
+const MAX_ENTRIES: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Image,
+    Video,
+    // Documents that turned out to be animated stickers.
+    Tgs,
+    Zip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub file_id: String,
+    pub size: u32,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    // Absent from entries of before several bots could run.
+    #[serde(default)]
+    bot: u64,
+    chat: i64,
+    msg: i32,
+    source: Source,
+}
+
+type Store = JsonStore<VecDeque<Entry>>;
+
+static STORE: OnceLock<Store> = OnceLock::new();
+
+fn store() -> &'static Store {
+    STORE.get_or_init(|| JsonStore::load(SOURCES_FILE))
+}
+
+pub fn init() {
+    store();
+}
+
+pub fn insert(bot: u64, chat: ChatId, msg: MessageId, source: Source) {
+    let s = store();
+    let mut entries = s.lock();
+    if entries.len() >= MAX_ENTRIES {
+        entries.pop_front();
+    }
+    entries.push_back(Entry {
+        bot,
+        chat: chat.0,
+        msg: msg.0,
+        source,
+    });
+    drop(entries);
+    s.save_later();
+}
+
+// Of every bot, as only the chat tells whose they are.
+pub fn count(chat: ChatId) -> usize {
+    store().lock().iter().filter(|e| e.chat == chat.0).count()
+}
+
+pub fn forget(chat: ChatId) -> io::Result<()> {
+    let s = store();
+    let mut entries = s.lock();
+    let n = entries.len();
+    entries.retain(|e| e.chat != chat.0);
+    if entries.len() == n {
+        return Ok(());
+    }
+    s.save(&entries)
+}
+
+pub fn get(bot: u64, chat: ChatId, msg: MessageId) -> Option<Source> {
+    store()
+        .lock()
+        .iter()
+        .rev()
+        .find(|e| e.bot == bot && e.chat == chat.0 && e.msg == msg.0)
+        .map(|e| e.source.clone())
+}
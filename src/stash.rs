@@ -0,0 +1,61 @@
+// Downloaded files whose conversion timed out, kept for a few minutes so that /retry converts them
+// again with more time, or lossy only, without downloading them again. Each user keeps the last
+// one per chat.
+
+use crate::error::BotError;
+use crate::options::VideoEdit;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use teloxide::types::{ChatId, Message, UserId};
+use tempfile::TempPath;
+
+pub const TTL_MINUTES: u64 = 5;
+const TTL: Duration = Duration::from_secs(TTL_MINUTES * 60);
+// Of the ffmpeg timeout, when retried.
+pub const TIMEOUT_FACTOR: u32 = 3;
+
+#[derive(Debug)]
+pub struct Stashed {
+    // The message containing the media, which results reply to.
+    pub msg: Message,
+    pub path: TempPath,
+    pub base: Option<String>,
+    pub edit: VideoEdit,
+    created: Instant,
+}
+
+type Key = (u64, ChatId, Option<UserId>);
+
+static STASH: OnceLock<Mutex<HashMap<Key, Stashed>>> = OnceLock::new();
+
+fn stash() -> &'static Mutex<HashMap<Key, Stashed>> {
+    STASH.get_or_init(Default::default)
+}
+
+impl Stashed {
+    pub fn new(msg: Message, path: TempPath, base: Option<String>, edit: VideoEdit) -> Self {
+        Self {
+            msg,
+            path,
+            base,
+            edit,
+            created: Instant::now(),
+        }
+    }
+}
+
+// Expired entries, and their files, are dropped here.
+pub fn insert(bot: u64, chat: ChatId, user: Option<UserId>, s: Stashed) {
+    let mut map = stash().lock().unwrap();
+    map.retain(|_, s| s.created.elapsed() < TTL);
+    map.insert((bot, chat, user), s);
+}
+
+pub fn take(bot: u64, chat: ChatId, user: Option<UserId>) -> Result<Stashed, BotError> {
+    let mut map = stash().lock().unwrap();
+    match map.remove(&(bot, chat, user)) {
+        Some(s) if s.created.elapsed() < TTL => Ok(s),
+        _ => Err(BotError::NothingToRetry),
+    }
+}
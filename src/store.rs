@@ -0,0 +1,93 @@
+// What is kept across restarts in json files under DATA_DIR, such as settings and histories. Each
+// file is read whole at startup, and written whole whenever it changes: right away for changes
+// that are asked for, and on a blocking thread for those made along the way, such as counts.
+
+use crate::config;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tokio::runtime::Handle;
+
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: Mutex<T>,
+    // Taken before letting go of the data, so that writes land in the order they were made.
+    writing: Mutex<()>,
+    // A write by save_later is on its way, which takes what changes meanwhile along.
+    pending: AtomicBool,
+}
+
+impl<T: Default + DeserializeOwned> JsonStore<T> {
+    // Starts empty when the file is missing or unreadable.
+    pub fn load(file: &str) -> Self {
+        let path = config::get().data_dir.join(file);
+        let data = match fs::read(&path) {
+            Ok(s) => serde_json::from_slice(&s).unwrap_or_else(|e| {
+                warn!("{}: {}", path.display(), e);
+                T::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => T::default(),
+            Err(e) => {
+                warn!("{}: {}", path.display(), e);
+                T::default()
+            }
+        };
+        info!("loaded {}", file);
+        Self {
+            path,
+            data: Mutex::new(data),
+            writing: Mutex::new(()),
+            pending: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<T> JsonStore<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.data.lock().unwrap()
+    }
+}
+
+impl<T: Serialize> JsonStore<T> {
+    fn write(&self, json: &[u8]) -> io::Result<()> {
+        // Written aside first so that a crash never leaves a truncated file behind.
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    // Of the data locked by the caller.
+    pub fn save(&self, data: &T) -> io::Result<()> {
+        let json = serde_json::to_vec(data)?;
+        let _writing = self.writing.lock().unwrap();
+        self.write(&json)
+    }
+}
+
+impl<T: Serialize + Send + 'static> JsonStore<T> {
+    // For changes not worth failing over or waiting for, made once the data is unlocked.
+    pub fn save_later(&'static self) {
+        if self.pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let run = move || {
+            let data = self.lock();
+            self.pending.store(false, Ordering::Release);
+            let json = serde_json::to_vec(&*data);
+            let _writing = self.writing.lock().unwrap();
+            drop(data);
+            if let Err(e) = json.map_err(io::Error::from).and_then(|j| self.write(&j)) {
+                warn!("{}: {}", self.path.display(), e);
+            }
+        };
+        match Handle::try_current() {
+            Ok(rt) => drop(rt.spawn_blocking(run)),
+            Err(_) => run(),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// Quick stylization given by filter=bayer, sepia, vhs or glitch in the caption, done over the
+// image crate for stills and with ffmpeg filters for videos, which look alike rather than the
+// same. The noise is seeded by position, so that the same input always looks the same.
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+// Levels per channel left by ordered dithering.
+const BAYER_LEVELS: f32 = 4.0;
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+// For 512 px outputs.
+const VHS_SHIFT: f32 = 3.0;
+const GLITCH_SHIFT: f32 = 8.0;
+const GLITCH_BAND: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Style {
+    Bayer,
+    Sepia,
+    Vhs,
+    Glitch,
+}
+
+impl Style {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "bayer" | "dither" => Some(Self::Bayer),
+            "sepia" => Some(Self::Sepia),
+            "vhs" => Some(Self::Vhs),
+            "glitch" => Some(Self::Glitch),
+            _ => None,
+        }
+    }
+
+    // Followed by a comma, as with the other filters.
+    pub fn ffmpeg_filter(self) -> &'static str {
+        match self {
+            Self::Bayer => "format=rgba,split[d0][d1];[d1]palettegen=max_colors=16:reserve_transparent=1[dp];[d0][dp]paletteuse=dither=bayer:bayer_scale=2,",
+            Self::Sepia => "colorchannelmixer=.393:.769:.189:0:.349:.686:.168:0:.272:.534:.131,",
+            Self::Vhs => "format=rgba,rgbashift=rh=-3:bh=3,noise=c0s=14:c1s=8:c2s=8:allf=t,",
+            Self::Glitch => "format=rgba,rgbashift=rh=8:gv=-4:bh=-8,noise=c0s=40:c1s=20:c2s=20:allf=t+u,",
+        }
+    }
+}
+
+fn hash(x: u32, y: u32) -> u32 {
+    let mut h = x.wrapping_mul(0x9e37_79b1) ^ y.wrapping_mul(0x85eb_ca77);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0xc2b2_ae3d);
+    h ^ (h >> 13)
+}
+
+// From -1 to 1.
+fn noise(x: u32, y: u32) -> f32 {
+    (hash(x, y) & 0xffff) as f32 / 32767.5 - 1.0
+}
+
+fn bayer(img: &mut RgbaImage) {
+    let step = 255.0 / (BAYER_LEVELS - 1.0);
+    for (x, y, p) in img.enumerate_pixels_mut() {
+        let t = (BAYER[(y % 4) as usize][(x % 4) as usize] + 0.5) / 16.0 - 0.5;
+        for c in &mut p.0[..3] {
+            let v = *c as f32 + t * step;
+            *c = ((v / step).round() * step).clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+fn sepia(img: &mut RgbaImage) {
+    for p in img.pixels_mut() {
+        let [r, g, b, _] = p.0.map(|c| c as f32);
+        p[0] = (0.393 * r + 0.769 * g + 0.189 * b).min(255.0) as u8;
+        p[1] = (0.349 * r + 0.686 * g + 0.168 * b).min(255.0) as u8;
+        p[2] = (0.272 * r + 0.534 * g + 0.131 * b).min(255.0) as u8;
+    }
+}
+
+// Red and blue taken from `dx` pixels to either side, `band` rows high slices moved sideways.
+fn shift(src: &RgbaImage, dx: i64, band: Option<u32>) -> RgbaImage {
+    let (w, h) = src.dimensions();
+    let at = |x: i64, y: u32| *src.get_pixel(x.clamp(0, w as i64 - 1) as u32, y);
+    RgbaImage::from_fn(w, h, |x, y| {
+        let offset = band.map_or(0, |b| {
+            let n = hash(y / b, 7);
+            if n % 3 == 0 {
+                (noise(y / b, 11) * dx as f32 * 2.0) as i64
+            } else {
+                0
+            }
+        });
+        let x = x as i64 + offset;
+        let (r, p, b) = (at(x - dx, y), at(x, y), at(x + dx, y));
+        Rgba([r[0], p[1], b[2], p[3].max(r[3]).max(b[3])])
+    })
+}
+
+fn grain(img: &mut RgbaImage, amount: f32, scanlines: bool) {
+    for (x, y, p) in img.enumerate_pixels_mut() {
+        let n = noise(x, y) * amount;
+        let dim = if scanlines && y % 2 == 1 { 0.85 } else { 1.0 };
+        for c in &mut p.0[..3] {
+            *c = ((*c as f32 + n) * dim).clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+pub fn apply(img: DynamicImage, style: Style) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let k = rgba.width().max(rgba.height()) as f32 / 512.0;
+    match style {
+        Style::Bayer => bayer(&mut rgba),
+        Style::Sepia => sepia(&mut rgba),
+        Style::Vhs => {
+            rgba = shift(&rgba, (VHS_SHIFT * k).round().max(1.0) as i64, None);
+            grain(&mut rgba, 14.0, true);
+        }
+        Style::Glitch => {
+            let band = ((GLITCH_BAND as f32 * k) as u32).max(1);
+            rgba = shift(
+                &rgba,
+                (GLITCH_SHIFT * k).round().max(1.0) as i64,
+                Some(band),
+            );
+            grain(&mut rgba, 24.0, false);
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
@@ -0,0 +1,75 @@
+// What the output is made for, which decides its dimensions and size limits.
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Sticker,
+    // Custom emoji, which are 100x100.
+    Emoji,
+    Discord,
+    Whatsapp,
+    Signal,
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sticker" => Some(Self::Sticker),
+            "emoji" => Some(Self::Emoji),
+            "discord" => Some(Self::Discord),
+            "whatsapp" => Some(Self::Whatsapp),
+            "signal" => Some(Self::Signal),
+            _ => None,
+        }
+    }
+
+    pub fn side(self) -> u32 {
+        match self {
+            Self::Sticker | Self::Whatsapp | Self::Signal => 512,
+            Self::Emoji => 100,
+            Self::Discord => 320,
+        }
+    }
+
+    // Whether outputs must be exactly side by side, rather than fit in it.
+    pub fn square(self) -> bool {
+        self != Self::Sticker
+    }
+
+    // Outputs for other messengers are encoded by the export module.
+    pub fn is_telegram(self) -> bool {
+        matches!(self, Self::Sticker | Self::Emoji)
+    }
+
+    pub fn max_webm_size(self) -> u64 {
+        match self {
+            Self::Emoji => 64 * 1000,
+            _ => 256 * 1000,
+        }
+    }
+}
+
+// Centers in a transparent square of `side`.
+pub fn pad_filter(side: u32) -> String {
+    format!(
+        ",format=yuva420p,pad={0}:{0}:(ow-iw)/2:(oh-ih)/2:color=black@0",
+        side
+    )
+}
+
+// Fits into a square of `side`, with the scaler flags from the config.
+pub fn scale_filter(side: u32) -> String {
+    let mut s = format!(
+        "scale=w={0}:h={0}:force_original_aspect_ratio=decrease",
+        side
+    );
+    if let Some(flags) = &config::get().scale_flags {
+        s.push_str(":flags=");
+        s.push_str(flags);
+    }
+    s
+}
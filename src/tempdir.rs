@@ -0,0 +1,147 @@
+// Temporary files all go under temp_dir, in a directory of each process named after its pid, so
+// that those left behind by a crash or a kill are removed at the next start without touching
+// those of another instance still running. Workers get a directory of their own inside that of
+// the process running them, removed when they are, which counts with its files. New files are
+// refused while ours take more than max_temp_size, as long set jobs could otherwise fill a small
+// disk. Each process keeps track of its own, rather than listing the directory for every new one.
+
+use crate::config;
+use crate::error::BotError;
+use anyhow::{bail, Result as AnyResult};
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tempfile::{Builder, TempDir, TempPath};
+
+const PREFIX: &str = "sticker-bot-";
+// Given to workers, with the directory they are to use.
+pub const DIR_ENV: &str = "STICKER_BOT_TEMP_DIR";
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+// Files and worker directories made since the start, until they are seen gone.
+static LIVE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn base() -> PathBuf {
+    config::get()
+        .temp_dir
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+pub fn dir() -> &'static Path {
+    DIR.get_or_init(|| {
+        let dir = match std::env::var_os(DIR_ENV) {
+            Some(dir) => PathBuf::from(dir),
+            None => base().join(format!("{}{}", PREFIX, std::process::id())),
+        };
+        if let Err(e) = fs::create_dir_all(&dir) {
+            warn!("{}: {}", dir.display(), e);
+        }
+        dir
+    })
+}
+
+fn is_running(pid: libc::pid_t) -> bool {
+    // Signal 0 only checks that the process exists.
+    unsafe { libc::kill(pid, 0) == 0 }
+    || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Of a file, or of the files in a directory.
+fn size(path: &Path) -> io::Result<u64> {
+    let m = fs::metadata(path)?;
+    if !m.is_dir() {
+        return Ok(m.len());
+    }
+    Ok(fs::read_dir(path)?
+        .flatten()
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum())
+}
+
+// Only called by the main process before anything runs. The directories of processes gone are
+// removed, while a pid taken again by another process leaves its directory until the next start.
+pub fn init() {
+    dir();
+    let base = base();
+    let entries = match fs::read_dir(&base) {
+        Ok(it) => it,
+        Err(e) => {
+            warn!("{}: {}", base.display(), e);
+            return;
+        }
+    };
+    let (mut n, mut bytes) = (0, 0);
+    for e in entries.flatten() {
+        let name = e.file_name();
+        let Some(pid) = name.to_str().and_then(|s| s.strip_prefix(PREFIX)) else {
+            continue;
+        };
+        match pid.parse() {
+            Ok(pid) if pid > 0 && !is_running(pid) => {}
+            _ => continue,
+        }
+        let path = e.path();
+        let len = size(&path).unwrap_or(0);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {
+                n += 1;
+                bytes += len;
+            }
+            Err(e) => warn!("{}: {}", path.display(), e),
+        }
+    }
+    if n > 0 {
+        info!(
+            "removed {} orphaned temporary directories of {} B",
+            n, bytes
+        );
+    }
+}
+
+// Of those still there, which are dropped from LIVE once removed.
+fn used(live: &mut Vec<PathBuf>) -> u64 {
+    let mut used = 0;
+    live.retain(|p| match size(p) {
+        Ok(n) => {
+            used += n;
+            true
+        }
+        Err(_) => false,
+    });
+    used
+}
+
+pub fn path() -> AnyResult<TempPath> {
+    let max = config::get().max_temp_size;
+    let mut live = LIVE.lock().unwrap();
+    if max > 0 {
+        let used = used(&mut live);
+        if used >= max {
+            warn!("temporary files take {} B, refusing more", used);
+            bail!(BotError::TempFull)
+        }
+    }
+    let path = Builder::new()
+        .prefix(PREFIX)
+        .tempfile_in(dir())?
+        .into_temp_path();
+    live.push(path.to_path_buf());
+    Ok(path)
+}
+
+// For a file a tool makes next to one of ours, such as pdftoppm appending the extension, which the
+// caller removes.
+pub fn track(path: &Path) {
+    LIVE.lock().unwrap().push(path.to_owned());
+}
+
+// For a worker, removed with whatever it left behind when dropped.
+pub fn worker_dir() -> io::Result<TempDir> {
+    let d = Builder::new().prefix("worker-").tempdir_in(dir())?;
+    track(d.path());
+    Ok(d)
+}
@@ -0,0 +1,198 @@
+// Meme-style text drawn over stickers, given by "text: ..." at the end of a caption.
+
+use crate::config;
+use crate::error::BotError;
+use ab_glyph::{point, Font, FontArc, Glyph, PxScale, ScaleFont};
+use anyhow::{anyhow, bail, Result as AnyResult};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+const MARGIN: f32 = 16.0;
+const MIN_SIZE: f32 = 16.0;
+const FILL: [u8; 3] = [255, 255, 255];
+const OUTLINE: [u8; 3] = [0, 0, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Position {
+    Top,
+    Center,
+    #[default]
+    Bottom,
+}
+
+impl Position {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "top" => Some(Self::Top),
+            "center" | "middle" => Some(Self::Center),
+            "bottom" => Some(Self::Bottom),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Overlay {
+    pub text: String,
+    pub position: Position,
+}
+
+static FONT: OnceLock<Result<FontArc, String>> = OnceLock::new();
+
+pub fn is_available() -> bool {
+    config::get().font_path.is_some()
+}
+
+// Failing to load the font is left to the admins, as users can do nothing about it.
+fn font() -> AnyResult<&'static FontArc> {
+    let Some(path) = &config::get().font_path else {
+        bail!(BotError::TextUnavailable)
+    };
+    FONT.get_or_init(|| {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        FontArc::try_from_vec(data).map_err(|e| format!("{}: {}", path.display(), e))
+    })
+    .as_ref()
+    .map_err(|e| anyhow!("{}", e))
+}
+
+// Lays out a single line at the origin, returning the glyphs and the total advance.
+fn layout(font: &FontArc, text: &str, size: f32) -> (Vec<Glyph>, f32) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut glyphs = Vec::new();
+    let mut x = 0.0;
+    let mut last = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = last {
+            x += scaled.kern(prev, id);
+        }
+        glyphs.push(id.with_scale_and_position(size, point(x, scaled.ascent())));
+        x += scaled.h_advance(id);
+        last = Some(id);
+    }
+    (glyphs, x)
+}
+
+fn blend(dst: &mut Rgba<u8>, rgb: [u8; 3], cov: f32) {
+    let cov = cov.clamp(0.0, 1.0);
+    let a = dst[3] as f32 / 255.0;
+    let out_a = cov + a * (1.0 - cov);
+    if out_a <= 0.0 {
+        return;
+    }
+    for (d, &s) in dst.0.iter_mut().zip(&rgb) {
+        *d = ((s as f32 * cov + *d as f32 * a * (1.0 - cov)) / out_a).round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+fn draw_glyphs(
+    img: &mut RgbaImage,
+    font: &FontArc,
+    glyphs: &[Glyph],
+    x0: f32,
+    y0: f32,
+    rgb: [u8; 3],
+) {
+    let (w, h) = img.dimensions();
+    for g in glyphs {
+        let mut g = g.clone();
+        g.position.x += x0;
+        g.position.y += y0;
+        let Some(o) = font.outline_glyph(g) else {
+            continue;
+        };
+        let b = o.px_bounds();
+        o.draw(|x, y, cov| {
+            let (x, y) = (b.min.x as i32 + x as i32, b.min.y as i32 + y as i32);
+            if x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h {
+                blend(img.get_pixel_mut(x as u32, y as u32), rgb, cov);
+            }
+        });
+    }
+}
+
+pub fn draw(img: &mut RgbaImage, o: &Overlay) -> AnyResult<()> {
+    let font = font()?;
+    let cfg = config::get();
+    let (w, h) = (img.width() as f32, img.height() as f32);
+    // Sizes are given for 512 px stickers.
+    let k = (w.max(h) / 512.0).min(1.0);
+    let margin = MARGIN * k;
+    let max_width = w - 2.0 * margin;
+
+    let mut size = cfg.text_size * k;
+    let (mut glyphs, mut width) = layout(font, &o.text, size);
+    if width > max_width && size > MIN_SIZE * k {
+        size = (size * max_width / width).max(MIN_SIZE * k);
+        (glyphs, width) = layout(font, &o.text, size);
+    }
+    let height = font.as_scaled(PxScale::from(size)).height();
+
+    let x0 = (w - width) / 2.0;
+    let y0 = match o.position {
+        Position::Top => margin,
+        Position::Center => (h - height) / 2.0,
+        Position::Bottom => h - height - margin,
+    };
+    let r = ((cfg.text_outline as f32 * k).round() as i32).max(1);
+    for dx in -r..=r {
+        for dy in -r..=r {
+            if (dx, dy) != (0, 0) && dx * dx + dy * dy <= r * r {
+                draw_glyphs(img, font, &glyphs, x0 + dx as f32, y0 + dy as f32, OUTLINE);
+            }
+        }
+    }
+    draw_glyphs(img, font, &glyphs, x0, y0, FILL);
+    Ok(())
+}
+
+// A single outlined line on a transparent canvas of its own size, for watermarks.
+pub fn render_line(text: &str, size: f32) -> AnyResult<RgbaImage> {
+    let font = font()?;
+    let (glyphs, width) = layout(font, text, size);
+    let height = font.as_scaled(PxScale::from(size)).height();
+    let r = ((config::get().text_outline as f32 * size / 64.0).round() as i32).max(1);
+    let mut img = RgbaImage::new(
+        (width.ceil() as i32 + 2 * r).max(1) as u32,
+        (height.ceil() as i32 + 2 * r).max(1) as u32,
+    );
+    for dx in -r..=r {
+        for dy in -r..=r {
+            if (dx, dy) != (0, 0) && dx * dx + dy * dy <= r * r {
+                let (x, y) = ((r + dx) as f32, (r + dy) as f32);
+                draw_glyphs(&mut img, font, &glyphs, x, y, OUTLINE);
+            }
+        }
+    }
+    draw_glyphs(&mut img, font, &glyphs, r as f32, r as f32, FILL);
+    Ok(img)
+}
+
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// The text itself is read from `text_file` so that it needs no escaping.
+pub fn drawtext_filter(o: &Overlay, text_file: &str, side: u32) -> AnyResult<String> {
+    font()?;
+    let cfg = config::get();
+    let k = (side as f32 / 512.0).min(1.0);
+    let margin = MARGIN * k;
+    let font_path = cfg.font_path.as_ref().unwrap().to_string_lossy();
+    let y = match o.position {
+        Position::Top => format!("{}", margin),
+        Position::Center => "(h-text_h)/2".to_owned(),
+        Position::Bottom => format!("h-text_h-{}", margin),
+    };
+    Ok(format!(
+        ",drawtext=fontfile={}:textfile={}:fontsize={}:fontcolor=white:borderw={}:bordercolor=black:x=(w-text_w)/2:y={}:fix_bounds=1",
+        quote(&font_path),
+        quote(text_file),
+        cfg.text_size * k,
+        ((cfg.text_outline as f32 * k).round() as u32).max(1),
+        y
+    ))
+}
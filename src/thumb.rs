@@ -0,0 +1,58 @@
+// Sticker set thumbnails: exactly 100x100, and at most 32 KB for video ones.
+
+use crate::error::BotError;
+use crate::ffjob::FfmpegJob;
+use crate::options::VideoEdit;
+use crate::probe::VideoInfo;
+use crate::target;
+use crate::{config, encode_static, pad_square, Blob, Input, MAX_DURATION};
+use anyhow::{bail, Result as AnyResult};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use log::info;
+use std::path::Path;
+
+const SIDE: u32 = 100;
+const MAX_WEBM_SIZE: u64 = 32 * 1000;
+const ATTEMPTS: u32 = 4;
+
+pub fn image(img: DynamicImage) -> AnyResult<Blob> {
+    let img = img.resize(SIDE, SIDE, FilterType::Lanczos3);
+    encode_static(&pad_square(&img, SIDE), None)
+}
+
+async fn encode(file: &Path, edit: &VideoEdit, bitrate: u64) -> AnyResult<Blob> {
+    FfmpegJob::new(Input::File(file), "webm")
+        .input_args(edit.input_args())
+        .filter(&edit.filters())
+        .filter(&edit.scale_filter(SIDE))
+        .filter(&target::pad_filter(SIDE))
+        .duration(MAX_DURATION)
+        .args(["-c:v", "libvpx-vp9"])
+        .args(&config::get().vp9_args)
+        .args(["-b:v".to_owned(), bitrate.to_string()])
+        .args(["-f", "webm", "-an"])
+        .probe()
+        .run()
+        .await
+}
+
+// The bit rate starts from what would exactly fill the budget and backs off while the output is
+// still too big, since libvpx tends to overshoot on short clips.
+pub async fn video(file: &Path, info: &VideoInfo, edit: &VideoEdit) -> AnyResult<Blob> {
+    let duration = info
+        .duration
+        .unwrap_or(MAX_DURATION)
+        .min(MAX_DURATION)
+        .max(0.1);
+    let mut bitrate = (MAX_WEBM_SIZE as f64 * 8.0 * 0.85 / duration) as u64;
+    for _ in 0..ATTEMPTS {
+        let b = encode(file, edit, bitrate).await?;
+        if b.len() <= MAX_WEBM_SIZE {
+            return Ok(b);
+        }
+        info!("thumb of {} B at {} bps too big", b.len(), bitrate);
+        bitrate = bitrate * 7 / 10;
+    }
+    bail!(BotError::ThumbTooBig)
+}
@@ -0,0 +1,82 @@
+// Everything kept about a user, sent to them as json by /export and deleted by /forgetme: their
+// settings, history and favorites, the sources of their uploads to the private chat, and the jobs
+// they started. What is kept of groups, such as their profiles, is the admins' and stays, and so
+// does the quota, as forgetting it would give a new one.
+
+use crate::access::Actor;
+use crate::history::{self, Entry, List};
+use crate::settings::{self, Settings};
+use crate::{jobs, quota, sources};
+use anyhow::Result as AnyResult;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use teloxide::types::ChatId;
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    // By what they were made from, of those still in the history.
+    results: BTreeMap<String, usize>,
+    favorites: usize,
+    sources: usize,
+    jobs: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Export {
+    id: i64,
+    settings: Settings,
+    history: Vec<Entry>,
+    favorites: Vec<Entry>,
+    jobs: Vec<jobs::Record>,
+    quota: quota::Usage,
+    stats: Stats,
+}
+
+// A private chat has the id of the user, and channels have none.
+fn private_chat(actor: Actor) -> Option<ChatId> {
+    match actor {
+        Actor::User(id) => Some(ChatId(id.0 as i64)),
+        Actor::Chat(_) => None,
+    }
+}
+
+pub fn export(actor: Actor) -> AnyResult<Vec<u8>> {
+    let history = history::entries(List::History, actor);
+    let favorites = history::entries(List::Favorites, actor);
+    let jobs = match actor {
+        Actor::User(id) => jobs::of_user(id)?,
+        Actor::Chat(_) => vec![],
+    };
+    let mut results = BTreeMap::new();
+    for e in &history {
+        *results.entry(e.input.clone()).or_default() += 1;
+    }
+    let stats = Stats {
+        results,
+        favorites: favorites.len(),
+        sources: private_chat(actor).map_or(0, sources::count),
+        jobs: jobs.len(),
+    };
+    let export = Export {
+        id: actor.key(),
+        settings: settings::get(actor),
+        history,
+        favorites,
+        jobs,
+        quota: quota::usage(actor),
+        stats,
+    };
+    Ok(serde_json::to_vec_pretty(&export)?)
+}
+
+pub fn forget(actor: Actor) -> AnyResult<()> {
+    settings::forget(actor)?;
+    history::forget(actor)?;
+    if let Some(chat) = private_chat(actor) {
+        sources::forget(chat)?;
+    }
+    if let Actor::User(id) = actor {
+        jobs::forget(id)?;
+    }
+    Ok(())
+}
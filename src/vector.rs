@@ -0,0 +1,94 @@
+// Vector inputs, rendered straight at the output size instead of being decoded as rasters.
+
+use crate::error::BotError;
+use anyhow::{anyhow, Result as AnyResult};
+use image::{DynamicImage, RgbaImage};
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{self, TreeParsing};
+
+#[cfg(feature = "pdf")]
+pub const PDFTOPPM: &str = "pdftoppm";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Svg,
+    Pdf,
+}
+
+pub fn sniff(data: &[u8]) -> Option<Kind> {
+    if data.starts_with(b"%PDF-") {
+        return Some(Kind::Pdf);
+    }
+    // Only the head is looked at, as svg files may start with comments or a doctype.
+    let head = String::from_utf8_lossy(&data[..data.len().min(1024)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    if head.starts_with('<') && head.contains("<svg") {
+        return Some(Kind::Svg);
+    }
+    None
+}
+
+// Fits the drawing into `side`, keeping the transparency.
+pub fn render_svg(data: &[u8], side: u32) -> AnyResult<DynamicImage> {
+    let tree =
+        usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|_| BotError::NotAnImage)?;
+    let tree = resvg::Tree::from_usvg(&tree);
+    let (w, h) = (tree.size.width(), tree.size.height());
+    let k = side as f32 / w.max(h);
+    let (pw, ph) = (
+        ((w * k).round() as u32).max(1),
+        ((h * k).round() as u32).max(1),
+    );
+    let mut pixmap = Pixmap::new(pw, ph).ok_or_else(|| anyhow!("svg: bad size {}x{}", pw, ph))?;
+    tree.render(Transform::from_scale(k, k), &mut pixmap.as_mut());
+    let mut img = RgbaImage::new(pw, ph);
+    for (dst, src) in img.pixels_mut().zip(pixmap.pixels()) {
+        let c = src.demultiply();
+        dst.0 = [c.red(), c.green(), c.blue(), c.alpha()];
+    }
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+// Renders the first page through poppler, which gives no transparency.
+#[cfg(feature = "pdf")]
+pub async fn render_pdf(data: Vec<u8>, side: u32) -> AnyResult<DynamicImage> {
+    use crate::{config, temp_file, temp_path, tempdir, wait_output};
+    use anyhow::bail;
+    use log::error;
+    use tokio::io::AsyncWriteExt;
+    use tokio::process::Command;
+
+    if !crate::caps::get().pdftoppm {
+        bail!(BotError::PdfUnavailable)
+    }
+
+    let (path, mut f) = temp_file().await?;
+    f.write_all(&data).await?;
+    drop(f);
+    // pdftoppm appends the extension itself.
+    let out_path = temp_path()?;
+    let png = out_path.with_extension("png");
+    tempdir::track(&png);
+    let out = wait_output(
+        Command::new(PDFTOPPM)
+            .args(["-png", "-singlefile", "-f", "1", "-l", "1", "-scale-to"])
+            .arg(side.to_string())
+            .arg(&path)
+            .arg(&out_path),
+        config::get().pdf_timeout,
+    )
+    .await?;
+    if !out.status.success() {
+        error!("pdftoppm failed: {:?}", out.status);
+        let _ = tokio::fs::remove_file(&png).await;
+        bail!(BotError::PdfFailed)
+    }
+    let r = tokio::fs::read(&png).await;
+    let _ = tokio::fs::remove_file(&png).await;
+    Ok(image::load_from_memory(&r?)?)
+}
+
+#[cfg(not(feature = "pdf"))]
+pub async fn render_pdf(_data: Vec<u8>, _side: u32) -> AnyResult<DynamicImage> {
+    anyhow::bail!(BotError::PdfUnavailable)
+}
@@ -0,0 +1,136 @@
+// A mark composited onto every output at a corner, either a line of text or an image, at the
+// given opacity. The one of the instance, from WATERMARK_TEXT or WATERMARK_IMAGE, applies to
+// everyone. Otherwise, with USER_WATERMARKS, users can set a text of their own with /watermark.
+// Texts need FONT_PATH, without which they are not taken.
+
+use crate::text;
+use crate::{config, temp_file};
+use anyhow::Result as AnyResult;
+use image::imageops::{self, FilterType};
+use image::{ImageOutputFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::PathBuf;
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+
+// For 512 px outputs.
+const MARGIN: f32 = 8.0;
+const TEXT_SIZE: f32 = 28.0;
+// Of the longer side of the output.
+const MAX_IMAGE_FRACTION: f32 = 0.25;
+pub const MAX_TEXT_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "top-left" => Some(Self::TopLeft),
+            "top-right" => Some(Self::TopRight),
+            "bottom-left" => Some(Self::BottomLeft),
+            "bottom-right" => Some(Self::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Mark {
+    Text(String),
+    Image(PathBuf),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Watermark {
+    pub mark: Mark,
+    pub corner: Corner,
+    // From 0 to 1.
+    pub opacity: f32,
+}
+
+// The one of the instance, or else the user's own when allowed.
+pub fn get(user_text: Option<&str>) -> Option<Watermark> {
+    let cfg = config::get();
+    let mark = match (&cfg.watermark_image, &cfg.watermark_text) {
+        (Some(path), _) => Mark::Image(path.clone()),
+        (None, Some(s)) => Mark::Text(s.clone()),
+        (None, None) if cfg.user_watermarks => Mark::Text(user_text?.to_owned()),
+        (None, None) => return None,
+    };
+    Some(Watermark {
+        mark,
+        corner: cfg.watermark_corner,
+        opacity: cfg.watermark_opacity,
+    })
+}
+
+// The mark sized for an output whose longer side is `side`.
+fn render(w: &Watermark, side: u32) -> AnyResult<RgbaImage> {
+    let k = side as f32 / 512.0;
+    let mut img = match &w.mark {
+        Mark::Text(s) => text::render_line(s, TEXT_SIZE * k)?,
+        Mark::Image(path) => {
+            let max = (side as f32 * MAX_IMAGE_FRACTION).max(1.0) as u32;
+            image::open(path)?
+                .resize(max, max, FilterType::Lanczos3)
+                .to_rgba8()
+        }
+    };
+    for p in img.pixels_mut() {
+        p[3] = (p[3] as f32 * w.opacity.clamp(0.0, 1.0)).round() as u8;
+    }
+    Ok(img)
+}
+
+fn margin(side: u32) -> i64 {
+    (MARGIN * side as f32 / 512.0).round() as i64
+}
+
+pub fn apply(img: &mut RgbaImage, w: &Watermark) -> AnyResult<()> {
+    let (iw, ih) = img.dimensions();
+    let mark = render(w, iw.max(ih))?;
+    let m = margin(iw.max(ih));
+    let (right, bottom) = (
+        iw as i64 - mark.width() as i64 - m,
+        ih as i64 - mark.height() as i64 - m,
+    );
+    let (x, y) = match w.corner {
+        Corner::TopLeft => (m, m),
+        Corner::TopRight => (right, m),
+        Corner::BottomLeft => (m, bottom),
+        Corner::BottomRight => (right, bottom),
+    };
+    imageops::overlay(img, &mark, x, y);
+    Ok(())
+}
+
+// Overlays the mark read from a PNG of its own, which has to be kept until ffmpeg is done.
+pub async fn filter(w: &Watermark, side: u32) -> AnyResult<(String, TempPath)> {
+    let mut png = Cursor::new(Vec::new());
+    render(w, side)?.write_to(&mut png, ImageOutputFormat::Png)?;
+    let (path, mut f) = temp_file().await?;
+    f.write_all(png.get_ref()).await?;
+    drop(f);
+    let m = margin(side);
+    let (x, y) = match w.corner {
+        Corner::TopLeft => (format!("{}", m), format!("{}", m)),
+        Corner::TopRight => (format!("W-w-{}", m), format!("{}", m)),
+        Corner::BottomLeft => (format!("{}", m), format!("H-h-{}", m)),
+        Corner::BottomRight => (format!("W-w-{}", m), format!("H-h-{}", m)),
+    };
+    let s = format!(
+        ",format=rgba[wmv];movie={}[wmi];[wmv][wmi]overlay={}:{}",
+        text::quote(&path.to_string_lossy()),
+        x,
+        y
+    );
+    Ok((s, path))
+}
@@ -0,0 +1,289 @@
+// Image conversions in separate processes, so that a decoder or libwebp crashing, or running out
+// of memory, loses one job rather than the dispatcher. Workers are this binary started with
+// --worker, reading jobs from stdin and answering on stdout, each as a json header followed by
+// the data. Idle workers are kept for the next job until they have done WORKER_JOBS, or until the
+// config is reloaded. Videos already go through ffmpeg subprocesses.
+
+use crate::error::BotError;
+use crate::i18n::Msg;
+use crate::options::VideoEdit;
+use crate::probe::VideoInfo;
+use crate::{config, tempdir, Blob, MAX_MEMORY_INPUT};
+use anyhow::{bail, Result as AnyResult};
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tempfile::TempDir;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Semaphore;
+
+pub const ARG: &str = "--worker";
+
+// Results are at most a few sticker sizes, but inputs may be up to MAX_MEMORY_INPUT.
+const MAX_FRAME_SIZE: u64 = MAX_MEMORY_INPUT as u64 * 2;
+
+#[derive(Serialize, Deserialize)]
+struct Job {
+    edit: VideoEdit,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Done {
+    Ok {
+        ext: String,
+        info: Option<VideoInfo>,
+        note: Option<Msg>,
+        hash: Option<u64>,
+    },
+    Err {
+        msg: Msg,
+        internal: bool,
+        detail: String,
+    },
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    header: &impl Serialize,
+    data: &[u8],
+) -> io::Result<()> {
+    let h = serde_json::to_vec(header)?;
+    w.write_u32_le(h.len() as u32).await?;
+    w.write_all(&h).await?;
+    w.write_u64_le(data.len() as u64).await?;
+    w.write_all(data).await?;
+    w.flush().await
+}
+
+async fn read_frame<R: AsyncRead + Unpin, T: DeserializeOwned>(
+    r: &mut R,
+) -> io::Result<(T, Vec<u8>)> {
+    let n = r.read_u32_le().await? as u64;
+    if n > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "header too big"));
+    }
+    let mut h = vec![0; n as usize];
+    r.read_exact(&mut h).await?;
+    let n = r.read_u64_le().await?;
+    if n > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "data too big"));
+    }
+    let mut data = vec![0; n as usize];
+    r.read_exact(&mut data).await?;
+    Ok((serde_json::from_slice(&h)?, data))
+}
+
+struct Worker {
+    // Killed when dropped, which is how a worker in a bad state is discarded.
+    child: Child,
+    // Where it puts its temporary files.
+    _dir: TempDir,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    jobs: u32,
+    generation: u32,
+}
+
+impl Worker {
+    fn spawn() -> io::Result<Self> {
+        let dir = tempdir::worker_dir()?;
+        let mut child = Command::new(env::current_exe()?)
+            .arg(ARG)
+            .env(tempdir::DIR_ENV, dir.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        info!("worker {:?} started", child.id());
+        Ok(Self {
+            stdin: child.stdin.take().unwrap(),
+            stdout: child.stdout.take().unwrap(),
+            child,
+            _dir: dir,
+            jobs: 0,
+            generation: GENERATION.load(Ordering::Relaxed),
+        })
+    }
+
+    async fn run(&mut self, edit: &VideoEdit, data: &[u8]) -> io::Result<(Done, Vec<u8>)> {
+        let job = Job { edit: edit.clone() };
+        write_frame(&mut self.stdin, &job, data).await?;
+        read_frame(&mut self.stdout).await
+    }
+}
+
+static IDLE: Mutex<Vec<Worker>> = Mutex::new(Vec::new());
+static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+// Bumped by a reload of the config, which workers only read when they start.
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+// Those busy are let go once done.
+pub fn retire() {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    IDLE.lock().unwrap().clear();
+}
+
+// Blob extensions are static, and the image pipeline only makes these.
+fn static_ext(s: &str) -> &'static str {
+    match s {
+        "png" => "png",
+        "gif" => "gif",
+        _ => "webp",
+    }
+}
+
+// Runs process_image in a worker, or in-process when WORKERS is 0.
+pub async fn process_image(file: Vec<u8>, edit: &VideoEdit) -> AnyResult<Blob> {
+    let cfg = config::get();
+    if cfg.workers == 0 {
+        return crate::process_image(file, edit).await;
+    }
+    let _slot = SLOTS
+        .get_or_init(|| Semaphore::new(cfg.workers))
+        .acquire()
+        .await
+        .unwrap();
+    let idle = IDLE.lock().unwrap().pop();
+    let mut w = match idle {
+        Some(w) => w,
+        None => Worker::spawn()?,
+    };
+    let timeout = cfg.worker_timeout;
+    let (done, data) = match tokio::time::timeout(timeout, w.run(edit, &file)).await {
+        Ok(Ok(r)) => r,
+        Ok(Err(e)) => {
+            error!("worker: {}", e);
+            bail!(BotError::WorkerCrashed)
+        }
+        Err(_) => {
+            warn!("worker {:?} timed out", w.child.id());
+            if let Err(e) = w.child.start_kill() {
+                warn!("worker: {}", e);
+            }
+            bail!(BotError::Timeout {
+                secs: timeout.as_secs()
+            })
+        }
+    };
+    w.jobs += 1;
+    if w.jobs < cfg.worker_jobs && w.generation == GENERATION.load(Ordering::Relaxed) {
+        IDLE.lock().unwrap().push(w);
+    }
+    match done {
+        Done::Ok {
+            ext,
+            info,
+            note,
+            hash,
+        } => Ok(Blob::new(data, static_ext(&ext))
+            .with_info(info)
+            .with_note(note)
+            .with_hash(hash)),
+        Done::Err {
+            msg,
+            internal,
+            detail,
+        } => bail!(BotError::Worker {
+            msg,
+            internal,
+            detail
+        }),
+    }
+}
+
+async fn convert(data: Vec<u8>, edit: &VideoEdit) -> (Done, Vec<u8>) {
+    let r = match crate::process_image(data, edit).await {
+        Ok(b) => b.bytes().await.map_err(anyhow::Error::from).map(|d| (b, d)),
+        Err(e) => Err(e),
+    };
+    match r {
+        Ok((b, d)) => (
+            Done::Ok {
+                ext: b.ext.to_owned(),
+                info: b.info,
+                note: b.note,
+                hash: b.hash,
+            },
+            d.to_vec(),
+        ),
+        Err(e) => {
+            let b = e.downcast_ref::<BotError>();
+            let done = Done::Err {
+                msg: b.map_or_else(|| Msg::new("error-generic"), |b| b.msg()),
+                internal: b.map_or(true, |b| b.is_internal()),
+                detail: format!("{:#}", e),
+            };
+            (done, vec![])
+        }
+    }
+}
+
+// The loop of a worker process, until the bot closes its stdin.
+pub async fn serve() {
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+    loop {
+        let (job, data): (Job, _) = match read_frame(&mut stdin).await {
+            Ok(r) => r,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return,
+            Err(e) => {
+                warn!("worker: {}", e);
+                return;
+            }
+        };
+        let (done, out) = convert(data, &job.edit).await;
+        if let Err(e) = write_frame(&mut stdout, &done, &out).await {
+            warn!("worker: {}", e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frame_round_trip() {
+        let job = Job {
+            edit: VideoEdit {
+                grid: Some((2, 3)),
+                ..Default::default()
+            },
+        };
+        let mut buf = vec![];
+        write_frame(&mut buf, &job, b"image").await.unwrap();
+        let done = Done::Ok {
+            ext: "png".to_owned(),
+            info: None,
+            note: None,
+            hash: Some(42),
+        };
+        write_frame(&mut buf, &done, &[]).await.unwrap();
+        let mut r = &buf[..];
+        let (job, data): (Job, _) = read_frame(&mut r).await.unwrap();
+        assert_eq!(job.edit.grid, Some((2, 3)));
+        assert_eq!(data, b"image");
+        let (done, data): (Done, _) = read_frame(&mut r).await.unwrap();
+        assert!(matches!(done, Done::Ok { ext, hash: Some(42), .. } if ext == "png"));
+        assert!(data.is_empty());
+        let e = read_frame::<_, Job>(&mut r).await.err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_refused() {
+        let mut buf = vec![];
+        buf.extend_from_slice(&2u32.to_le_bytes());
+        buf.extend_from_slice(b"{}");
+        buf.extend_from_slice(&(MAX_FRAME_SIZE + 1).to_le_bytes());
+        let e = read_frame::<_, Job>(&mut &buf[..]).await.err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}